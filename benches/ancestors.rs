@@ -0,0 +1,26 @@
+//! Walking the whole ancestor chain should take time proportional to the
+//! length of the path. Compare the two benchmarks: ten times the components
+//! should take about ten times as long, not a hundred.
+
+#![feature(test)]
+extern crate test;
+
+use psilo_vfs::PathBuf;
+use test::{Bencher, black_box};
+
+fn deep_path(depth: usize) -> PathBuf {
+    let mut ret = String::from("/");
+    for n in 0 .. depth { ret.push_str(&format!("component{}/", n)); }
+    ret.push_str("leaf");
+    PathBuf::from_str(&ret)
+}
+
+#[bench] fn ancestors_50(b: &mut Bencher) {
+    let path = deep_path(50);
+    b.iter(|| black_box(&path).ancestors().count());
+}
+
+#[bench] fn ancestors_500(b: &mut Bencher) {
+    let path = deep_path(500);
+    b.iter(|| black_box(&path).ancestors().count());
+}
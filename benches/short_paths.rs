@@ -0,0 +1,64 @@
+//! Most asset paths are short. Building a lot of them should allocate a lot
+//! less with the `compact_str` feature, which keeps short paths inline, than
+//! without it. Compare:
+//!
+//! ```text
+//! cargo bench --bench short_paths -- --nocapture
+//! cargo bench --bench short_paths --features compact_str -- --nocapture
+//! ```
+//!
+//! Each run prints the number of allocations per iteration, as well as the
+//! time.
+
+#![feature(test)]
+extern crate test;
+
+use psilo_vfs::{Path, PathBuf, p};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use test::{Bencher, black_box};
+
+/// Counts every allocation, then lets the system allocator do the work.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const NAMES: &[&str] = &["grass.png", "dirt.png", "door.wav", "e1m1.map",
+                         "title.ogg", "font.ttf", "menu.txt", "hud.png"];
+
+/// Builds one short path per name, the way a game would while loading.
+fn build_paths() -> Vec<PathBuf> {
+    let base = p!("/gfx/");
+    NAMES.iter().map(|name| {
+        let mut path = base.to_owned();
+        path.join(&Path::from_str(name));
+        path
+    }).collect()
+}
+
+#[bench] fn short_paths(b: &mut Bencher) {
+    // The first time through sets up the path validation machinery, which
+    // allocates on its own account
+    black_box(build_paths());
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(build_paths());
+    let per_iteration = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    eprintln!("short_paths: {} allocations per iteration ({} paths)",
+              per_iteration, NAMES.len());
+    b.iter(|| black_box(build_paths()));
+}
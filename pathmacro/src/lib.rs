@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     error::Error,
     fmt::{Display, Formatter},
 };
@@ -6,20 +7,22 @@ use proc_macro::TokenStream;
 use syn::{parse_macro_input, LitStr};
 use once_cell::sync::Lazy;
 use regex::Regex;
-use unicode_normalization::char::decompose_canonical;
+use unicode_normalization::{IsNormalized, UnicodeNormalization, is_nfd_quick};
 use quote::quote;
 
-fn normalized(s: &str) -> String {
-    // `s.len()` will usually be exactly enough
-    let mut ret = String::with_capacity(s.len());
-    for c in s.chars() {
-	decompose_canonical(c, |c| ret.push(c));
+fn normalized(s: &str) -> Cow<'_, str> {
+    // Almost every path is written already normalized (most are pure ASCII),
+    // so check before doing any work
+    if is_nfd_quick(s.chars()) == IsNormalized::Yes {
+	return Cow::Borrowed(s)
     }
-    ret
+    Cow::Owned(s.nfd().collect())
 }
 
 // Let's duplicate most of the logic of `Path::from_str` and include
-// `PathFromStrError` verbatim!
+// `PathFromStrError` verbatim! (`p!` never sets a length or escape limit, so
+// three of its variants go unused here.)
+#[allow(dead_code)]
 #[derive(Debug,PartialEq,Eq)]
 enum PathFromStrError {
     /// There were two or more consecutive slashes in the path.
@@ -37,6 +40,18 @@ enum PathFromStrError {
     EscapedRoot,
     /// A path ended with ".." (as opposed to "../")
     DotDotFile,
+    /// A component was longer than the limit passed to
+    /// [`try_from_str_bounded`](struct.Path.html#method.try_from_str_bounded).
+    ComponentTooLong,
+    /// The whole path was longer than the limit passed to
+    /// [`try_from_str_bounded`](struct.Path.html#method.try_from_str_bounded).
+    PathTooLong,
+    /// The path started with more `..`s than the limit passed to
+    /// [`try_from_str_max_escape`](struct.Path.html#method.try_from_str_max_escape).
+    TooManyParents,
+    /// The path looked like a Windows path (`C:\foo` or `\\server\share`),
+    /// rather than a Psilo-VFS path.
+    LooksLikeOsPath,
 }
 
 impl Display for PathFromStrError {
@@ -57,12 +72,32 @@ impl Display for PathFromStrError {
 				\"..\")"),
 	    PathFromStrError::DotDotFile
 		=> write!(fmt, "path ended with \"..\" (instead of \"../\")"),
+	    PathFromStrError::ComponentTooLong
+		=> write!(fmt, "some component of path was too long"),
+	    PathFromStrError::PathTooLong
+		=> write!(fmt, "path was too long"),
+	    PathFromStrError::TooManyParents
+		=> write!(fmt, "path started with too many \"..\""),
+	    PathFromStrError::LooksLikeOsPath
+		=> write!(fmt, "path looks like a Windows path; Psilo-VFS \
+				paths are virtual, separated by \"/\", and \
+				absolute paths start with \"/\""),
 	}
     }
 }
 
 impl Error for PathFromStrError {}
 
+// These are compiled the first time they're needed, and then kept for every
+// later `p!` the compiler expands: the compiler loads this crate once and
+// keeps it loaded for as long as it's compiling.
+
+/// A drive letter path (`C:\foo`, `C:/foo`, or just `C:`) or a UNC path
+/// (`\\server\share`).
+static OS_PATH_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^(?:[A-Za-z]:(?:[\\/]|$)|\\\\)"#)
+	.unwrap()
+});
 static INVALID_PATH_PREFIX_CHAR_PATTERN: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r#"^\."#)
 	.unwrap()
@@ -76,12 +111,20 @@ static INVALID_PATH_CHAR_PATTERN: Lazy<Regex> = Lazy::new(|| {
 	.unwrap()
 });
 static INVALID_PATH_NAME_PATTERN: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r#"^(?i:AUX|CO(?:M[1-9]|N)|LPT[1-9]|NUL|PRN)(?:\.|$)"#)
+    // (`¹`, `²`, and `³` count as digits here, `⁴` and up don't)
+    Regex::new(r#"(?x)
+	^(?i: AUX | CLOCK\$ | CON | CONIN\$ | CONOUT\$ | NUL | PRN
+	      | (?:COM|LPT)[1-9\x{B9}\x{B2}\x{B3}] )
+	(?:\.|$)"#)
 	.unwrap()
 });
 
+/// Takes a path that has already been `normalized`.
 fn validated(s: &str) -> Result<String, PathFromStrError> {
-    if s == "" || s == "/" {
+    if OS_PATH_PATTERN.is_match(s) {
+	return Err(PathFromStrError::LooksLikeOsPath)
+    }
+    else if s == "" || s == "/" {
 	return Ok(s.to_string())
     }
     else if s == "//" {
@@ -93,8 +136,19 @@ fn validated(s: &str) -> Result<String, PathFromStrError> {
     // First, check that all components are valid.
     let subset = s.strip_prefix("/").unwrap_or(s);
     let subset = subset.strip_suffix("/").unwrap_or(subset);
+    let mut need_edit = false;
+    let mut any_non_dotdot_components = false;
     for component in subset.split('/') {
-	if component == "." || component == ".." {}
+	if component == "." { need_edit = true }
+	else if component == ".." {
+	    // (in an absolute path, it escapes the root, caught below)
+	    if any_non_dotdot_components || s.starts_with('/') {
+		need_edit = true
+	    }
+	}
+	else if component.is_empty() {
+	    return Err(PathFromStrError::DoubleSlash)
+	}
 	else if INVALID_PATH_PREFIX_CHAR_PATTERN.is_match(component) {
 	    return Err(PathFromStrError::InvalidStartChar)
 	}
@@ -107,7 +161,9 @@ fn validated(s: &str) -> Result<String, PathFromStrError> {
 	else if INVALID_PATH_NAME_PATTERN.is_match(component) {
 	    return Err(PathFromStrError::ReservedName)
 	}
+	else { any_non_dotdot_components = true }
     }
+    if !need_edit { return Ok(s.to_string()) }
     // this string might grow slightly beyond this, hope that's OK
     let mut ret = String::with_capacity(s.len()+1);
     if s.starts_with("/") { ret.push('/') }
@@ -134,14 +190,13 @@ fn validated(s: &str) -> Result<String, PathFromStrError> {
 	    }
 	}
 	else {
-	    for c in component.chars() {
-		decompose_canonical(c, |c| ret.push(c));
-	    }
+	    ret.push_str(component);
 	    ret.push('/');
 	}
     }
-    // remove the extra trailing `/` that appeared
-    if !s.ends_with("/") { ret.pop(); }
+    // remove the extra trailing `/` that appeared, unless the path
+    // ended with `.`, which is a directory
+    if !s.ends_with("/") && !s.ends_with(".") { ret.pop(); }
     Ok(ret)
 }
 
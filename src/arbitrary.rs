@@ -0,0 +1,79 @@
+use crate::*;
+
+use proptest::{collection::vec, prelude::*};
+
+/// Generates a single valid path component. Every character is one that's
+/// already in normal form D, so the component never needs normalizing.
+fn component() -> impl Strategy<Value=String> {
+    "[a-zA-Z0-9_ßλ日-]([a-zA-Z0-9_ßλ日 .~^!-]{0,8}[a-zA-Z0-9_ßλ日-])?"
+        .prop_filter("reserved name",
+                     |x| Path::try_from_str(x).is_ok())
+}
+
+/// Generates valid paths: absolute or relative, file or directory, with zero
+/// to five components. Relative paths occasionally start with some `..`s.
+///
+/// Every generated path is exactly as `Path::try_from_str` would leave it, so
+/// parsing one borrows it unchanged.
+impl Arbitrary for PathBuf {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<PathBuf>;
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let dotdots = prop_oneof![4 => Just(0usize), 1 => 1 ..= 2usize];
+        (any::<bool>(), any::<bool>(), dotdots, vec(component(), 0 ..= 5))
+            .prop_map(|(absolute, directory, dotdots, components)| {
+                let mut ret = String::new();
+                if absolute { ret.push('/') }
+                else {
+                    for _ in 0 .. dotdots { ret.push_str("../") }
+                }
+                for component in components.iter() {
+                    ret.push_str(component);
+                    ret.push('/');
+                }
+                // "" and "/" are the only ways to have no components, and
+                // ".." can't name a file
+                if !directory && !components.is_empty() { ret.pop(); }
+                Path::from_str_preverified(&ret).to_owned()
+            }).boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::borrow::Cow;
+    proptest! {
+        #[test] fn round_trip(path in any::<PathBuf>()) {
+            match Path::try_from_str(path.as_str()) {
+                Ok(Cow::Borrowed(x)) => prop_assert_eq!(x.as_str(),
+                                                        path.as_str()),
+                x => prop_assert!(false, "{:?} became {:?}", path, x),
+            }
+        }
+        /// Normalizing a path that's already been normalized must leave it
+        /// alone, no matter how messy the original was. The alphabet is
+        /// heavy on things that need normalizing: `.` and `..` components,
+        /// precomposed characters, and combining marks in every order.
+        #[test] fn normalization_is_idempotent(
+            s in "(/|\\.|\\.\\.|a|e|é|ṩ|\u{301}|\u{323}|\u{308}|\u{307}| |~){0,16}"
+        ) {
+            if let Ok(once) = Path::try_from_str(&s) {
+                // Nothing left to normalize away, either
+                let rest = match once.as_str().strip_prefix('/') {
+                    Some(x) => x,
+                    None => once.as_str().trim_start_matches("../"),
+                };
+                let rest = rest.strip_suffix('/').unwrap_or(rest);
+                prop_assert!(rest.is_empty() || rest.split('/').all(|x| {
+                    !x.is_empty() && x != "." && x != ".."
+                }), "{:?} became {:?}", s, once);
+                match Path::try_from_str(once.as_str()) {
+                    Ok(Cow::Borrowed(x)) => prop_assert_eq!(x, &*once),
+                    x => prop_assert!(false, "{:?} became {:?}, then {:?}",
+                                      s, once, x),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::*;
+
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind},
+};
+
+/// A read-only source for content-addressed storage. Files are looked up by
+/// their logical paths in a manifest, which gives the hash of each file's
+/// contents; the contents themselves are stored in another source (the blob
+/// store), as files named after their hashes, all in its root directory.
+/// Any number of logical paths can share one blob.
+///
+/// The hashes are only used as names. Nothing is verified; see
+/// [`VFS::open_verified`](struct.VFS.html#method.open_verified) if you need
+/// that.
+///
+/// Directories are implied by the manifest: a directory exists if any file
+/// in the manifest is inside it.
+pub struct Source {
+    /// Logical path to blob path (`/` followed by the hash).
+    manifest: BTreeMap<PathBuf, PathBuf>,
+    blobs: Box<dyn VFSSource>,
+}
+
+impl Source {
+    /// Creates a source from a manifest of logical paths and hashes, and the
+    /// source the blobs are kept in.
+    ///
+    /// Returns `InvalidInput` if a logical path isn't an absolute path to a
+    /// file, or if a hash isn't usable as a file name.
+    pub fn new<I>(manifest: I, blobs: Box<dyn VFSSource>) -> io::Result<Source>
+    where I: IntoIterator<Item=(PathBuf, String)> {
+        let manifest = manifest.into_iter().map(|(path, hash)| {
+            if !path.is_absolute() || path.is_directory() {
+                let err = format!("manifest entry is not an absolute path \
+                                   to a file: {:?}", path);
+                return Err(io::Error::new(ErrorKind::InvalidInput, err))
+            }
+            match Path::try_from_str(&hash) {
+                Ok(name) if name.components().count() == 1
+                    && name.is_relative() && !name.is_directory() => {
+                        let mut blob = PathBuf::from_str("/");
+                        blob.join(&name);
+                        Ok((path, blob))
+                    },
+                _ => {
+                    let err = format!("manifest entry has an unusable hash: \
+                                       {:?}", hash);
+                    Err(io::Error::new(ErrorKind::InvalidInput, err))
+                },
+            }
+        }).collect::<io::Result<_>>()?;
+        Ok(Source { manifest, blobs })
+    }
+}
+
+/// Returns true if any of the given files is inside the given directory.
+fn any_inside<T>(index: &BTreeMap<PathBuf, T>, dir: &Path) -> bool {
+    index.range(dir.to_owned()..).next()
+        .map(|(x, _)| x.starts_with(dir.as_str())).unwrap_or(false)
+}
+
+/// The error for opening a file that isn't in the given index of files:
+/// `IsADirectory` if some file in the index is inside it, `NotFound`
+/// otherwise. Shared with other sources whose directories are implied by an
+/// index.
+pub(crate) fn not_in_index<T>(index: &BTreeMap<PathBuf, T>, path: &Path)
+                              -> io::Error {
+    let mut dir = path.to_owned();
+    dir.make_file_into_dir();
+    if any_inside(index, &dir) {
+        io::Error::from(ErrorKind::IsADirectory)
+    }
+    else {
+        io::Error::from(ErrorKind::NotFound)
+    }
+}
+
+/// Lists a directory implied by the given index of files. Shared with other
+/// sources whose directories are implied by an index.
+pub(crate) fn ls_index<T>(index: &BTreeMap<PathBuf, T>, path: &Path)
+                          -> io::Result<Vec<PathBuf>> {
+    let mut ret: Vec<PathBuf> = vec![];
+    // Everything inside this directory is contiguous in the map.
+    for (file, _) in index.range(path.to_owned()..) {
+        let rest = match file.as_str().strip_prefix(path.as_str()) {
+            Some(x) => x,
+            None => break,
+        };
+        let name = match rest.find('/') {
+            Some(i) => &rest[..=i],
+            None => rest,
+        };
+        if ret.last().map(|x| x.as_str() != name).unwrap_or(true) {
+            ret.push(PathBuf::from_str(name));
+        }
+    }
+    if ret.is_empty() && path.as_str() != "/" {
+        let file = &path.as_str()[..path.len()-1];
+        if index.contains_key(Path::from_str_preverified(file)) {
+            return Err(io::Error::from(ErrorKind::NotADirectory))
+        }
+        return Err(io::Error::from(ErrorKind::NotFound))
+    }
+    ret.sort_by(|a, b| a.dir_aware_cmp(b));
+    Ok(ret)
+}
+
+impl VFSSource for Source {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        match self.manifest.get(path) {
+            Some(blob) => self.blobs.open(blob),
+            None => Err(not_in_index(&self.manifest, path)),
+        }
+    }
+    fn open_stream(&self, path: &Path) -> io::Result<Box<dyn DataStream>> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        match self.manifest.get(path) {
+            Some(blob) => self.blobs.open_stream(blob),
+            None => Err(not_in_index(&self.manifest, path)),
+        }
+    }
+    fn open_range(&self, path: &Path, start: u64, len: u64)
+                  -> io::Result<Box<dyn DataFile>> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        match self.manifest.get(path) {
+            Some(blob) => self.blobs.open_range(blob, start, len),
+            None => Err(not_in_index(&self.manifest, path)),
+        }
+    }
+    fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        debug_assert!(path.is_absolute() && path.is_directory());
+        ls_index(&self.manifest, path)
+    }
+    fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+        Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::READ | Capabilities::LIST
+    }
+    fn physical_path(&self, path: &Path) -> Option<std::path::PathBuf> {
+        self.blobs.physical_path(self.manifest.get(path)?)
+    }
+    fn flush(&self) -> io::Result<()> {
+        self.blobs.flush()
+    }
+}
+
+#[cfg(all(test, feature = "rom"))]
+mod test {
+    use super::*;
+    const fn fsp(i: &str) -> &Path { Path::from_str_preverified(i) }
+    #[test] fn shared_blob() {
+        let blobs = crate::rom::Source::new(&[
+            (fsp("/5eed"), b"grass texture"),
+            (fsp("/c0de"), b"fn main() {}"),
+        ]);
+        let manifest = [
+            ("/textures/grass.png", "5eed"),
+            ("/textures/lawn.png", "5eed"),
+            ("/main.rs", "c0de"),
+            ("/missing", "dead"),
+        ].into_iter().map(|(path, hash)| {
+            (PathBuf::from_str(path), hash.to_string())
+        });
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(
+            Source::new(manifest, Box::new(blobs)).unwrap())).unwrap();
+        assert_eq!(vfs.read_as_bytes(fsp("/textures/grass.png")).unwrap(),
+                   b"grass texture");
+        assert_eq!(vfs.read_as_bytes(fsp("/textures/lawn.png")).unwrap(),
+                   b"grass texture");
+        assert_eq!(vfs.open(fsp("/5eed")).err().unwrap().kind(),
+                   ErrorKind::NotFound);
+        assert_eq!(vfs.open(fsp("/missing")).err().unwrap().kind(),
+                   ErrorKind::NotFound);
+        assert_eq!(vfs.open(fsp("/textures")).err().unwrap().kind(),
+                   ErrorKind::IsADirectory);
+        assert_eq!(vfs.ls(fsp("/")).unwrap().iter().map(|x| x.as_str())
+                   .collect::<Vec<_>>(), &["main.rs", "missing", "textures/"]);
+        assert_eq!(vfs.ls(fsp("/textures/")).unwrap().iter()
+                   .map(|x| x.as_str()).collect::<Vec<_>>(),
+                   &["grass.png", "lawn.png"]);
+        assert!(Source::new([(PathBuf::from_str("/bad"),
+                              "not/a/hash".to_string())],
+                            Box::new(crate::rom::Source::new(&[]))).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::*;
+
+use std::io::{self, Cursor, Read};
+
+/// A stream cipher, for use with [`CryptSource`](struct.CryptSource.html).
+///
+/// Both directions work on a whole file at once, in place. The path of the
+/// file is passed along, in case the cipher wants to derive a per-file key or
+/// nonce from it. (It's the path within the wrapped source, not within the
+/// VFS.)
+pub trait Cipher : Send + Sync {
+    /// Turns the stored contents of a file into its real contents.
+    fn decrypt(&self, path: &Path, data: &mut [u8]);
+    /// Turns the real contents of a file into its stored contents. Must be
+    /// the inverse of `decrypt`.
+    fn encrypt(&self, path: &Path, data: &mut [u8]);
+}
+
+/// Wraps another source, decrypting files as they're opened and encrypting
+/// them as they're updated. Listings pass through unchanged.
+///
+/// Whether (and how) an encrypted stream can be seeked in depends on the
+/// cipher, so it isn't attempted. Instead, a file is read into memory and
+/// decrypted in its entirety when it's opened.
+pub struct Source {
+    inner: Box<dyn VFSSource>,
+    cipher: Box<dyn Cipher>,
+}
+
+impl Source {
+    pub fn new(inner: Box<dyn VFSSource>, cipher: Box<dyn Cipher>)
+               -> Source {
+        Source { inner, cipher }
+    }
+}
+
+impl VFSSource for Source {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        let mut buf = vec![];
+        self.inner.open(path)?.read_to_end(&mut buf)?;
+        self.cipher.decrypt(path, &mut buf);
+        Ok(Box::new(Cursor::new(buf)))
+    }
+    fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        self.inner.ls(path)
+    }
+    fn dir_exists(&self, dir: &Path) -> io::Result<bool> {
+        self.inner.dir_exists(dir)
+    }
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        self.inner.exists(path)
+    }
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+    fn update(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        let mut buf = data.to_vec();
+        self.cipher.encrypt(path, &mut buf);
+        self.inner.update(path, &buf)
+    }
+    fn flush(&self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::MapSource;
+    const fn fsp(i: &str) -> &Path { Path::from_str_preverified(i) }
+    /// XORs every byte with a single key byte. Its own inverse.
+    struct Xor(u8);
+    impl Cipher for Xor {
+        fn decrypt(&self, _: &Path, data: &mut [u8]) {
+            data.iter_mut().for_each(|x| *x ^= self.0);
+        }
+        fn encrypt(&self, path: &Path, data: &mut [u8]) {
+            self.decrypt(path, data)
+        }
+    }
+    #[test] fn xor() {
+        let source = MapSource::new(&[("/secret", b"\x17\x16\x0c")]);
+        let stored = source.0.clone();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(Source::new(
+            Box::new(source), Box::new(Xor(0x42))))).unwrap();
+        assert_eq!(vfs.read_as_bytes(fsp("/secret")).unwrap(), b"UTN");
+        vfs.update(fsp("/secret"), b"hidden").unwrap();
+        assert_eq!(stored.lock().unwrap()[&PathBuf::from_str("/secret")],
+                   b"*+&&',");
+        assert_eq!(vfs.read_as_bytes(fsp("/secret")).unwrap(), b"hidden");
+        assert_eq!(vfs.ls(fsp("/")).unwrap().iter().map(|x| x.as_str())
+                   .collect::<Vec<_>>(), &["secret"]);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::*;
+
+use std::io::{self, ErrorKind};
+
+/// A `VFS` plus a current working directory, against which relative paths
+/// are resolved, for shell-like tools. Absolute paths are used as-is.
+pub struct VfsCwd {
+    vfs: VFS,
+    cwd: PathBuf,
+}
+
+impl VfsCwd {
+    /// Wraps the given `VFS`, starting out at `/`.
+    pub fn new(vfs: VFS) -> VfsCwd {
+        VfsCwd { vfs, cwd: PathBuf::from_str("/") }
+    }
+    /// Returns the `VFS` this is working on.
+    pub fn vfs(&self) -> &VFS { &self.vfs }
+    /// Returns the current working directory.
+    pub fn pwd(&self) -> &Path { &self.cwd }
+    /// Resolves the given path against the current working directory. Fails
+    /// with `InvalidInput` if a relative path tries to escape the root.
+    pub fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        let mut ret = self.cwd.clone();
+        ret.try_join_or_replace(path)
+            .map_err(|x| io::Error::new(ErrorKind::InvalidInput, x))?;
+        Ok(ret)
+    }
+    /// Resolves the given path, as a directory even if it wasn't written with
+    /// a trailing `/`.
+    fn resolve_dir(&self, path: &Path) -> io::Result<PathBuf> {
+        let mut ret = self.resolve(path)?;
+        if !ret.is_directory() { ret.make_file_into_dir() }
+        Ok(ret)
+    }
+    /// Changes the current working directory. The new directory must exist
+    /// (that is, `ls` must succeed on it). The trailing `/` is optional.
+    pub fn cd(&mut self, path: &Path) -> io::Result<()> {
+        let new_cwd = self.resolve_dir(path)?;
+        self.vfs.ls(&new_cwd)?;
+        self.cwd = new_cwd;
+        Ok(())
+    }
+    /// Opens the given file, relative to the current working directory.
+    pub fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        self.vfs.open(&self.resolve(path)?)
+    }
+    /// Lists the given directory, relative to the current working directory.
+    /// The trailing `/` is optional.
+    pub fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        self.vfs.ls(&self.resolve_dir(path)?)
+    }
+}
+
+#[cfg(all(test, feature = "rom"))]
+mod test {
+    use super::*;
+    use std::io::Read;
+    const fn fsp(i: &str) -> &Path { Path::from_str_preverified(i) }
+    #[test] fn cwd() {
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(crate::rom::Source::new(&[
+            (fsp("/levels/"), b""),
+            (fsp("/levels/one"), b"level one"),
+            (fsp("/levels/secret/"), b""),
+            (fsp("/levels/secret/cow"), b"moo"),
+            (fsp("/title"), b"title screen"),
+        ]))).unwrap();
+        let mut shell = VfsCwd::new(vfs);
+        assert_eq!(shell.pwd(), fsp("/"));
+        shell.cd(fsp("levels")).unwrap();
+        assert_eq!(shell.pwd(), fsp("/levels/"));
+        let mut buf = String::new();
+        shell.open(fsp("one")).unwrap().read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "level one");
+        assert_eq!(shell.ls(fsp("secret")).unwrap(),
+                   &[PathBuf::from_str("cow")]);
+        assert_eq!(shell.ls(fsp("")).unwrap(),
+                   &[PathBuf::from_str("one"), PathBuf::from_str("secret/")]);
+        shell.cd(fsp("secret/")).unwrap();
+        let mut buf = String::new();
+        shell.open(fsp("../../title")).unwrap().read_to_string(&mut buf)
+            .unwrap();
+        assert_eq!(buf, "title screen");
+        assert_eq!(shell.open(fsp("../../../title")).err().unwrap().kind(),
+                   ErrorKind::InvalidInput);
+        assert_eq!(shell.cd(fsp("nowhere")).unwrap_err().kind(),
+                   ErrorKind::NotFound);
+        assert_eq!(shell.pwd(), fsp("/levels/secret/"));
+        shell.cd(fsp("/")).unwrap();
+        assert_eq!(shell.pwd(), fsp("/"));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::*;
+
+use std::io::{self, ErrorKind};
+
+/// A source made of two other sources: a primary one, and a secondary one to
+/// fall back on, such as a user's own directory in front of the defaults that
+/// ship with a program. Unlike mounting both at the same point, this gives a
+/// single source, which can be wrapped or mounted like any other.
+///
+/// Files are opened from the primary source, or from the secondary source if
+/// the primary one gives `NotFound`. Listings are the union of both. Updates
+/// always go to the primary source, so the secondary one is never written to.
+pub struct Source {
+    primary: Box<dyn VFSSource>,
+    secondary: Box<dyn VFSSource>,
+}
+
+impl Source {
+    /// Creates a source that tries `primary` first, then `secondary`.
+    pub fn new(primary: Box<dyn VFSSource>, secondary: Box<dyn VFSSource>)
+               -> Source {
+        Source { primary, secondary }
+    }
+}
+
+impl VFSSource for Source {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        match self.primary.open(path) {
+            Err(x) if x.kind() == ErrorKind::NotFound
+                => self.secondary.open(path),
+            x => x,
+        }
+    }
+    fn open_stream(&self, path: &Path) -> io::Result<Box<dyn DataStream>> {
+        match self.primary.open_stream(path) {
+            Err(x) if x.kind() == ErrorKind::NotFound
+                => self.secondary.open_stream(path),
+            x => x,
+        }
+    }
+    fn open_range(&self, path: &Path, start: u64, len: u64)
+                  -> io::Result<Box<dyn DataFile>> {
+        match self.primary.open_range(path, start, len) {
+            Err(x) if x.kind() == ErrorKind::NotFound
+                => self.secondary.open_range(path, start, len),
+            x => x,
+        }
+    }
+    fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut listings = vec![];
+        let mut failed_with_not_dir = false;
+        for source in [&self.primary, &self.secondary] {
+            match source.ls(path) {
+                Ok(x) => listings.push(x),
+                Err(x) if x.kind() == ErrorKind::NotFound => (),
+                Err(x) if x.kind() == ErrorKind::NotADirectory
+                    => failed_with_not_dir = true,
+                Err(x) => return Err(x),
+            }
+        }
+        if listings.is_empty() {
+            if failed_with_not_dir {
+                return Err(io::Error::from(ErrorKind::NotADirectory))
+            }
+            return Err(io::Error::from(ErrorKind::NotFound))
+        }
+        let mut ret = vec![];
+        merge_listings(listings, &mut ret);
+        Ok(ret)
+    }
+    fn dir_exists(&self, dir: &Path) -> io::Result<bool> {
+        match self.primary.dir_exists(dir) {
+            Ok(false) => self.secondary.dir_exists(dir),
+            x => x,
+        }
+    }
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        match self.primary.exists(path) {
+            Ok(false) => self.secondary.exists(path),
+            x => x,
+        }
+    }
+    fn update(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.primary.update(path, data)
+    }
+    fn prepare_update(&self, path: &Path, data: &[u8])
+                      -> io::Result<Box<dyn PreparedUpdate + '_>> {
+        self.primary.prepare_update(path, data)
+    }
+    fn capabilities(&self) -> Capabilities {
+        let either = Capabilities::READ | Capabilities::LIST;
+        self.primary.capabilities() | (self.secondary.capabilities() & either)
+    }
+    fn flush(&self) -> io::Result<()> {
+        let primary = self.primary.flush();
+        let secondary = self.secondary.flush();
+        primary.and(secondary)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::MapSource;
+    const fn fsp(i: &str) -> &Path { Path::from_str_preverified(i) }
+    fn names(listing: Vec<PathBuf>) -> Vec<String> {
+        listing.into_iter().map(PathBuf::into_string).collect()
+    }
+    #[test] fn fallback() {
+        let primary = MapSource::new(&[
+            ("/config.ini", b"user config"),
+            ("/saves/1.sav", b"save"),
+        ]);
+        let primary_files = primary.0.clone();
+        let secondary = MapSource::new(&[
+            ("/config.ini", b"default config"),
+            ("/keys.ini", b"default keys"),
+            ("/saves", b"not a directory"),
+            ("/maps/start.map", b"map"),
+        ]);
+        let secondary_files = secondary.0.clone();
+        let source = Source::new(Box::new(primary), Box::new(secondary));
+        let read = |path| {
+            let mut ret = String::new();
+            source.open(fsp(path))?.read_to_string(&mut ret)?;
+            io::Result::Ok(ret)
+        };
+        assert_eq!(read("/config.ini").unwrap(), "user config");
+        assert_eq!(read("/keys.ini").unwrap(), "default keys");
+        assert_eq!(read("/saves/1.sav").unwrap(), "save");
+        assert_eq!(read("/nope").unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(names(source.ls(fsp("/")).unwrap()),
+                   ["config.ini", "keys.ini", "maps/", "saves/"]);
+        assert_eq!(names(source.ls(fsp("/maps/")).unwrap()), ["start.map"]);
+        assert_eq!(names(source.ls(fsp("/saves/")).unwrap()), ["1.sav"]);
+        assert_eq!(source.ls(fsp("/keys.ini/")).unwrap_err().kind(),
+                   ErrorKind::NotADirectory);
+        assert_eq!(source.ls(fsp("/nope/")).unwrap_err().kind(),
+                   ErrorKind::NotFound);
+        // Updates only ever go to the primary source
+        source.update(fsp("/keys.ini"), b"user keys").unwrap();
+        assert_eq!(read("/keys.ini").unwrap(), "user keys");
+        assert_eq!(primary_files.lock().unwrap()
+                   .get(fsp("/keys.ini")).unwrap(), b"user keys");
+        assert_eq!(secondary_files.lock().unwrap()
+                   .get(fsp("/keys.ini")).unwrap(), b"default keys");
+    }
+}
@@ -1,24 +1,137 @@
 use crate::*;
 
 use std::{
-    fs::{File, OpenOptions, rename, read_dir, remove_file},
+    borrow::Cow,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs::{File, OpenOptions, create_dir, create_dir_all, metadata, rename,
+         read_dir, remove_dir_all, remove_file, write},
     io::{self, Write},
     path,
 };
 use log::debug;
+use unicode_normalization::UnicodeNormalization;
 
 pub struct Source {
     base: path::PathBuf,
     read_only: bool,
+    normalization: NormalizationMode,
+    reserved_suffixes: bool,
+    noatime: bool,
 }
 
-impl DataFile for File {}
+/// An error that occurred during one step of an operation on a particular
+/// file. Keeps the kind of the original error, and gives the original error
+/// as its `source()`.
+#[derive(Debug)]
+struct StepError {
+    path: PathBuf,
+    step: &'static str,
+    inner: io::Error,
+}
+
+impl Display for StepError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{}: {}: {}", self.path, self.step, self.inner)
+    }
+}
+
+impl Error for StepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.inner)
+    }
+}
+
+/// For use with `map_err`. Wraps an `io::Error` in a `StepError`.
+fn step_error<'a>(path: &'a Path, step: &'static str)
+                  -> impl FnOnce(io::Error) -> io::Error + 'a {
+    move |inner| {
+        io::Error::new(inner.kind(),
+                       StepError { path: path.to_owned(), step, inner })
+    }
+}
+
+impl DataFile for File {
+    fn stream_len(&mut self) -> io::Result<u64> {
+        self.metadata().map(|x| x.len())
+    }
+}
 
 impl Source {
     pub fn new(base: path::PathBuf, read_only: bool) -> io::Result<Source> {
         debug!("Mounting {:?} read-{}", base,
                if read_only { "only" } else { "write" });
-        Ok(Source { base, read_only })
+        Ok(Source { base, read_only,
+                    normalization: NormalizationMode::default(),
+                    reserved_suffixes: true, noatime: false })
+    }
+    /// Changes the normal form this source uses on disk. See
+    /// [`NormalizationMode`](enum.NormalizationMode.html).
+    pub fn with_normalization(mut self, normalization: NormalizationMode)
+                              -> Source {
+        self.normalization = normalization;
+        self
+    }
+    /// Turns the backup and intermediate file conventions on (the default)
+    /// or off.
+    ///
+    /// When on, a file whose name ends with `^` or `!` is an intermediate
+    /// file, and is never listed; one whose name ends with `~` or `~~` is a
+    /// backup, which `open` falls back on if the original is missing, and
+    /// which is listed under the original's name. `update` keeps two
+    /// generations of backup: the file it replaces becomes `FILENAME~`, the
+    /// old `FILENAME~` becomes `FILENAME~~`, and the old `FILENAME~~` is
+    /// deleted. `open` tries the newer backup first.
+    ///
+    /// When off, every name on disk means exactly what it says. Names ending
+    /// in those characters are listed and opened like any other, but since
+    /// they can only be written as paths with
+    /// [`Path::try_from_str_permissive`](struct.Path.html#method.try_from_str_permissive),
+    /// you'll need that to get at them. `update` writes its intermediate file
+    /// under a name starting with `.` (which is never a valid path
+    /// component) and keeps no backup.
+    pub fn with_reserved_suffixes(mut self, reserved_suffixes: bool)
+                                  -> Source {
+        self.reserved_suffixes = reserved_suffixes;
+        self
+    }
+    /// Asks the OS not to update files' access times when this source opens
+    /// them, or stops asking (the default). For read-only assets that are
+    /// opened over and over, this saves the filesystem a write each time.
+    ///
+    /// Only does anything on Linux (and Android), with `O_NOATIME`, and only
+    /// for files owned by the user the program runs as. Other files, and
+    /// other platforms, are opened the ordinary way.
+    pub fn with_noatime(mut self, noatime: bool) -> Source {
+        self.noatime = noatime;
+        self
+    }
+    /// Opens a file for reading, with `O_NOATIME` if asked and allowed.
+    fn open_file(&self, os_path: &path::Path) -> io::Result<File> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if self.noatime {
+            use std::os::unix::fs::OpenOptionsExt;
+            // Using `O_NOATIME` on someone else's file is `EPERM`
+            match OpenOptions::new().read(true)
+                .custom_flags(libc::O_NOATIME).open(os_path) {
+                    Err(x) if x.raw_os_error() == Some(libc::EPERM) => (),
+                    x => return x,
+                }
+        }
+        File::open(os_path)
+    }
+    /// Works out where, on disk, the given absolute path lives.
+    fn os_path(&self, path: &Path) -> path::PathBuf {
+        self.base.join(&*self.os_relative(&path.as_str()[1..]))
+    }
+    /// Converts a relative path (or any part of one) into the normal form
+    /// used on disk.
+    fn os_relative<'a>(&self, relative: &'a str) -> Cow<'a, str> {
+        match self.normalization {
+            NormalizationMode::Nfd => Cow::Borrowed(relative),
+            NormalizationMode::Nfc
+                => Cow::Owned(relative.nfc().collect::<String>()),
+        }
     }
 }
 
@@ -28,22 +141,55 @@ impl VFSSource for Source {
     // ones do too. Not sure about Linux ones?
     fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
         debug_assert!(path.is_absolute() && !path.is_directory());
-        let os_path = self.base.join(&path.as_str()[1..]);
-        match File::open(&os_path) {
-            Err(x) if x.kind() == io::ErrorKind::NotFound => {
-                let mut backup_path = os_path;
-                backup_path.set_file_name(backup_path.file_name().unwrap()
-                                          .to_str().unwrap()
-                                          .to_string() + "~");
-                File::open(&backup_path)
+        let os_path = self.os_path(path);
+        // `NotFound` is left bare. It's the usual outcome when the VFS probes
+        // a mount that doesn't have the file, and it carries no extra
+        // information anyway.
+        match self.open_file(&os_path) {
+            Err(x) if x.kind() == io::ErrorKind::NotFound
+                && self.reserved_suffixes => {
+                let filename = os_path.file_name().unwrap().to_str().unwrap();
+                let mut ret = Err(x);
+                for suffix in ["~", "~~"] {
+                    let backup_path = os_path.with_file_name(
+                        filename.to_string() + suffix);
+                    match self.open_file(&backup_path) {
+                        Err(x) if x.kind() == io::ErrorKind::NotFound
+                            => ret = Err(x),
+                        x => {
+                            ret = x.map_err(step_error(path,
+                                                       "opening backup"));
+                            break
+                        },
+                    }
+                }
+                ret
             },
+            Err(x) => Err(step_error(path, "opening")(x)),
             x => x,
         }.map(|x| -> Box<dyn DataFile> { Box::new(x) })
     }
     fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
         debug_assert!(path.is_absolute() && path.is_directory());
         let mut paths = Vec::<PathBuf>::new();
-        let os_path = self.base.join(&path.as_str()[1..]);
+        let os_path = self.os_path(path);
+        // Look before leaping, since what `read_dir` says when pointed at a
+        // file varies from platform to platform. A path that goes *through*
+        // a file (`ENOTDIR`) is just missing; only the file itself isn't a
+        // directory. (The trailing `/` is left off, or the file itself would
+        // give `ENOTDIR` too.)
+        let bare_path: path::PathBuf = os_path.components().collect();
+        match metadata(&bare_path) {
+            Ok(x) if x.is_dir() => (),
+            Ok(_) => {
+                return Err(io::Error::from(io::ErrorKind::NotADirectory))
+            },
+            Err(x) if x.kind() == io::ErrorKind::NotFound
+                || x.kind() == io::ErrorKind::NotADirectory => {
+                return Err(io::Error::from(io::ErrorKind::NotFound))
+            },
+            Err(x) => return Err(x),
+        }
         let mut dir = read_dir(os_path)?;
         while let Some(entry) = dir.next() {
             let entry = entry?;
@@ -53,43 +199,765 @@ impl VFSSource for Source {
                     Some(x) => x,
                     _ => continue,
                 };
-            if filename.ends_with("^") || filename.ends_with("!")
-                || filename.ends_with("~~") { continue }
-            else if filename.ends_with("~") {
-                filename.pop(); // :)
+            if self.reserved_suffixes {
+                if filename.ends_with("^") || filename.ends_with("!") {
+                    continue
+                }
+                else if filename.ends_with("~") {
+                    filename.pop(); // :)
+                    // "FILENAME~~" is the older backup
+                    if filename.ends_with("~") { filename.pop(); }
+                }
             }
             if entry.path().is_dir() { filename.push('/'); }
-            match PathBuf::try_from_str(&filename) {
-                Ok(path) => paths.push(path),
+            let path = if self.reserved_suffixes {
+                Path::try_from_str(&filename)
+            } else { Path::try_from_str_permissive(&filename) };
+            match path {
+                Ok(path) => paths.push(path.into_owned()),
                 _ => continue,
             }
         }
+        paths.sort_by(|a, b| a.dir_aware_cmp(b));
+        // Names that differ only in normal form end up the same
+        paths.dedup();
         Ok(paths)
     }
+    fn dir_exists(&self, dir: &Path) -> io::Result<bool> {
+        debug_assert!(dir.is_absolute() && dir.is_directory());
+        match metadata(self.os_path(dir)) {
+            Ok(x) if x.is_dir() => Ok(true),
+            Ok(_) => Err(io::Error::from(io::ErrorKind::NotADirectory)),
+            Err(x) if x.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(x) => Err(x),
+        }
+    }
+    /// Mirrors `open`, including falling back on a backup file.
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        let os_path = self.os_path(path);
+        let suffixes: &[&str] = if self.reserved_suffixes { &["", "~", "~~"] }
+        else { &[""] };
+        for suffix in suffixes {
+            let mut candidate = os_path.clone().into_os_string();
+            candidate.push(suffix);
+            match metadata(&candidate) {
+                Ok(_) => return Ok(true),
+                Err(x) if x.kind() == io::ErrorKind::NotFound => (),
+                Err(x) => return Err(x),
+            }
+        }
+        Ok(false)
+    }
+    /// Mirrors `open`, including falling back on a backup file.
+    fn physical_path(&self, path: &Path) -> Option<path::PathBuf> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        let os_path = self.os_path(path);
+        if os_path.is_file() { return Some(os_path) }
+        if !self.reserved_suffixes || os_path.exists() { return None }
+        let filename = os_path.file_name()?.to_str()?;
+        ["~", "~~"].into_iter()
+            .map(|x| os_path.with_file_name(filename.to_string() + x))
+            .find(|backup_path| backup_path.is_file())
+    }
+    fn capabilities(&self) -> Capabilities {
+        if self.read_only { Capabilities::READ | Capabilities::LIST }
+        else { Capabilities::READ | Capabilities::WRITE | Capabilities::LIST }
+    }
+    fn spec(&self) -> Option<MountSpec> {
+        Some(MountSpec::Fs { base: self.base.clone(),
+                             read_only: self.read_only,
+                             normalization: self.normalization,
+                             reserved_suffixes: self.reserved_suffixes,
+                             noatime: self.noatime })
+    }
     fn update(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.prepare_update(path, data)?.commit()
+    }
+    fn prepare_update(&self, path: &Path, data: &[u8])
+                      -> io::Result<Box<dyn PreparedUpdate + '_>> {
         debug_assert!(path.is_absolute() && !path.is_directory());
         if self.read_only { return Err(io::Error::from(io::ErrorKind
                                                        ::ReadOnlyFilesystem)) }
-        let os_path = self.base.join(&path.as_str()[1..]);
-        let mut backup_path = os_path.clone();
-        backup_path.set_file_name(os_path.file_name().unwrap()
-                                  .to_str().unwrap().to_string() + "~");
-        let mut updated_path = os_path.clone();
-        updated_path.set_file_name(os_path.file_name().unwrap()
-                                   .to_str().unwrap().to_string() + "^");
+        let os_path = self.os_path(path);
+        let filename = os_path.file_name().unwrap().to_str().unwrap();
+        let (backup_path, updated_path) = if self.reserved_suffixes {
+            (Some(os_path.with_file_name(filename.to_string() + "~")),
+             os_path.with_file_name(filename.to_string() + "^"))
+        } else {
+            (None, os_path.with_file_name(format!(".{}^", filename)))
+        };
         // Try to write the new data to "FILENAME^"
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
-            .open(&updated_path)?;
-        file.write_all(data)?;
+            .open(&updated_path)
+            .map_err(step_error(path, "creating intermediate file"))?;
+        // From here on, dropping `update` cleans up "FILENAME^"
+        let update = Update { path: path.to_owned(), os_path, backup_path,
+                                  updated_path: Some(updated_path) };
+        file.write_all(data)
+            .map_err(step_error(path, "writing intermediate file"))?;
         drop(file);
-        // Delete "FILENAME~", ignoring errors
-        let _ = remove_file(&backup_path);
-        // Move "FILENAME" to "FILENAME~"
-        rename(&os_path, &backup_path)?;
+        Ok(Box::new(update))
+    }
+    /// Writes the new contents into a staging directory next to the old one
+    /// ("DIRNAME^", or ".DIRNAME^" without reserved suffixes), then swaps it
+    /// into place with two renames, and deletes the old one. In between the
+    /// renames, the directory briefly doesn't exist; if the second rename
+    /// fails, the old directory is put back. Either way, nothing is changed
+    /// if writing the staging directory fails.
+    fn replace_dir(&self, dir: &Path, entries: &[(PathBuf, Vec<u8>)])
+                   -> io::Result<()> {
+        debug_assert!(dir.is_absolute() && dir.is_directory());
+        if self.read_only { return Err(io::Error::from(io::ErrorKind
+                                                       ::ReadOnlyFilesystem)) }
+        if dir.as_str() == "/" {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "can't replace the root of an \
+                                       FsSource"))
+        }
+        let os_path = self.os_path(Path::from_str_preverified(
+            &dir.as_str()[..dir.len()-1]));
+        let dirname = os_path.file_name().unwrap().to_str().unwrap();
+        let (staging_path, old_path) = if self.reserved_suffixes {
+            (os_path.with_file_name(dirname.to_string() + "^"),
+             os_path.with_file_name(dirname.to_string() + "!"))
+        } else {
+            (os_path.with_file_name(format!(".{}^", dirname)),
+             os_path.with_file_name(format!(".{}!", dirname)))
+        };
+        // Leftovers from an earlier attempt that didn't finish
+        let _ = remove_dir_all(&staging_path);
+        let _ = remove_dir_all(&old_path);
+        create_dir(&staging_path)
+            .map_err(step_error(dir, "creating staging directory"))?;
+        let staged = entries.iter().try_for_each(|(path, data)| {
+            let os_file = staging_path.join(&*self.os_relative(path.as_str()));
+            create_dir_all(os_file.parent().unwrap())
+                .and_then(|_| write(&os_file, data))
+                .map_err(step_error(path, "writing into staging directory"))
+        });
+        if let Err(x) = staged {
+            let _ = remove_dir_all(&staging_path);
+            return Err(x)
+        }
+        let had_old = match rename(&os_path, &old_path) {
+            Err(x) if x.kind() == io::ErrorKind::NotFound => false,
+            Err(x) => {
+                let _ = remove_dir_all(&staging_path);
+                return Err(step_error(dir, "moving old directory aside")(x))
+            },
+            Ok(()) => true,
+        };
+        if let Err(x) = rename(&staging_path, &os_path) {
+            if had_old { let _ = rename(&old_path, &os_path); }
+            let _ = remove_dir_all(&staging_path);
+            return Err(step_error(dir, "moving staging directory into \
+                                       place")(x))
+        }
+        if had_old { let _ = remove_dir_all(&old_path); }
+        Ok(())
+    }
+}
+
+/// An update whose new data has been written to "FILENAME^" (or ".FILENAME^"
+/// without reserved suffixes), but not yet moved into place.
+struct Update {
+    path: PathBuf,
+    os_path: path::PathBuf,
+    /// `None` without reserved suffixes.
+    backup_path: Option<path::PathBuf>,
+    /// `None` once the intermediate file has been moved into place.
+    updated_path: Option<path::PathBuf>,
+}
+
+impl PreparedUpdate for Update {
+    fn commit(mut self: Box<Self>) -> io::Result<()> {
+        let path = &self.path;
+        let updated_path = self.updated_path.as_ref().unwrap();
+        if let Some(backup_path) = self.backup_path.as_ref() {
+            // Move "FILENAME~" to "FILENAME~~", replacing the old
+            // "FILENAME~~", ignoring errors. If there's no "FILENAME~", the
+            // old "FILENAME~~" stays. If this fails otherwise, the rename
+            // below replaces "FILENAME~" instead, losing that generation.
+            let mut oldest_path = backup_path.clone().into_os_string();
+            oldest_path.push("~");
+            let _ = rename(backup_path, &oldest_path);
+            // Move "FILENAME" to "FILENAME~", if there is a "FILENAME"
+            match rename(&self.os_path, backup_path) {
+                Err(x) if x.kind() == io::ErrorKind::NotFound => (),
+                x => x.map_err(step_error(path,
+                                          "moving old file to backup"))?,
+            }
+        }
         // Move "FILENAME^" to "FILENAME"
-        rename(&updated_path, &os_path)
+        rename(updated_path, &self.os_path)
+            .map_err(step_error(path, "moving intermediate file into place"))?;
+        self.updated_path = None;
+        Ok(())
+    }
+}
+
+impl Drop for Update {
+    fn drop(&mut self) {
+        // Not committed (or failed to commit), clean up "FILENAME^"
+        if let Some(updated_path) = self.updated_path.as_ref() {
+            let _ = remove_file(updated_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Seek};
+    /// Makes a fresh, empty directory for a test to scribble in.
+    fn scratch_dir(name: &str) -> path::PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("psilo-vfs-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    #[test] fn stream_len_keeps_position() {
+        let dir = scratch_dir("stream_len");
+        std::fs::write(dir.join("lipsum"), b"Lorem ipsum dolor sit amet")
+            .unwrap();
+        let source = Source::new(dir, true).unwrap();
+        let mut file = source.open(Path::from_str_preverified("/lipsum"))
+            .unwrap();
+        let mut buf = [0u8; 6];
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(DataFile::stream_len(file.as_mut()).unwrap(), 26);
+        assert_eq!(file.stream_position().unwrap(), 6);
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ipsum ");
+    }
+    #[test] fn ls_errors() {
+        let dir = scratch_dir("ls_errors");
+        std::fs::write(dir.join("file.txt"), b"not a directory").unwrap();
+        let source = Source::new(dir, true).unwrap();
+        assert_eq!(source.ls(Path::from_str_preverified("/file.txt/"))
+                   .unwrap_err().kind(), io::ErrorKind::NotADirectory);
+        assert_eq!(source.ls(Path::from_str_preverified("/file.txt/sub/"))
+                   .unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(source.ls(Path::from_str_preverified("/missing/"))
+                   .unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(source.ls(Path::from_str_preverified("/")).unwrap(),
+                   &[PathBuf::from_str("file.txt")]);
+    }
+    /// An empty directory is listed as empty, no matter what the other
+    /// mounts think of it.
+    #[test] fn empty_directories() {
+        let dir = scratch_dir("empty_directories");
+        for subdir in ["empty", "mnt", "elsewhere"] {
+            std::fs::create_dir(dir.join(subdir)).unwrap();
+        }
+        std::fs::write(dir.join("elsewhere").join("file"), b"").unwrap();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(Source::new(dir.clone(), true).unwrap())).unwrap();
+        vfs.mount(PathBuf::from_str("/mnt/"),
+                  Box::new(Source::new(dir.join("mnt"), true).unwrap()))
+            .unwrap();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(Source::new(dir.join("elsewhere"), true).unwrap()))
+            .unwrap();
+        for path in ["/empty/", "/mnt/"] {
+            assert_eq!(vfs.ls(Path::from_str_preverified(path)).unwrap(),
+                       &[] as &[PathBuf], "{:?}", path);
+        }
+        assert_eq!(vfs.ls(Path::from_str_preverified("/missing/"))
+                   .unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(vfs.ls(Path::from_str_preverified("/file/"))
+                   .unwrap_err().kind(), io::ErrorKind::NotADirectory);
+    }
+    /// A buffered file doesn't see changes made after it was opened, since
+    /// it's entirely in memory. An unbuffered one does.
+    #[test] fn open_buffered() {
+        let dir = scratch_dir("open_buffered");
+        std::fs::write(dir.join("small"), b"small").unwrap();
+        std::fs::write(dir.join("large"), b"much larger").unwrap();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(Source::new(dir.clone(), true).unwrap())).unwrap();
+        let mut small = vfs.open_buffered(Path::from_str_preverified("/small"),
+                                          8).unwrap();
+        let mut large = vfs.open_buffered(Path::from_str_preverified("/large"),
+                                          8).unwrap();
+        std::fs::write(dir.join("small"), b"SMALL").unwrap();
+        std::fs::write(dir.join("large"), b"MUCH LARGER").unwrap();
+        let mut buf = String::new();
+        small.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "small");
+        let mut buf = String::new();
+        large.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "MUCH LARGER");
+    }
+    /// A directory in the way of the backup file makes the update fail at the
+    /// rename step. The error should say so.
+    #[cfg(unix)]
+    #[test] fn update_error_context() {
+        let dir = scratch_dir("update_error_context");
+        std::fs::write(dir.join("save"), b"old").unwrap();
+        // (two of them, so the old backup can't be moved out of the way)
+        for backup in ["save~", "save~~"] {
+            std::fs::create_dir(dir.join(backup)).unwrap();
+            std::fs::write(dir.join(backup).join("obstacle"), b"").unwrap();
+        }
+        let source = Source::new(dir.clone(), false).unwrap();
+        let err = source.update(Path::from_str_preverified("/save"), b"new")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::IsADirectory);
+        assert!(err.to_string()
+                .starts_with("/save: moving old file to backup: "),
+                "unexpected message: {}", err);
+        let inner = err.get_ref().and_then(Error::source)
+            .and_then(|x| x.downcast_ref::<io::Error>())
+            .expect("original error should be the source");
+        assert_eq!(inner.kind(), io::ErrorKind::IsADirectory);
+        assert_eq!(std::fs::read(dir.join("save")).unwrap(), b"old");
+    }
+    /// Each update pushes the backups back a generation, keeping two.
+    #[test] fn double_backup() {
+        let dir = scratch_dir("double_backup");
+        std::fs::write(dir.join("save"), b"first").unwrap();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(Source::new(dir.clone(), false).unwrap())).unwrap();
+        let path = Path::from_str_preverified("/save");
+        vfs.update(path, b"second").unwrap();
+        vfs.update(path, b"third").unwrap();
+        let read = |name| String::from_utf8(std::fs::read(dir.join(name))
+                                            .unwrap()).unwrap();
+        assert_eq!([read("save"), read("save~"), read("save~~")],
+                   ["third", "second", "first"]);
+        assert_eq!(vfs.ls(Path::from_str_preverified("/")).unwrap(),
+                   &[PathBuf::from_str("save")]);
+        // `open` falls back on the newer backup, then the older one
+        std::fs::remove_file(dir.join("save")).unwrap();
+        assert_eq!(vfs.read_as_string(path).unwrap(), "second");
+        std::fs::remove_file(dir.join("save~")).unwrap();
+        assert_eq!(vfs.read_as_string(path).unwrap(), "first");
+        assert_eq!(vfs.physical_path(path), Some(dir.join("save~~")));
+        assert_eq!(vfs.ls(Path::from_str_preverified("/")).unwrap(),
+                   &[PathBuf::from_str("save")]);
+        // The oldest generation is the one that's dropped
+        std::fs::write(dir.join("save"), b"third").unwrap();
+        std::fs::write(dir.join("save~"), b"second").unwrap();
+        vfs.update(path, b"fourth").unwrap();
+        assert_eq!([read("save"), read("save~"), read("save~~")],
+                   ["fourth", "third", "second"]);
+        // With no newer backup to take its place, the oldest one is kept
+        std::fs::remove_file(dir.join("save~")).unwrap();
+        vfs.update(path, b"fifth").unwrap();
+        assert_eq!([read("save"), read("save~"), read("save~~")],
+                   ["fifth", "fourth", "second"]);
+    }
+    /// With reserved suffixes off, `data!` is an ordinary file, and `save~`
+    /// is not a backup of `save`.
+    #[test] fn without_reserved_suffixes() {
+        let dir = scratch_dir("without_reserved_suffixes");
+        std::fs::write(dir.join("data!"), b"exclamatory").unwrap();
+        std::fs::write(dir.join("save~"), b"old").unwrap();
+        let strict = Source::new(dir.clone(), false).unwrap();
+        let listing = strict.ls(Path::from_str_preverified("/")).unwrap();
+        let listing = listing.iter().map(|x| x.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(listing, &["save"]);
+        let source = Source::new(dir.clone(), false).unwrap()
+            .with_reserved_suffixes(false);
+        let listing = source.ls(Path::from_str_preverified("/")).unwrap();
+        let listing = listing.iter().map(|x| x.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(listing, &["data!", "save~"]);
+        let data = Path::try_from_str_permissive("/data!").unwrap();
+        let mut buf = String::new();
+        source.open(&data).unwrap().read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "exclamatory");
+        assert_eq!(source.open(Path::from_str_preverified("/save"))
+                   .err().unwrap().kind(), io::ErrorKind::NotFound);
+        source.update(Path::from_str_preverified("/save"), b"new").unwrap();
+        source.update(&data, b"calm").unwrap();
+        let mut leftovers = std::fs::read_dir(&dir).unwrap()
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        leftovers.sort();
+        assert_eq!(leftovers, &["data!", "save", "save~"]);
+        assert_eq!(std::fs::read(dir.join("data!")).unwrap(), b"calm");
+        assert_eq!(std::fs::read(dir.join("save~")).unwrap(), b"old");
+    }
+    /// A directory's contents are swapped out whole, with nothing left
+    /// behind.
+    #[test] fn replace_dir() {
+        let dir = scratch_dir("replace_dir");
+        let current = dir.join("data").join("current");
+        std::fs::create_dir_all(current.join("sub")).unwrap();
+        for name in ["old1", "old2", "sub/old3"] {
+            std::fs::write(current.join(name), b"old").unwrap();
+        }
+        std::fs::write(dir.join("data").join("other"), b"other").unwrap();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/data/"),
+                  Box::new(Source::new(dir.join("data"), false).unwrap()))
+            .unwrap();
+        vfs.mount(PathBuf::from_str("/ro/"),
+                  Box::new(Source::new(dir.clone(), true).unwrap()))
+            .unwrap();
+        let fsp = Path::from_str_preverified;
+        let ls = |path: &str| vfs.ls(fsp(path)).unwrap().iter()
+            .map(|x| x.as_str().to_string()).collect::<Vec<_>>();
+        let entries = || [("a", b"A"), ("b", b"B"), ("sub/c", b"C")]
+            .into_iter()
+            .map(|(path, data)| (PathBuf::from_str(path), data.to_vec()));
+        vfs.replace_dir(fsp("/data/current/"), entries()).unwrap();
+        assert_eq!(ls("/data/current/"), &["a", "b", "sub/"]);
+        assert_eq!(ls("/data/current/sub/"), &["c"]);
+        assert_eq!(vfs.read_as_bytes(fsp("/data/current/sub/c")).unwrap(),
+                   b"C");
+        // Nothing left behind, not even hidden
+        let mut on_disk = std::fs::read_dir(dir.join("data")).unwrap()
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        on_disk.sort();
+        assert_eq!(on_disk, &["current", "other"]);
+        // A directory that doesn't exist yet is just created
+        vfs.replace_dir(fsp("/data/new/"), entries()).unwrap();
+        assert_eq!(ls("/data/new/"), &["a", "b", "sub/"]);
+        assert_eq!(vfs.replace_dir(fsp("/ro/data/"), entries()).unwrap_err()
+                   .kind(), io::ErrorKind::ReadOnlyFilesystem);
+        assert_eq!(vfs.replace_dir(fsp("/data/missing/dir/"), entries())
+                   .unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(vfs.replace_dir(fsp("/data/current/"),
+                                   [(PathBuf::from_str("../x"), vec![])])
+                   .unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(vfs.replace_dir(fsp("/data/"), entries()).unwrap_err()
+                   .kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(ls("/data/current/"), &["a", "b", "sub/"]);
+        assert_eq!(ls("/"), &["data/", "ro/"]);
+        vfs.set_dry_run(true);
+        vfs.replace_dir(fsp("/data/current/"), entries().take(1)).unwrap();
+        assert_eq!(vfs.take_dry_run_log(), &[DryRunOp::ReplaceDir {
+            mount_point: PathBuf::from_str("/data/"),
+            path: PathBuf::from_str("/data/current/"),
+            entries: entries().take(1).collect(),
+        }]);
+        assert_eq!(ls("/data/current/"), &["a", "b", "sub/"]);
+    }
+    /// Writes in dry-run mode are checked and logged, but nothing on disk
+    /// changes.
+    #[test] fn dry_run() {
+        let dir = scratch_dir("dry_run");
+        std::fs::create_dir(dir.join("save")).unwrap();
+        std::fs::create_dir(dir.join("ro")).unwrap();
+        std::fs::write(dir.join("save").join("slot1"), b"old").unwrap();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/save/"),
+                  Box::new(Source::new(dir.join("save"), false).unwrap()))
+            .unwrap();
+        vfs.mount(PathBuf::from_str("/ro/"),
+                  Box::new(Source::new(dir.join("ro"), true).unwrap()))
+            .unwrap();
+        vfs.set_dry_run(true);
+        vfs.update(Path::from_str_preverified("/save/slot1"), b"new")
+            .unwrap();
+        vfs.update(Path::from_str_preverified("/save/slot2"), b"two")
+            .unwrap();
+        assert_eq!(vfs.update(Path::from_str_preverified("/ro/x"), b"")
+                   .unwrap_err().kind(), io::ErrorKind::ReadOnlyFilesystem);
+        assert_eq!(vfs.update(Path::from_str_preverified("/save/a/b"), b"")
+                   .unwrap_err().kind(), io::ErrorKind::NotFound);
+        let mut transaction = vfs.transaction();
+        transaction.update(Path::from_str_preverified("/save/slot3"), b"3");
+        transaction.commit().unwrap();
+        let mut transaction = vfs.transaction();
+        transaction.update(Path::from_str_preverified("/save/slot4"), b"4");
+        transaction.update(Path::from_str_preverified("/ro/slot5"), b"5");
+        assert!(transaction.commit().is_err());
+        assert_eq!(vfs.read_as_bytes(Path::from_str_preverified("/save/slot1"))
+                   .unwrap(), b"old");
+        let update = |path: &str, data: &[u8]| DryRunOp::Update {
+            mount_point: PathBuf::from_str("/save/"),
+            path: PathBuf::from_str(path),
+            data: data.to_vec(),
+        };
+        assert_eq!(vfs.take_dry_run_log(),
+                   &[update("/save/slot1", b"new"),
+                     update("/save/slot2", b"two"),
+                     update("/save/slot3", b"3")]);
+        assert!(vfs.take_dry_run_log().is_empty());
+        let mut leftovers = std::fs::read_dir(dir.join("save")).unwrap()
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        leftovers.sort();
+        assert_eq!(leftovers, &["slot1"]);
+        vfs.set_dry_run(false);
+        vfs.update(Path::from_str_preverified("/save/slot1"), b"new")
+            .unwrap();
+        assert!(vfs.take_dry_run_log().is_empty());
+        assert_eq!(std::fs::read(dir.join("save").join("slot1")).unwrap(),
+                   b"new");
+    }
+    /// Many threads update one file at once. Every update has to succeed,
+    /// and the file always has to hold exactly one of them.
+    #[test] fn concurrent_updates() {
+        const THREADS: u8 = 8;
+        const UPDATES: usize = 100;
+        let dir = scratch_dir("concurrent_updates");
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(Source::new(dir.clone(), false).unwrap())).unwrap();
+        let path = Path::from_str_preverified("/slot");
+        vfs.update(path, &[b'@'; 65536]).unwrap();
+        let threads = (0 .. THREADS).map(|n| {
+            let vfs = vfs.clone();
+            std::thread::spawn(move || {
+                let data = [b'a' + n; 65536];
+                for _ in 0 .. UPDATES {
+                    vfs.update(path, &data).unwrap();
+                    let read = vfs.read_as_bytes(path).unwrap();
+                    assert_eq!(read.len(), 65536);
+                    assert!(read.iter().all(|&x| x == read[0]));
+                }
+            })
+        }).collect::<Vec<_>>();
+        for thread in threads { thread.join().unwrap() }
+        let mut leftovers = std::fs::read_dir(&dir).unwrap()
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        leftovers.sort();
+        assert_eq!(leftovers, &["slot", "slot~", "slot~~"]);
+    }
+    #[test] fn update_new_file() {
+        let dir = scratch_dir("update_new_file");
+        let source = Source::new(dir.clone(), false).unwrap();
+        let path = Path::from_str_preverified("/save");
+        // No old "save" to move aside, and no backup made of nothing
+        source.update(path, b"first").unwrap();
+        let names = || {
+            let mut names = std::fs::read_dir(&dir).unwrap()
+                .map(|x| x.unwrap().file_name().into_string().unwrap())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(names(), &["save"]);
+        source.update(path, b"second").unwrap();
+        assert_eq!(names(), &["save", "save~"]);
+        assert_eq!(std::fs::read(dir.join("save~")).unwrap(), b"first");
+    }
+    #[test] fn update_parent_directory() {
+        let dir = scratch_dir("update_parent_directory");
+        std::fs::create_dir(dir.join("saves")).unwrap();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(Source::new(dir.clone(), false).unwrap())).unwrap();
+        vfs.update(Path::from_str_preverified("/saves/slot1"), b"saved")
+            .unwrap();
+        assert_eq!(std::fs::read(dir.join("saves").join("slot1")).unwrap(),
+                   b"saved");
+        let err = vfs.update(Path::from_str_preverified("/nope/slot1"),
+                             b"saved").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("\"/nope/\""),
+                "unexpected message: {}", err);
+        assert!(!dir.join("nope").exists());
+        let err = vfs.update(Path::from_str_preverified("saves/slot1"),
+                             b"saved").unwrap_err();
+        assert!(err.to_string().starts_with("attempt to update"),
+                "unexpected message: {}", err);
+    }
+    #[cfg(feature = "rom")]
+    #[test] fn physical_path() {
+        const ROM: &[(&Path, &[u8])] = &[
+            (Path::from_str_preverified("/splash.png"), b"not really a png"),
+            (Path::from_str_preverified("/shadowed"), b"from rom"),
+        ];
+        let dir = scratch_dir("physical_path");
+        std::fs::write(dir.join("video.webm"), b"not really a video").unwrap();
+        std::fs::write(dir.join("backed-up~"), b"old").unwrap();
+        std::fs::write(dir.join("shadowed"), b"from disk").unwrap();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(Source::new(dir.clone(), true).unwrap())).unwrap();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(crate::rom::Source::new(ROM))).unwrap();
+        let physical_path = |path: &str| {
+            vfs.physical_path(Path::from_str_preverified(path))
+        };
+        assert_eq!(physical_path("/video.webm"), Some(dir.join("video.webm")));
+        assert_eq!(physical_path("/backed-up"), Some(dir.join("backed-up~")));
+        assert_eq!(physical_path("/splash.png"), None);
+        assert_eq!(physical_path("/shadowed"), None);
+        assert_eq!(physical_path("/missing"), None);
+    }
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test] fn noatime() {
+        let dir = scratch_dir("noatime");
+        std::fs::write(dir.join("asset"), b"read me").unwrap();
+        std::fs::write(dir.join("asset~"), b"backup").unwrap();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(Source::new(dir.clone(), true).unwrap()
+                           .with_noatime(true))).unwrap();
+        for _ in 0 .. 2 {
+            assert_eq!(vfs.read_as_string(Path::from_str_preverified("/asset"))
+                       .unwrap(), "read me");
+        }
+        std::fs::remove_file(dir.join("asset")).unwrap();
+        assert_eq!(vfs.read_as_string(Path::from_str_preverified("/asset"))
+                   .unwrap(), "backup");
+        assert_eq!(vfs.open(Path::from_str_preverified("/missing"))
+                   .err().unwrap().kind(), io::ErrorKind::NotFound);
+        match &vfs.to_specs().unwrap()[0] {
+            (_, MountSpec::Fs { noatime: true, .. }) => (),
+            x => panic!("unexpected spec: {:?}", x),
+        }
+    }
+    /// Round-trips a VFS with both an `fs` and a `rom` source through
+    /// `MountSpec`s.
+    #[cfg(feature = "rom")]
+    #[test] fn specs_round_trip() {
+        const ROM: &[(&Path, &[u8])] = &[
+            (Path::from_str_preverified("/splash.png"), b"not really a png"),
+        ];
+        let dir = scratch_dir("specs_round_trip");
+        std::fs::write(dir.join("settings"), b"volume=11").unwrap();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(crate::rom::Source::new(ROM))).unwrap();
+        vfs.mount(PathBuf::from_str("/config/"),
+                  Box::new(Source::new(dir.clone(), false).unwrap())).unwrap();
+        let specs = vfs.to_specs().unwrap();
+        assert_eq!(specs.len(), 2);
+        match &specs[1] {
+            (point, MountSpec::Fs { base, read_only: false, .. })
+                if point.as_str() == "/config/" && *base == dir => (),
+            x => panic!("unexpected spec: {:?}", x),
+        }
+        let restored = VFS::from_specs(specs).unwrap();
+        assert_eq!(restored.read_as_string(&Path::from_str("/splash.png"))
+                   .unwrap(), "not really a png");
+        assert_eq!(restored.read_as_string(&Path::from_str("/config/settings"))
+                   .unwrap(), "volume=11");
+    }
+    /// One file in a transaction can't be written, so none of them change.
+    /// Then they all do.
+    #[test] fn transaction() {
+        let dir = scratch_dir("transaction");
+        for name in ["a", "b", "c"] {
+            std::fs::write(dir.join(name), b"old").unwrap();
+        }
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(Source::new(dir.clone(), false).unwrap())).unwrap();
+        let mut transaction = vfs.transaction();
+        transaction.update(Path::from_str_preverified("/a"), b"new");
+        transaction.update(Path::from_str_preverified("/b"), b"new");
+        transaction.update(Path::from_str_preverified("/missing/c"), b"new");
+        assert_eq!(transaction.commit().unwrap_err().kind(),
+                   io::ErrorKind::NotFound);
+        let mut leftovers = std::fs::read_dir(&dir).unwrap()
+            .map(|x| x.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        leftovers.sort();
+        assert_eq!(leftovers, &["a", "b", "c"]);
+        for name in ["a", "b", "c"] {
+            assert_eq!(std::fs::read(dir.join(name)).unwrap(), b"old");
+        }
+        let mut transaction = vfs.transaction();
+        transaction.update(Path::from_str_preverified("/a"), b"new");
+        transaction.update(Path::from_str_preverified("/b"), b"new");
+        transaction.update(Path::from_str_preverified("/c"), b"newer");
+        transaction.update(Path::from_str_preverified("/c"), b"newest");
+        transaction.commit().unwrap();
+        assert_eq!(std::fs::read(dir.join("a")).unwrap(), b"new");
+        assert_eq!(std::fs::read(dir.join("b")).unwrap(), b"new");
+        assert_eq!(std::fs::read(dir.join("c")).unwrap(), b"newest");
+        assert_eq!(std::fs::read(dir.join("c~")).unwrap(), b"old");
+    }
+    /// One name on disk in each form. Each mode finds only the name in its
+    /// own form, and lists both in normal form D. (Not on macOS, where the
+    /// filesystem does its own normalization and finds both either way.)
+    #[cfg(not(target_os = "macos"))]
+    #[test] fn normalization_modes() {
+        const CAFE_NFC: &str = "caf\u{E9}";
+        const NAIVE_NFD: &str = "nai\u{308}ve";
+        const EXPECTATIONS: &[(NormalizationMode, &str, bool)] = &[
+            (NormalizationMode::Nfd, "/caf\u{E9}", false),
+            (NormalizationMode::Nfd, "/nai\u{308}ve", true),
+            (NormalizationMode::Nfc, "/caf\u{E9}", true),
+            (NormalizationMode::Nfc, "/nai\u{308}ve", false),
+        ];
+        let dir = scratch_dir("normalization_modes");
+        std::fs::write(dir.join(CAFE_NFC), b"coffee").unwrap();
+        std::fs::write(dir.join(NAIVE_NFD), b"innocent").unwrap();
+        for &(mode, path, should_find) in EXPECTATIONS.iter() {
+            let source = Source::new(dir.clone(), true).unwrap()
+                .with_normalization(mode);
+            let path = Path::from_str(path);
+            assert_eq!(source.open(&path).is_ok(), should_find,
+                       "{:?} mode, {:?}", mode, path);
+            assert_eq!(source.ls(Path::from_str_preverified("/")).unwrap(),
+                       &[PathBuf::from_str("cafe\u{301}"),
+                         PathBuf::from_str(NAIVE_NFD)],
+                       "{:?} mode", mode);
+        }
+        let source = Source::new(dir.clone(), false).unwrap()
+            .with_normalization(NormalizationMode::Nfc);
+        source.update(&Path::from_str("/caf\u{E9}"), b"tea").unwrap();
+        assert_eq!(std::fs::read(dir.join(CAFE_NFC)).unwrap(), b"tea");
+    }
+    #[cfg(feature = "rom")]
+    #[test] fn capabilities() {
+        const READ_LIST: Capabilities
+            = Capabilities::READ.union(Capabilities::LIST);
+        const ALL_THREE: Capabilities = READ_LIST.union(Capabilities::WRITE);
+        const EXPECTATIONS: &[(&str, Capabilities)] = &[
+            ("/", READ_LIST),
+            ("/splash.png", READ_LIST),
+            ("/config/", ALL_THREE),
+            ("/config/settings", ALL_THREE),
+            ("/shipped/", READ_LIST),
+            ("/shipped/defaults", READ_LIST),
+        ];
+        let dir = scratch_dir("capabilities");
+        let vfs = VFS::new();
+        assert!(vfs.capabilities_at(Path::from_str_preverified("/"))
+                .is_empty());
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(crate::rom::Source::new(&[]))).unwrap();
+        vfs.mount(PathBuf::from_str("/config/"),
+                  Box::new(Source::new(dir.clone(), false).unwrap())).unwrap();
+        vfs.mount(PathBuf::from_str("/shipped/"),
+                  Box::new(Source::new(dir, true).unwrap())).unwrap();
+        for &(path, expected) in EXPECTATIONS.iter() {
+            let got = vfs.capabilities_at(Path::from_str_preverified(path));
+            assert_eq!(got, expected, "{:?}", path);
+        }
+        let caps = vfs.capabilities_at(Path::from_str_preverified("/config/"));
+        assert!(caps.contains(Capabilities::WRITE | Capabilities::READ));
+        assert!(!caps.contains(Capabilities::WRITE | Capabilities::DELETE));
+        assert_eq!(caps & Capabilities::WATCH, Capabilities::empty());
+    }
+    #[test] fn specs_of_custom_source() {
+        struct Custom;
+        impl VFSSource for Custom {
+            fn open(&self, _: &Path) -> io::Result<Box<dyn DataFile>> {
+                Err(io::Error::from(io::ErrorKind::NotFound))
+            }
+            fn ls(&self, _: &Path) -> io::Result<Vec<PathBuf>> {
+                Err(io::Error::from(io::ErrorKind::NotFound))
+            }
+            fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+                Err(io::Error::from(io::ErrorKind::ReadOnlyFilesystem))
+            }
+        }
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(Custom)).unwrap();
+        assert_eq!(vfs.to_specs().unwrap_err().kind(),
+                   io::ErrorKind::Unsupported);
     }
 }
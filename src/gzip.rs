@@ -0,0 +1,149 @@
+use crate::*;
+
+use std::io::{self, Cursor, ErrorKind, Read, Write};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+
+/// Wraps another source, transparently decompressing gzipped files. A file
+/// stored as `/foo.json.gz` in the wrapped source appears as `/foo.json`.
+///
+/// Files that aren't gzipped still come through unchanged. If both
+/// `/foo.json` and `/foo.json.gz` exist, the gzipped one wins.
+///
+/// A gzip stream can't be seeked in, so a gzipped file is decompressed into
+/// memory in its entirety when it's opened. A small file can decompress to
+/// a very large one, so if the files come from somewhere you don't trust, use
+/// `with_max_decompressed_bytes` to put a limit on that.
+pub struct Source {
+    inner: Box<dyn VFSSource>,
+    max_decompressed_bytes: Option<u64>,
+}
+
+impl Source {
+    pub fn new(inner: Box<dyn VFSSource>) -> Source {
+        Source { inner, max_decompressed_bytes: None }
+    }
+    /// Limits how large a gzipped file may be once decompressed. Opening one
+    /// that would be larger fails with `InvalidData`, having decompressed no
+    /// more than one byte past the limit. Files that aren't gzipped aren't
+    /// affected. The default is no limit.
+    pub fn with_max_decompressed_bytes(mut self, limit: u64) -> Source {
+        self.max_decompressed_bytes = Some(limit);
+        self
+    }
+}
+
+/// Returns the path of the gzipped version of the given file.
+fn gz_path(path: &Path) -> io::Result<PathBuf> {
+    let mut ret = path.to_owned();
+    ret.append_extension("gz")
+        .map_err(|x| io::Error::new(ErrorKind::InvalidInput, x))?;
+    Ok(ret)
+}
+
+impl VFSSource for Source {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        let mut compressed = match self.inner.open(&gz_path(path)?) {
+            Err(x) if x.kind() == ErrorKind::NotFound
+                || x.kind() == ErrorKind::IsADirectory
+                => return self.inner.open(path),
+            x => x?,
+        };
+        let mut buf = vec![];
+        let mut decoder = GzDecoder::new(&mut compressed);
+        match self.max_decompressed_bytes {
+            None => { decoder.read_to_end(&mut buf)?; },
+            Some(limit) => {
+                decoder.take(limit.saturating_add(1)).read_to_end(&mut buf)?;
+                if buf.len() as u64 > limit {
+                    return Err(io::Error::new(ErrorKind::InvalidData,
+                                              "gzipped file is larger than \
+                                               the decompression limit"))
+                }
+            },
+        }
+        Ok(Box::new(Cursor::new(buf)))
+    }
+    fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut ret = self.inner.ls(path)?;
+        for entry in ret.iter_mut() {
+            if entry.is_directory() { continue }
+            if let Some(stripped) = entry.as_str().strip_suffix(".gz") {
+                // "foo.gz" becomes "foo", ".gz" stays ".gz"
+                if !stripped.is_empty() {
+                    *entry = PathBuf::from_str(stripped);
+                }
+            }
+        }
+        ret.sort_by(|a, b| a.dir_aware_cmp(b));
+        ret.dedup();
+        Ok(ret)
+    }
+    fn dir_exists(&self, dir: &Path) -> io::Result<bool> {
+        self.inner.dir_exists(dir)
+    }
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        Ok(self.inner.exists(&gz_path(path)?)? || self.inner.exists(path)?)
+    }
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+    /// Always writes the gzipped version of the file, since that's the one
+    /// `open` will find.
+    fn update(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data)?;
+        self.inner.update(&gz_path(path)?, &encoder.finish()?)
+    }
+    fn flush(&self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(all(test, feature = "rom"))]
+mod test {
+    use super::*;
+    const fn fsp(i: &str) -> &Path { Path::from_str_preverified(i) }
+    #[test] fn gzip() {
+        const TEXT: &[u8] = br#"{"volume": 11, "subtitles": true}"#;
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(TEXT).unwrap();
+        let compressed: &'static [u8] = encoder.finish().unwrap().leak();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(Source::new(Box::new(
+            crate::rom::Source::new(&[
+                (fsp("/config.json.gz"), compressed),
+                (fsp("/plain.txt"), b"not compressed"),
+                (fsp("/plain.txt.gz/"), b""),
+            ]))))).unwrap();
+        assert_eq!(vfs.read_as_bytes(fsp("/config.json")).unwrap(), TEXT);
+        assert_eq!(vfs.read_as_bytes(fsp("/plain.txt")).unwrap(),
+                   b"not compressed");
+        assert_eq!(vfs.ls(fsp("/")).unwrap().iter().map(|x| x.as_str())
+                   .collect::<Vec<_>>(),
+                   &["config.json", "plain.txt", "plain.txt.gz/"]);
+        assert_eq!(vfs.update(fsp("/config.json"), b"").unwrap_err().kind(),
+                   ErrorKind::ReadOnlyFilesystem);
+    }
+    #[test] fn decompression_limit() {
+        // A megabyte of zeroes squeezes down to about a kilobyte
+        let mut encoder = GzEncoder::new(vec![], Compression::best());
+        encoder.write_all(&[0; 1 << 20]).unwrap();
+        let bomb: &'static [u8] = encoder.finish().unwrap().leak();
+        let mut encoder = GzEncoder::new(vec![], Compression::best());
+        encoder.write_all(&[0; 4096]).unwrap();
+        let exact: &'static [u8] = encoder.finish().unwrap().leak();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(Source::new(Box::new(
+            crate::rom::Source::new(&[
+                (fsp("/bomb.gz"), bomb),
+                (fsp("/exact.gz"), exact),
+                (fsp("/plain"), &[0; 8192]),
+            ]))).with_max_decompressed_bytes(4096))).unwrap();
+        assert_eq!(vfs.open(fsp("/bomb")).err().unwrap().kind(),
+                   ErrorKind::InvalidData);
+        assert_eq!(vfs.read_as_bytes(fsp("/exact")).unwrap().len(), 4096);
+        assert_eq!(vfs.read_as_bytes(fsp("/plain")).unwrap().len(), 8192);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::*;
+
+use std::io::{self, Cursor, ErrorKind};
+use once_cell::sync::OnceCell;
+use reqwest::{StatusCode, blocking::Client};
+
+/// The index file, relative to the base URL.
+const INDEX_NAME: &str = "index.txt";
+
+/// A read-only source that fetches files over HTTP(S), relative to a base URL.
+/// Each file is downloaded in its entirety when it's opened.
+///
+/// HTTP has no way to list a directory, so the server must provide an index:
+/// a plain text file named `index.txt`, directly under the base URL, giving
+/// the absolute path of one file per line. (Directories are implied by the
+/// files in them. Blank lines are ignored.) The index is fetched the first
+/// time it's needed, and kept. Only `ls` needs it; files can be opened
+/// without one.
+pub struct Source {
+    base: String,
+    client: Client,
+    index: OnceCell<Vec<PathBuf>>,
+}
+
+impl Source {
+    /// Creates a source fetching files from under the given base URL. A
+    /// trailing `/` is added to the URL if it doesn't have one.
+    pub fn new(base: &str) -> io::Result<Source> {
+        let mut base = base.to_string();
+        if !base.ends_with('/') { base.push('/') }
+        let client = Client::builder().build().map_err(io::Error::other)?;
+        Ok(Source { base, client, index: OnceCell::new() })
+    }
+    /// Returns the URL of the file at the given path, relative to the base.
+    fn url(&self, relative: &str) -> String {
+        let mut ret = self.base.clone();
+        ret.push_str(&Path::from_str_preverified(relative).to_url_path());
+        ret
+    }
+    /// Fetches the file at the given path, relative to the base.
+    fn get(&self, relative: &str) -> io::Result<Vec<u8>> {
+        let url = self.url(relative);
+        let response = self.client.get(&url).send()
+            .map_err(io::Error::other)?;
+        match response.status() {
+            x if x.is_success() => (),
+            StatusCode::NOT_FOUND | StatusCode::GONE
+                => return Err(io::Error::from(ErrorKind::NotFound)),
+            StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED
+                => return Err(io::Error::from(ErrorKind::PermissionDenied)),
+            x => return Err(io::Error::other(format!("{}: HTTP {}", url, x))),
+        }
+        Ok(response.bytes().map_err(io::Error::other)?.to_vec())
+    }
+    fn index(&self) -> io::Result<&[PathBuf]> {
+        self.index.get_or_try_init(|| {
+            let index = String::from_utf8(self.get(INDEX_NAME)?)
+                .map_err(|x| io::Error::new(ErrorKind::InvalidData, x))?;
+            let mut ret = vec![];
+            for line in index.lines().map(str::trim) {
+                if line.is_empty() { continue }
+                let path = PathBuf::try_from_str(line).map_err(|x| {
+                    io::Error::new(ErrorKind::InvalidData,
+                                   format!("{:?}: {}", line, x))
+                })?;
+                if !path.is_absolute() || path.is_directory() {
+                    return Err(io::Error::new(ErrorKind::InvalidData,
+                                              format!("{:?}: not an absolute \
+                                                       path to a file", line)))
+                }
+                ret.push(path);
+            }
+            ret.sort();
+            Ok(ret)
+        }).map(Vec::as_slice)
+    }
+}
+
+impl VFSSource for Source {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        Ok(Box::new(Cursor::new(self.get(&path.as_str()[1..])?)))
+    }
+    fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        debug_assert!(path.is_absolute() && path.is_directory());
+        let mut ret: Vec<PathBuf> = vec![];
+        for file in self.index()? {
+            let rest = match file.as_str().strip_prefix(path.as_str()) {
+                Some(x) => x,
+                None => continue,
+            };
+            let name = match rest.find('/') {
+                Some(i) => &rest[..=i],
+                None => rest,
+            };
+            ret.push(PathBuf::from_str(name));
+        }
+        if ret.is_empty() && path.as_str() != "/" {
+            return Err(io::Error::from(ErrorKind::NotFound))
+        }
+        ret.sort_by(|a, b| a.dir_aware_cmp(b));
+        ret.dedup();
+        Ok(ret)
+    }
+    fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+        Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::READ | Capabilities::LIST
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+    const fn fsp(i: &str) -> &Path { Path::from_str_preverified(i) }
+    /// Serves the given files, forever, from a background thread. Returns
+    /// the base URL.
+    fn mock_server(files: &'static [(&'static str, &'static [u8])])
+                   -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}/dlc/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&mut stream);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() { break }
+                }
+                let target = request.split(' ').nth(1).unwrap_or("");
+                let (status, body): (&str, &[u8])
+                    = match files.iter().find(|(x, _)| *x == target) {
+                        Some((_, body)) => ("200 OK", body),
+                        None => ("404 Not Found", b""),
+                    };
+                write!(stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\n\
+                                Connection: close\r\n\r\n",
+                       status, body.len()).unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+        base
+    }
+    #[test] fn http() {
+        const FILES: &[(&str, &[u8])] = &[
+            ("/dlc/index.txt", b"/maps/bonus.map\n/maps/extra/1.map\n\n\
+                                 /readme\n"),
+            ("/dlc/maps/bonus.map", b"bonus level"),
+            ("/dlc/maps/extra/1.map", b"extra level"),
+            ("/dlc/readme", b"thanks for buying"),
+            ("/dlc/with%20a%20space%23", b"escaped"),
+        ];
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/dlc/"),
+                  Box::new(Source::new(&mock_server(FILES)).unwrap()))
+            .unwrap();
+        assert_eq!(vfs.read_as_string(fsp("/dlc/maps/bonus.map")).unwrap(),
+                   "bonus level");
+        assert_eq!(vfs.read_as_string(fsp("/dlc/with a space#")).unwrap(),
+                   "escaped");
+        assert_eq!(vfs.open(fsp("/dlc/nope")).err().unwrap().kind(),
+                   ErrorKind::NotFound);
+        assert_eq!(vfs.ls(fsp("/dlc/")).unwrap(),
+                   &[PathBuf::from_str("maps/"), PathBuf::from_str("readme")]);
+        assert_eq!(vfs.ls(fsp("/dlc/maps/")).unwrap(),
+                   &[PathBuf::from_str("bonus.map"),
+                     PathBuf::from_str("extra/")]);
+        assert_eq!(vfs.ls(fsp("/dlc/nope/")).unwrap_err().kind(),
+                   ErrorKind::NotFound);
+        assert_eq!(vfs.update(fsp("/dlc/readme"), b"").unwrap_err().kind(),
+                   ErrorKind::ReadOnlyFilesystem);
+    }
+}
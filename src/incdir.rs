@@ -0,0 +1,131 @@
+use crate::*;
+
+use std::io::{self, Cursor, ErrorKind};
+use include_dir::{Dir, DirEntry};
+use log::warn;
+
+/// A read-only source serving the contents of a directory embedded with the
+/// `include_dir` crate's `include_dir!` macro.
+///
+/// Names are checked against Psilo-VFS's path rules. An entry whose name
+/// doesn't pass is skipped. Each one is warned about once, when the source
+/// is created.
+#[derive(Clone,Copy,Debug)]
+pub struct Source {
+    root: &'static Dir<'static>,
+}
+
+impl Source {
+    pub fn new(root: &'static Dir<'static>) -> Source {
+        warn_invalid_names(root);
+        Source { root }
+    }
+}
+
+/// Warns about every entry in the given directory, at any depth, that
+/// `entry_name` will skip.
+fn warn_invalid_names(dir: &Dir<'static>) {
+    for entry in dir.entries() {
+        if entry_name(entry).is_none() {
+            warn!("skipping embedded file with invalid name: {:?}",
+                  entry.path());
+        }
+        else if let Some(dir) = entry.as_dir() {
+            warn_invalid_names(dir)
+        }
+    }
+}
+
+/// Returns the Psilo-VFS name of the given entry, or `None` if it doesn't
+/// have a valid one.
+fn entry_name(entry: &DirEntry<'static>) -> Option<PathBuf> {
+    let raw = entry.path().file_name().and_then(|x| x.to_str());
+    raw.and_then(|x| Path::try_from_str(x).ok())
+        .filter(|x| !x.is_empty() && x.components().count() == 1
+                && x.as_str() != "..")
+        .map(|x| x.into_owned())
+}
+
+impl Source {
+    /// Finds the entry at the given absolute path. Returns `None` for the
+    /// root directory, which has no entry.
+    fn resolve(&self, path: &Path)
+               -> io::Result<Option<&'static DirEntry<'static>>> {
+        let mut dir = Some(self.root);
+        let mut ret = None;
+        for component in path.components() {
+            // (a file has no entries, so `dir` is `None` after one)
+            let entry = dir.and_then(|dir| dir.entries().iter().find(|x| {
+                entry_name(x).as_deref().map(Path::as_str)
+                    == Some(component.as_str())
+            })).ok_or_else(|| io::Error::from(ErrorKind::NotFound))?;
+            dir = entry.as_dir();
+            ret = Some(entry);
+        }
+        Ok(ret)
+    }
+}
+
+impl VFSSource for Source {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        match self.resolve(path)? {
+            Some(DirEntry::File(file))
+                => Ok(Box::new(Cursor::new(file.contents()))),
+            _ => Err(io::Error::from(ErrorKind::IsADirectory)),
+        }
+    }
+    fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        debug_assert!(path.is_absolute() && path.is_directory());
+        let dir = match self.resolve(path)? {
+            None => self.root,
+            Some(DirEntry::Dir(dir)) => dir,
+            Some(DirEntry::File(_))
+                => return Err(io::Error::from(ErrorKind::NotADirectory)),
+        };
+        let mut ret: Vec<PathBuf> = dir.entries().iter().filter_map(|entry| {
+            let mut name = entry_name(entry)?;
+            if let DirEntry::Dir(_) = entry { name.make_file_into_dir() }
+            Some(name)
+        }).collect();
+        ret.sort_by(|a, b| a.dir_aware_cmp(b));
+        Ok(ret)
+    }
+    fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+        Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::READ | Capabilities::LIST
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use include_dir::include_dir;
+    const fn fsp(i: &str) -> &Path { Path::from_str_preverified(i) }
+    static EMBEDDED: Dir<'static>
+        = include_dir!("$CARGO_MANIFEST_DIR/testdata/embedded");
+    #[test] fn embedded() {
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/assets/"),
+                  Box::new(Source::new(&EMBEDDED))).unwrap();
+        assert_eq!(vfs.read_as_string(fsp("/assets/readme.txt")).unwrap(),
+                   "Hello from an embedded file.\n");
+        assert_eq!(vfs.read_as_string(fsp("/assets/levels/one.map")).unwrap(),
+                   "level one");
+        assert_eq!(vfs.ls(fsp("/assets/")).unwrap(),
+                   &[PathBuf::from_str("levels/"),
+                     PathBuf::from_str("readme.txt")]);
+        assert_eq!(vfs.ls(fsp("/assets/levels/")).unwrap(),
+                   &[PathBuf::from_str("one.map")]);
+        assert_eq!(vfs.open(fsp("/assets/nope")).err().unwrap().kind(),
+                   ErrorKind::NotFound);
+        assert_eq!(vfs.open(fsp("/assets/levels")).err().unwrap().kind(),
+                   ErrorKind::IsADirectory);
+        assert_eq!(vfs.open(fsp("/assets/readme.txt/x")).err().unwrap()
+                   .kind(), ErrorKind::NotFound);
+        assert_eq!(vfs.ls(fsp("/assets/readme.txt/")).unwrap_err().kind(),
+                   ErrorKind::NotADirectory);
+    }
+}
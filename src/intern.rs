@@ -0,0 +1,173 @@
+use crate::*;
+
+use std::collections::HashMap;
+
+/// A path stored in a [`PathInterner`](struct.PathInterner.html). Only
+/// meaningful to the interner that handed it out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InternedPath(u32);
+
+/// The empty (relative) path.
+const RELATIVE_ROOT: InternedPath = InternedPath(0);
+/// The absolute path `/`.
+const ABSOLUTE_ROOT: InternedPath = InternedPath(1);
+
+/// Stores a large number of paths compactly, for tools that need to keep
+/// hundreds of thousands of them around. Each path is stored as its last
+/// component plus the path of its parent directory, so prefixes shared by many
+/// paths are only stored once. Each distinct component name is stored once,
+/// too.
+///
+/// A path goes in with [`intern`](#method.intern), which gives back a small
+/// `InternedPath` id, and comes back out with
+/// [`to_path_buf`](#method.to_path_buf).
+pub struct PathInterner {
+    /// Component names. Directory components include their trailing `/`.
+    names: Vec<Box<str>>,
+    name_ids: HashMap<Box<str>, u32>,
+    /// For each interned path: the parent, and the index of the last component
+    /// in `names`.
+    nodes: Vec<(InternedPath, u32)>,
+    node_ids: HashMap<(InternedPath, u32), InternedPath>,
+}
+
+impl PathInterner {
+    pub fn new() -> PathInterner {
+        let mut ret = PathInterner {
+            names: vec![], name_ids: HashMap::new(),
+            nodes: vec![], node_ids: HashMap::new(),
+        };
+        // The two roots. They are their own parents, and are never looked up
+        // in `node_ids`.
+        let empty = ret.name_id("");
+        let slash = ret.name_id("/");
+        ret.nodes.push((RELATIVE_ROOT, empty));
+        ret.nodes.push((ABSOLUTE_ROOT, slash));
+        ret
+    }
+    fn name_id(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.name_ids.get(name) { return id }
+        let id = self.names.len() as u32;
+        self.names.push(name.into());
+        self.name_ids.insert(name.into(), id);
+        id
+    }
+    /// Calls `f` with each component of the path, in order, with a `/`
+    /// appended to each component that names a directory.
+    fn each_name(path: &Path, mut f: impl FnMut(&str)) {
+        let mut name = String::new();
+        let mut components = path.components().peekable();
+        while let Some(component) = components.next() {
+            name.clear();
+            name.push_str(component.as_str());
+            if components.peek().is_some() || path.is_directory() {
+                name.push('/');
+            }
+            f(&name);
+        }
+    }
+    fn root_of(path: &Path) -> InternedPath {
+        if path.is_absolute() { ABSOLUTE_ROOT } else { RELATIVE_ROOT }
+    }
+    /// Stores the given path (and all its ancestors), if it isn't already
+    /// stored, and returns its id.
+    pub fn intern(&mut self, path: &Path) -> InternedPath {
+        let mut cur = PathInterner::root_of(path);
+        PathInterner::each_name(path, |name| {
+            let name = self.name_id(name);
+            let next = InternedPath(self.nodes.len() as u32);
+            cur = *self.node_ids.entry((cur, name)).or_insert_with(|| {
+                self.nodes.push((cur, name));
+                next
+            });
+        });
+        cur
+    }
+    /// Returns the id of the given path, if it has been interned.
+    pub fn get(&self, path: &Path) -> Option<InternedPath> {
+        let mut cur = Some(PathInterner::root_of(path));
+        PathInterner::each_name(path, |name| {
+            cur = cur.and_then(|parent| {
+                let name = *self.name_ids.get(name)?;
+                self.node_ids.get(&(parent, name)).copied()
+            });
+        });
+        cur
+    }
+    /// Returns the id of the parent directory of the given path, or `None` if
+    /// it's `""` or `"/"`.
+    pub fn parent(&self, id: InternedPath) -> Option<InternedPath> {
+        match self.nodes[id.0 as usize].0 {
+            parent if parent == id => None,
+            parent => Some(parent),
+        }
+    }
+    /// Rebuilds the full path with the given id.
+    pub fn to_path_buf(&self, id: InternedPath) -> PathBuf {
+        let mut names = vec![];
+        let mut cur = id;
+        loop {
+            let (parent, name) = self.nodes[cur.0 as usize];
+            names.push(&*self.names[name as usize]);
+            if parent == cur { break }
+            cur = parent;
+        }
+        let s: String = names.into_iter().rev().collect();
+        // Every name came out of a valid path, so the joined one is valid too
+        Path::from_str_preverified(&s).to_owned()
+    }
+}
+
+impl Default for PathInterner {
+    fn default() -> PathInterner { PathInterner::new() }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test] fn round_trip() {
+        const PATHS: &[&str] = &[
+            "/", "", "/data/", "/data/maps/forest/01.map",
+            "/data/maps/forest/02.map", "/data/maps/desert/01.map",
+            "/data/maps/forest/", "/data/maps/forest", "../shared/01.map",
+            "readme",
+        ];
+        let mut interner = PathInterner::new();
+        let ids = PATHS.iter().map(|x| interner.intern(Path::from_str(x)
+                                                        .as_ref()))
+            .collect::<Vec<_>>();
+        for (path, id) in PATHS.iter().zip(ids.iter()) {
+            assert_eq!(interner.to_path_buf(*id).as_str(), *path);
+            assert_eq!(interner.get(Path::from_str_preverified(path)),
+                       Some(*id), "{:?} looked up wrong", path);
+            assert_eq!(interner.intern(Path::from_str_preverified(path)), *id,
+                       "{:?} interned twice", path);
+        }
+        assert_eq!(interner.get(Path::from_str_preverified("/data/sounds/")),
+                   None);
+        let forest = interner.parent(ids[3]).unwrap();
+        assert_eq!(interner.to_path_buf(forest).as_str(),
+                   "/data/maps/forest/");
+        assert_eq!(forest, ids[6]);
+        assert_eq!(interner.parent(ids[0]), None);
+        assert_eq!(interner.parent(ids[1]), None);
+    }
+    #[test] fn shared_prefixes() {
+        let mut interner = PathInterner::new();
+        let mut path = PathBuf::from_str("/");
+        for depth in 0 .. 20 {
+            path.join(Path::from_str_preverified(&format!("level{}/", depth)));
+            for leaf in 0 .. 10 {
+                let mut leaf_path = path.clone();
+                leaf_path.join(Path::from_str_preverified(&format!("leaf{}",
+                                                                   leaf)));
+                let id = interner.intern(&leaf_path);
+                assert_eq!(interner.to_path_buf(id), leaf_path);
+            }
+        }
+        // Two roots, twenty directories, two hundred files. Nothing else.
+        assert_eq!(interner.nodes.len(), 2 + 20 + 200);
+        // The names of the ten leaves are shared between directories.
+        assert_eq!(interner.names.len(), 2 + 20 + 10);
+    }
+}
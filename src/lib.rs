@@ -108,11 +108,48 @@
 pub use psilo_vfs_pathmacro::p;
 
 mod path;
-pub use path::{Path, PathBuf};
+pub use path::{Path, PathBuf, PathBuilder, PathFromStrError,
+               PathFromUrlError, PathFromUtf8Error, PathJoinError,
+               TypedComponent};
 
 mod vfs;
 pub use vfs::*;
 
+/// The text encodings that
+/// [`VFS::read_to_string_with_encoding`](struct.VFS.html#method.read_to_string_with_encoding)
+/// takes.
+#[cfg(feature = "encoding")]
+pub use encoding_rs;
+
+mod intern;
+pub use intern::{InternedPath, PathInterner};
+
+mod cwd;
+pub use cwd::VfsCwd;
+
+mod rewrite;
+pub use rewrite::{Lowercase, NameRewrite};
+
+mod subtree;
+
+mod crypt;
+pub use crypt::{Cipher, Source as CryptSource};
+
+mod cas;
+pub use cas::Source as CasSource;
+
+mod pack;
+pub use pack::Source as PackSource;
+
+mod fallback;
+pub use fallback::Source as FallbackSource;
+
+mod stats;
+pub use stats::{AccessCounts, AccessStats, Source as StatsSource};
+
+#[cfg(test)]
+mod memory;
+
 #[cfg(feature = "fs")]
 mod fs;
 #[cfg(feature = "fs")]
@@ -122,3 +159,29 @@ pub use fs::Source as FsSource;
 mod rom;
 #[cfg(feature = "rom")]
 pub use rom::Source as RomSource;
+
+#[cfg(feature = "spill")]
+mod spill;
+#[cfg(feature = "spill")]
+pub use spill::Source as SpillSource;
+
+#[cfg(feature = "gzip")]
+mod gzip;
+#[cfg(feature = "gzip")]
+pub use gzip::Source as GzipSource;
+
+#[cfg(feature = "include_dir")]
+mod incdir;
+#[cfg(feature = "include_dir")]
+pub use incdir::Source as IncludeDirSource;
+
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "http")]
+pub use http::Source as HttpSource;
+
+#[cfg(feature = "proptest")]
+mod arbitrary;
+
+#[cfg(feature = "serde")]
+mod serialize;
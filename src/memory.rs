@@ -0,0 +1,40 @@
+//! A writable source for tests, shared by the test modules of everything
+//! that needs one.
+
+use crate::*;
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Cursor},
+    sync::{Arc, Mutex},
+};
+
+/// A writable source that keeps its files in a map, which the test can keep
+/// a handle on to look at (or change) what's stored.
+pub(crate) struct MapSource(pub(crate) Arc<Mutex<BTreeMap<PathBuf, Vec<u8>>>>);
+
+impl MapSource {
+    /// Creates a source holding the given files.
+    pub(crate) fn new(files: &[(&str, &[u8])]) -> MapSource {
+        MapSource(Arc::new(Mutex::new(files.iter().map(|(path, data)| {
+            (PathBuf::from_str(path), data.to_vec())
+        }).collect())))
+    }
+}
+
+impl VFSSource for MapSource {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        let files = self.0.lock().unwrap();
+        match files.get(path) {
+            Some(x) => Ok(Box::new(Cursor::new(x.clone()))),
+            None => Err(crate::cas::not_in_index(&files, path)),
+        }
+    }
+    fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        crate::cas::ls_index(&self.0.lock().unwrap(), path)
+    }
+    fn update(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.0.lock().unwrap().insert(path.to_owned(), data.to_vec());
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+use crate::*;
+
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind, Read, Seek, SeekFrom},
+    sync::{Arc, Mutex},
+};
+
+/// A read-only source for a pack file: the data of many files, concatenated
+/// into one, along with an index giving the path, offset, and length of each
+/// one. How the index is stored is up to you; parse it however your format
+/// says to, and pass it to `new` along with the pack itself.
+///
+/// The pack can be anything that's `Read + Seek`, such as a `std::fs::File`,
+/// or a `Cursor` over a byte slice or `Vec<u8>`. It's shared by every file
+/// opened from the source, so reads from different files take turns.
+///
+/// Directories are implied by the index: a directory exists if any file in
+/// the index is inside it.
+pub struct Source<R> {
+    /// Path to offset and length.
+    index: BTreeMap<PathBuf, (u64, u64)>,
+    pack: Arc<Mutex<R>>,
+}
+
+impl<R: Read + Seek> Source<R> {
+    /// Creates a source from a pack and its index, which gives the path,
+    /// offset, and length of each file within the pack.
+    ///
+    /// Returns `InvalidInput` if a path isn't an absolute path to a file, or
+    /// appears more than once, and `InvalidData` if a file extends past the
+    /// end of the pack.
+    pub fn new<I>(mut pack: R, index: I) -> io::Result<Source<R>>
+    where I: IntoIterator<Item=(PathBuf, u64, u64)> {
+        let pack_len = pack.seek(SeekFrom::End(0))?;
+        let mut map = BTreeMap::new();
+        for (path, offset, length) in index.into_iter() {
+            if !path.is_absolute() || path.is_directory() {
+                let err = format!("pack index entry is not an absolute path \
+                                   to a file: {:?}", path);
+                return Err(io::Error::new(ErrorKind::InvalidInput, err))
+            }
+            if offset.checked_add(length).map(|end| end > pack_len)
+                .unwrap_or(true) {
+                    let err = format!("pack index entry extends past the end \
+                                       of the pack: {:?}", path);
+                    return Err(io::Error::new(ErrorKind::InvalidData, err))
+                }
+            if map.contains_key(&path) {
+                let err = format!("pack index entry is a duplicate: {:?}",
+                                  path);
+                return Err(io::Error::new(ErrorKind::InvalidInput, err))
+            }
+            map.insert(path, (offset, length));
+        }
+        Ok(Source { index: map, pack: Arc::new(Mutex::new(pack)) })
+    }
+}
+
+/// A file within a pack. Only the file's own part of the pack can be read or
+/// seeked in.
+struct PackFile<R> {
+    pack: Arc<Mutex<R>>,
+    offset: u64,
+    length: u64,
+    /// Relative to `offset`. May be past `length`, as with any file.
+    pos: u64,
+}
+
+impl<R: Read + Seek> Read for PackFile<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.length.saturating_sub(self.pos);
+        let wanted = (buf.len() as u64).min(left) as usize;
+        if wanted == 0 { return Ok(0) }
+        let mut pack = self.pack.lock().unwrap();
+        pack.seek(SeekFrom::Start(self.offset + self.pos))?;
+        let n = pack.read(&mut buf[..wanted])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R> Seek for PackFile<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(x) => Some(x),
+            SeekFrom::End(x) => self.length.checked_add_signed(x),
+            SeekFrom::Current(x) => self.pos.checked_add_signed(x),
+        };
+        match new_pos {
+            Some(x) => { self.pos = x; Ok(x) },
+            None => Err(io::Error::new(ErrorKind::InvalidInput,
+                                       "seek to a negative position")),
+        }
+    }
+}
+
+impl<R: Read + Seek> DataFile for PackFile<R> {
+    fn stream_len(&mut self) -> io::Result<u64> {
+        Ok(self.length)
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> VFSSource for Source<R> {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        match self.index.get(path) {
+            Some(&(offset, length)) => Ok(Box::new(PackFile {
+                pack: self.pack.clone(), offset, length, pos: 0,
+            })),
+            None => Err(crate::cas::not_in_index(&self.index, path)),
+        }
+    }
+    fn open_range(&self, path: &Path, start: u64, len: u64)
+                  -> io::Result<Box<dyn DataFile>> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        match self.index.get(path) {
+            Some(&(offset, length)) => {
+                let start = start.min(length);
+                Ok(Box::new(PackFile {
+                    pack: self.pack.clone(), offset: offset + start,
+                    length: len.min(length - start), pos: 0,
+                }))
+            },
+            None => Err(crate::cas::not_in_index(&self.index, path)),
+        }
+    }
+    fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        debug_assert!(path.is_absolute() && path.is_directory());
+        crate::cas::ls_index(&self.index, path)
+    }
+    fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+        Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::READ | Capabilities::LIST
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    const fn fsp(i: &str) -> &Path { Path::from_str_preverified(i) }
+    /// Builds a pack out of the given files, returning the pack and its index.
+    fn build(files: &[(&str, &[u8])]) -> (Vec<u8>, Vec<(PathBuf, u64, u64)>) {
+        let mut pack = b"HEADER".to_vec();
+        let mut index = vec![];
+        for &(path, data) in files.iter() {
+            index.push((PathBuf::from_str(path), pack.len() as u64,
+                        data.len() as u64));
+            pack.extend_from_slice(data);
+        }
+        (pack, index)
+    }
+    #[test] fn pack() {
+        let (pack, index) = build(&[
+            ("/maps/e1m1.map", b"first map"),
+            ("/maps/e1m2.map", b"second map"),
+            ("/sounds/door.wav", b"creak"),
+            ("/empty", b""),
+        ]);
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(
+            Source::new(Cursor::new(pack), index).unwrap())).unwrap();
+        assert_eq!(vfs.read_as_bytes(fsp("/maps/e1m2.map")).unwrap(),
+                   b"second map");
+        assert_eq!(vfs.read_as_bytes(fsp("/empty")).unwrap(), b"");
+        // Each file only sees its own data, even when seeking around
+        let mut a = vfs.open(fsp("/maps/e1m1.map")).unwrap();
+        let mut b = vfs.open(fsp("/sounds/door.wav")).unwrap();
+        assert_eq!(DataFile::stream_len(b.as_mut()).unwrap(), 5);
+        let mut buf = [0; 5];
+        a.seek(SeekFrom::End(-3)).unwrap();
+        b.read_exact(&mut buf[..2]).unwrap();
+        let mut rest = vec![];
+        a.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"map");
+        b.read_exact(&mut buf[2..]).unwrap();
+        assert_eq!(&buf, b"creak");
+        assert_eq!(b.read(&mut buf).unwrap(), 0);
+        b.seek(SeekFrom::Start(100)).unwrap();
+        assert_eq!(b.read(&mut buf).unwrap(), 0);
+        assert!(b.seek(SeekFrom::Current(-101)).is_err());
+        assert_eq!(vfs.open(fsp("/maps")).err().unwrap().kind(),
+                   ErrorKind::IsADirectory);
+        assert_eq!(vfs.open(fsp("/HEADER")).err().unwrap().kind(),
+                   ErrorKind::NotFound);
+        assert_eq!(vfs.ls(fsp("/")).unwrap().iter().map(|x| x.as_str())
+                   .collect::<Vec<_>>(), &["empty", "maps/", "sounds/"]);
+        assert_eq!(vfs.ls(fsp("/maps/")).unwrap().iter().map(|x| x.as_str())
+                   .collect::<Vec<_>>(), &["e1m1.map", "e1m2.map"]);
+        assert_eq!(vfs.ls(fsp("/empty/")).unwrap_err().kind(),
+                   ErrorKind::NotADirectory);
+    }
+    /// Counts how many bytes are read from the pack.
+    struct CountingPack(Cursor<Vec<u8>>, Arc<Mutex<usize>>);
+    impl Read for CountingPack {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.read(buf)?;
+            *self.1.lock().unwrap() += n;
+            Ok(n)
+        }
+    }
+    impl Seek for CountingPack {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+    #[test] fn range() {
+        let big = vec![b'x'; 100000];
+        let (mut pack, index) = build(&[("/small", b"0123456789"),
+                                        ("/big", &big)]);
+        pack[16 + 50000 .. 16 + 50005].copy_from_slice(b"hello");
+        let count = Arc::new(Mutex::new(0));
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(
+            Source::new(CountingPack(Cursor::new(pack), count.clone()), index)
+                .unwrap())).unwrap();
+        assert_eq!(vfs.read_range(fsp("/big"), 50000, 5).unwrap(), b"hello");
+        assert_eq!(*count.lock().unwrap(), 5);
+        assert_eq!(vfs.read_range(fsp("/small"), 3, 4).unwrap(), b"3456");
+        assert_eq!(vfs.read_range(fsp("/small"), 8, 100).unwrap(), b"89");
+        assert_eq!(vfs.read_range(fsp("/small"), 100, 1).unwrap(), b"");
+        let mut file = vfs.open_range(fsp("/small"), 2, 5).unwrap();
+        assert_eq!(DataFile::stream_len(file.as_mut()).unwrap(), 5);
+        file.seek(SeekFrom::End(-1)).unwrap();
+        let mut rest = vec![];
+        file.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"6");
+        assert_eq!(vfs.read_range(fsp("/nope"), 0, 1).unwrap_err().kind(),
+                   ErrorKind::NotFound);
+    }
+    #[test] fn bad_index() {
+        let (pack, mut index) = build(&[("/a", b"data")]);
+        index[0].2 += 1;
+        assert_eq!(Source::new(Cursor::new(&pack[..]), index.clone())
+                   .err().unwrap().kind(), ErrorKind::InvalidData);
+        index[0].2 -= 1;
+        index.push(index[0].clone());
+        assert_eq!(Source::new(Cursor::new(&pack[..]), index)
+                   .err().unwrap().kind(), ErrorKind::InvalidInput);
+        assert_eq!(Source::new(Cursor::new(&pack[..]),
+                               [(PathBuf::from_str("/dir/"), 0, 0)])
+                   .err().unwrap().kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -1,16 +1,18 @@
 use std::{
     borrow::{Borrow, Cow},
+    cmp::Ordering,
     error::Error,
     fmt::{Debug, Display, Formatter},
     hash::{Hash, Hasher},
+    io,
     ops::Deref,
     str,
 };
 use once_cell::sync::Lazy;
 use regex::Regex;
 use unicode_normalization::{
-    IsNormalized,
-    char::decompose_canonical,
+    IsNormalized, UnicodeNormalization,
+    char::is_combining_mark,
     is_nfd_quick,
 };
 
@@ -31,6 +33,18 @@ pub enum PathFromStrError {
     EscapedRoot,
     /// A path ended with ".." (as opposed to "../")
     DotDotFile,
+    /// A component was longer than the limit passed to
+    /// [`try_from_str_bounded`](struct.Path.html#method.try_from_str_bounded).
+    ComponentTooLong,
+    /// The whole path was longer than the limit passed to
+    /// [`try_from_str_bounded`](struct.Path.html#method.try_from_str_bounded).
+    PathTooLong,
+    /// The path started with more `..`s than the limit passed to
+    /// [`try_from_str_max_escape`](struct.Path.html#method.try_from_str_max_escape).
+    TooManyParents,
+    /// The path looked like a Windows path (`C:\foo` or `\\server\share`),
+    /// rather than a Psilo-VFS path.
+    LooksLikeOsPath,
 }
 
 impl Display for PathFromStrError {
@@ -51,12 +65,30 @@ impl Display for PathFromStrError {
 				\"..\")"),
 	    PathFromStrError::DotDotFile
 		=> write!(fmt, "path ended with \"..\" (instead of \"../\")"),
+	    PathFromStrError::ComponentTooLong
+		=> write!(fmt, "some component of path was too long"),
+	    PathFromStrError::PathTooLong
+		=> write!(fmt, "path was too long"),
+	    PathFromStrError::TooManyParents
+		=> write!(fmt, "path started with too many \"..\""),
+	    PathFromStrError::LooksLikeOsPath
+		=> write!(fmt, "path looks like a Windows path; Psilo-VFS \
+				paths are virtual, separated by \"/\", and \
+				absolute paths start with \"/\""),
 	}
     }
 }
 
 impl Error for PathFromStrError {}
 
+/// Makes a `PathFromStrError` into an `InvalidInput` error, so that `?` can
+/// pass it along from a function that returns `io::Result`.
+impl From<PathFromStrError> for io::Error {
+    fn from(x: PathFromStrError) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidInput, x)
+    }
+}
+
 #[derive(Debug,PartialEq,Eq)]
 pub enum PathJoinError {
     /// You called `join` on a path that wasn't a directory.
@@ -85,6 +117,84 @@ impl Display for PathJoinError {
 
 impl Error for PathJoinError {}
 
+/// Makes a `PathJoinError` into an `InvalidInput` error, so that `?` can
+/// pass it along from a function that returns `io::Result`.
+impl From<PathJoinError> for io::Error {
+    fn from(x: PathJoinError) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidInput, x)
+    }
+}
+
+/// An error from
+/// [`PathBuf::try_from_utf8`](struct.PathBuf.html#method.try_from_utf8).
+#[derive(Debug,PartialEq,Eq)]
+pub enum PathFromUtf8Error {
+    /// The bytes weren't valid UTF-8.
+    NotUtf8(std::str::Utf8Error),
+    /// The bytes were valid UTF-8, but not a valid path.
+    InvalidPath(PathFromStrError),
+}
+
+impl Display for PathFromUtf8Error {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+	match self {
+	    PathFromUtf8Error::NotUtf8(x)
+		=> write!(fmt, "path was not UTF-8: {}", x),
+	    PathFromUtf8Error::InvalidPath(x) => Display::fmt(x, fmt),
+	}
+    }
+}
+
+impl Error for PathFromUtf8Error {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+	match self {
+	    PathFromUtf8Error::NotUtf8(x) => Some(x),
+	    PathFromUtf8Error::InvalidPath(x) => Some(x),
+	}
+    }
+}
+
+/// An error from
+/// [`PathBuf::from_url_path`](struct.PathBuf.html#method.from_url_path).
+#[derive(Debug,PartialEq,Eq)]
+pub enum PathFromUrlError {
+    /// A `%` wasn't followed by two hex digits.
+    BadEscape,
+    /// The decoded bytes weren't valid UTF-8.
+    NotUtf8(std::str::Utf8Error),
+    /// The decoded path wasn't valid. An escaped `/` (`%2F`) counts as an
+    /// invalid character, rather than a separator.
+    InvalidPath(PathFromStrError),
+}
+
+impl Display for PathFromUrlError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+	match self {
+	    PathFromUrlError::BadEscape
+		=> write!(fmt, "bad percent escape in URL path"),
+	    PathFromUrlError::NotUtf8(x)
+		=> write!(fmt, "URL path was not UTF-8: {}", x),
+	    PathFromUrlError::InvalidPath(x) => Display::fmt(x, fmt),
+	}
+    }
+}
+
+impl Error for PathFromUrlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+	match self {
+	    PathFromUrlError::BadEscape => None,
+	    PathFromUrlError::NotUtf8(x) => Some(x),
+	    PathFromUrlError::InvalidPath(x) => Some(x),
+	}
+    }
+}
+
+/// A drive letter path (`C:\foo`, `C:/foo`, or just `C:`) or a UNC path
+/// (`\\server\share`).
+static OS_PATH_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^(?:[A-Za-z]:(?:[\\/]|$)|\\\\)"#)
+	.unwrap()
+});
 static INVALID_PATH_PREFIX_CHAR_PATTERN: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r#"^\."#)
 	.unwrap()
@@ -93,15 +203,65 @@ static INVALID_PATH_SUFFIX_CHAR_PATTERN: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r#"[. ~^!]$"#)
 	.unwrap()
 });
+/// The same as above, minus the characters reserved for the backup and
+/// intermediate file conventions. See `Path::try_from_str_permissive`.
+static PERMISSIVE_INVALID_PATH_SUFFIX_CHAR_PATTERN: Lazy<Regex>
+    = Lazy::new(|| {
+	Regex::new(r#"[. ]$"#)
+	    .unwrap()
+    });
 static INVALID_PATH_CHAR_PATTERN: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r#"[\x00-\x1F\u{0080}-\u{009F}"*/:?\\<>|]"#)
 	.unwrap()
 });
+/// The same as above, minus the glob metacharacters `*` and `?`. See
+/// `Path::try_glob_from_str`.
+static GLOB_INVALID_PATH_CHAR_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"[\x00-\x1F\u{0080}-\u{009F}"/:\\<>|]"#)
+	.unwrap()
+});
 static INVALID_PATH_NAME_PATTERN: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r#"^(?i:AUX|CO(?:M[1-9]|N)|LPT[1-9]|NUL|PRN)(?:\.|$)"#)
+    // (`¹`, `²`, and `³` count as digits here, `⁴` and up don't)
+    Regex::new(r#"(?x)
+	^(?i: AUX | CLOCK\$ | CON | CONIN\$ | CONOUT\$ | NUL | PRN
+	      | (?:COM|LPT)[1-9\x{B9}\x{B2}\x{B3}] )
+	(?:\.|$)"#)
 	.unwrap()
 });
 
+/// Checks a single component, which must not be "." or "..", against the
+/// rules given in the [`Path`](struct.Path.html) documentation.
+fn check_component(component: &str) -> Result<(), PathFromStrError> {
+    check_component_with(component, false, false)
+}
+
+/// As `check_component`, but if `permissive` is true, allows components that
+/// end with `!`, `^`, or `~`, and if `glob` is true, allows `*` and `?`.
+fn check_component_with(component: &str, permissive: bool, glob: bool)
+			-> Result<(), PathFromStrError> {
+    let suffix_pattern = if permissive {
+	&PERMISSIVE_INVALID_PATH_SUFFIX_CHAR_PATTERN
+    } else { &INVALID_PATH_SUFFIX_CHAR_PATTERN };
+    let char_pattern = if glob {
+	&GLOB_INVALID_PATH_CHAR_PATTERN
+    } else { &INVALID_PATH_CHAR_PATTERN };
+    if INVALID_PATH_PREFIX_CHAR_PATTERN.is_match(component) {
+	Err(PathFromStrError::InvalidStartChar)
+    }
+    else if suffix_pattern.is_match(component) {
+	Err(PathFromStrError::InvalidEndChar)
+    }
+    else if char_pattern.is_match(component) {
+	Err(PathFromStrError::InvalidChar)
+    }
+    else if INVALID_PATH_NAME_PATTERN.is_match(component) {
+	Err(PathFromStrError::ReservedName)
+    }
+    else {
+	Ok(())
+    }
+}
+
 /// Analogous to the `Path` struct in the standard library, this is a
 /// non-owned slice over a Psilo-VFS path.
 ///
@@ -141,16 +301,26 @@ static INVALID_PATH_NAME_PATTERN: Lazy<Regex> = Lazy::new(|| {
 ///   the following followed by a `.`. They are given in uppercase, but
 ///   mixed- and lowercase versions are forbidden as well.
 ///     - "AUX"
-///     - "COM1" through "COM9"
+///     - "CLOCK$"
+///     - "COM1" through "COM9", and "COM¹", "COM²", and "COM³" (with
+///       superscript digits)
 ///     - "CON"
-///     - "LPT1" through "LPT9"
+///     - "CONIN$"
+///     - "CONOUT$"
+///     - "LPT1" through "LPT9", and "LPT¹", "LPT²", and "LPT³"
 ///     - "NUL"
 ///     - "PRN"
+///
+///   ("COM0" and "LPT0" are allowed.)
 /// - A path component additionally MUST NOT *end* with any of the following:
 ///     - `!` (U+0021 EXCLAMATION MARK; reserved for future use)
 ///     - `^` (U+005E CIRCUMFLEX ACCENT; reserved for intermediate files)
 ///     - `~` (U+007E TILDE; reserved for backup files)
 ///
+///   (These three are only reserved for the sake of `FsSource`'s backup and
+///   intermediate file conventions. See
+///   [`try_from_str_permissive`](#method.try_from_str_permissive).)
+///
 /// In most filing systems, a path component "." denotes the current directory
 /// and a path component ".." denotes the parent directory. This is also the
 /// case in Psilo-VFS. In some filing systems, ".." in the root directory is
@@ -184,6 +354,12 @@ static INVALID_PATH_NAME_PATTERN: Lazy<Regex> = Lazy::new(|| {
 /// archive formats will be unable to handle this if pushed. In particular,
 /// don't poke the sleeping dragon by using filenames that differ only
 /// in case.
+///
+/// The `Ord` implementation is raw codepoint order of the underlying string.
+/// That's fast, but it isn't the order `ls` gives, in which a directory comes
+/// just before a file of the same name. (In raw codepoint order, `foo` comes
+/// before `foo/`, and `foo-bar` comes between them.) For the order the VFS
+/// uses, see [`dir_aware_cmp`](#method.dir_aware_cmp).
 #[repr(transparent)]
 #[derive(PartialEq,Eq,PartialOrd,Ord)]
 pub struct Path {
@@ -211,7 +387,65 @@ impl Path {
     /// is already in normal form D, no copying will take place. Returns an
     /// error if the passed path is invalid in any way.
     pub fn try_from_str(s: &str) -> Result<Cow<'_, Path>, PathFromStrError> {
-	if s == "" || s == "/" {
+	Path::try_from_str_with(s, false, false)
+    }
+    /// As [`try_from_str`](#method.try_from_str), but allows components that
+    /// end with `!`, `^`, or `~`. Those characters are only reserved for the
+    /// sake of [`FsSource`](struct.FsSource.html)'s backup and intermediate
+    /// files. If you've turned that off (see
+    /// [`FsSource::with_reserved_suffixes`](struct.FsSource.html#method.with_reserved_suffixes)),
+    /// or aren't using `FsSource` at all, you can use this to make paths that
+    /// end with them. Any other source will treat them like any other name.
+    ///
+    /// The resulting paths can't be joined to with the strict `join`
+    /// functions, and `p!` won't accept them.
+    pub fn try_from_str_permissive(s: &str)
+				   -> Result<Cow<'_, Path>, PathFromStrError> {
+	Path::try_from_str_with(s, true, false)
+    }
+    /// Creates a glob pattern for [`matches_glob`](#method.matches_glob) from
+    /// a `&str`. Validates and normalizes it as
+    /// [`try_from_str`](#method.try_from_str) does, except that `*` and `?`
+    /// are allowed.
+    ///
+    /// The result is only meant to be used as a pattern. Opening it, or
+    /// joining it to a real path, won't do anything useful.
+    pub fn try_glob_from_str(s: &str)
+			     -> Result<Cow<'_, Path>, PathFromStrError> {
+	Path::try_from_str_with(s, false, true)
+    }
+    /// A porting aid for code that used to build paths with `\` as a
+    /// separator. Converts every `\` to `/`, then validates and normalizes
+    /// the result as [`try_from_str`](#method.try_from_str) does, so
+    /// `foo\bar` becomes `foo/bar`. Invalid components are still rejected
+    /// after conversion. Drive letter and UNC paths (`C:\foo`,
+    /// `\\server\share`) are still refused with `LooksLikeOsPath`, since
+    /// they don't name anything in the VFS.
+    ///
+    /// Only use this on strings that came from code written for `\`
+    /// separators. In a real Psilo-VFS path, `\` is an invalid character,
+    /// and quietly treating it as a separator would hide mistakes.
+    ///
+    /// ```
+    /// # use psilo_vfs::Path;
+    /// assert_eq!(Path::from_os_like_str(r"textures\grass.png").unwrap()
+    ///            .as_str(), "textures/grass.png");
+    /// ```
+    pub fn from_os_like_str(s: &str)
+			    -> Result<Cow<'_, Path>, PathFromStrError> {
+	if !s.contains('\\') { return Path::try_from_str(s) }
+	if OS_PATH_PATTERN.is_match(s) {
+	    return Err(PathFromStrError::LooksLikeOsPath)
+	}
+	let converted = s.replace('\\', "/");
+	Path::try_from_str(&converted).map(|x| Cow::Owned(x.into_owned()))
+    }
+    fn try_from_str_with(s: &str, permissive: bool, glob: bool)
+			 -> Result<Cow<'_, Path>, PathFromStrError> {
+	if OS_PATH_PATTERN.is_match(s) {
+	    return Err(PathFromStrError::LooksLikeOsPath)
+	}
+	else if s == "" || s == "/" {
 	    return Ok(Cow::Borrowed(Path::from_str_preverified(s)))
 	}
 	else if s == "//" {
@@ -230,23 +464,17 @@ impl Path {
 		need_edit = true;
 	    }
 	    else if component == ".." {
-		if any_non_dotdot_components {
+		// (in an absolute path, this escapes the root, which is caught
+		// while editing)
+		if any_non_dotdot_components || s.starts_with('/') {
 		    need_edit = true;
 		}
 	    }
-	    else if INVALID_PATH_PREFIX_CHAR_PATTERN.is_match(component) {
-		return Err(PathFromStrError::InvalidStartChar)
-	    }
-	    else if INVALID_PATH_SUFFIX_CHAR_PATTERN.is_match(component) {
-		return Err(PathFromStrError::InvalidEndChar)
-	    }
-	    else if INVALID_PATH_CHAR_PATTERN.is_match(component) {
-		return Err(PathFromStrError::InvalidChar)
-	    }
-	    else if INVALID_PATH_NAME_PATTERN.is_match(component) {
-		return Err(PathFromStrError::ReservedName)
+	    else if component.is_empty() {
+		return Err(PathFromStrError::DoubleSlash)
 	    }
 	    else {
+		check_component_with(component, permissive, glob)?;
 		any_non_dotdot_components = true;
 	    }
 	}
@@ -257,7 +485,7 @@ impl Path {
 	}
 	else {
 	    // this string might grow slightly beyond this, hope that's OK
-	    let mut ret = String::with_capacity(s.len()+1);
+	    let mut ret = Inner::with_capacity(s.len()+1);
 	    if s.starts_with("/") { ret.push('/') }
 	    for component in subset.split('/') {
 		if component == "." { continue }
@@ -282,19 +510,93 @@ impl Path {
 		    }
 		}
 		else {
-		    for c in component.chars() {
-			decompose_canonical(c, |c| ret.push(c));
-		    }
+		    ret.extend(component.nfd());
 		    ret.push('/');
 		}
 	    }
-	    // remove the extra trailing `/` that appeared
-	    if !s.ends_with("/") { ret.pop(); }
+	    // remove the extra trailing `/` that appeared, unless the path
+	    // ended with `.`, which is a directory
+	    if !s.ends_with("/") && !s.ends_with(".") { ret.pop(); }
 	    Ok(Cow::Owned(PathBuf { inner: ret }))
 	}
     }
+    /// As [`try_from_str`](#method.try_from_str), but additionally returns
+    /// an error if any component is longer than `max_component_len`, or if
+    /// the whole path is longer than `max_total_len`. Lengths are in UTF-8
+    /// bytes, and are checked *after* normalization.
+    ///
+    /// Useful when the path will end up in an archive format or filesystem
+    /// with hard length limits. (See the [`Path`](struct.Path.html)
+    /// documentation for the 255 byte rule of thumb.)
+    pub fn try_from_str_bounded(s: &str, max_component_len: usize,
+				max_total_len: usize)
+				-> Result<Cow<'_, Path>, PathFromStrError> {
+	let ret = Path::try_from_str(s)?;
+	if ret.components().any(|x| x.inner.len() > max_component_len) {
+	    return Err(PathFromStrError::ComponentTooLong)
+	}
+	if ret.inner.len() > max_total_len {
+	    return Err(PathFromStrError::PathTooLong)
+	}
+	Ok(ret)
+    }
+    /// As [`try_from_str`](#method.try_from_str), but additionally returns
+    /// `TooManyParents` if the path, once normalized, starts with more than
+    /// `max_leading_parents` `..`s. (An absolute path never starts with any.)
+    ///
+    /// Useful when you know how deep the directory the path will be joined
+    /// to is, and want to reject a path that would climb out of it as soon
+    /// as it's read. See also [`would_escape`](#method.would_escape).
+    ///
+    /// ```
+    /// # use psilo_vfs::{Path, PathFromStrError};
+    /// assert!(Path::try_from_str_max_escape("../../a", 2).is_ok());
+    /// assert_eq!(Path::try_from_str_max_escape("../b/../../a", 1),
+    ///            Err(PathFromStrError::TooManyParents));
+    /// ```
+    pub fn try_from_str_max_escape(s: &str, max_leading_parents: usize)
+				   -> Result<Cow<'_, Path>, PathFromStrError> {
+	let ret = Path::try_from_str(s)?;
+	let dotdots = ret.components()
+	    .take_while(|x| x.as_str() == "..").count();
+	if dotdots > max_leading_parents {
+	    return Err(PathFromStrError::TooManyParents)
+	}
+	Ok(ret)
+    }
     /// Returns the path as a `&str`.
     pub fn as_str(&self) -> &str { &self.inner }
+    /// Returns the UTF-8 bytes of this path.
+    pub fn as_bytes(&self) -> &[u8] { self.inner.as_bytes() }
+    /// Returns the path in a form suitable for the path part of a URL. Every
+    /// byte other than an ASCII letter, digit, `-`, `.`, `_`, `~`, or the
+    /// separating `/`s is percent-encoded, including every byte of a
+    /// non-ASCII character (in its normal form D encoding). The inverse is
+    /// [`PathBuf::from_url_path`](struct.PathBuf.html#method.from_url_path).
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert_eq!(p!("/my saves/r\u{00E9}sum\u{00E9}").to_url_path(),
+    ///            "/my%20saves/re%CC%81sume%CC%81");
+    /// ```
+    pub fn to_url_path(&self) -> String {
+	let mut ret = String::with_capacity(self.inner.len());
+	for b in self.inner.bytes() {
+	    match b {
+		b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9'
+		    | b'-' | b'.' | b'_' | b'~' | b'/' => ret.push(b as char),
+		_ => ret.push_str(&format!("%{:02X}", b)),
+	    }
+	}
+	ret
+    }
+    /// Copies the path into a `Box<Path>`, for when a `PathBuf`'s spare
+    /// capacity isn't wanted.
+    pub fn to_boxed(&self) -> Box<Path> {
+	let boxed: Box<str> = self.inner.into();
+	// Sound for the same reason as `from_str_preverified`.
+	unsafe { Box::from_raw(Box::into_raw(boxed) as *mut Path) }
+    }
     /// Returns true if the path is absolute (begins with `/`), false if it's
     /// relative.
     pub fn is_absolute(&self) -> bool {
@@ -327,12 +629,112 @@ impl Path {
 	}
 	else { PathComponents::new(slice.split('/')) }
     }
+    /// Returns the component at the given index (counting from 0), without
+    /// a trailing `/`, or `None` if there aren't that many components.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// let path = p!("/users/1234/saves/quick");
+    /// assert_eq!(path.component(1), Some(p!("1234")));
+    /// assert_eq!(path.component(4), None);
+    /// ```
+    pub fn component(&self, index: usize) -> Option<&Path> {
+	self.components().nth(index)
+    }
+    /// As [`component`](#method.component), but counting from the last
+    /// component (which is index 0).
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// let path = p!("/users/1234/saves/quick");
+    /// assert_eq!(path.component_from_end(0), Some(p!("quick")));
+    /// ```
+    pub fn component_from_end(&self, index: usize) -> Option<&Path> {
+	self.components().nth_back(index)
+    }
+    /// Returns the components of this path, like
+    /// [`components`](#method.components), along with whether each is the
+    /// last one and whether it names a directory. Every component but the last
+    /// is a directory; the last is a directory if this path is.
+    pub fn components_typed(&self) -> impl Iterator<Item=TypedComponent<'_>> {
+	let is_directory = self.is_directory();
+	let mut components = self.components().peekable();
+	std::iter::from_fn(move || {
+	    let name = components.next()?;
+	    let is_last = components.peek().is_none();
+	    Some(TypedComponent { name, is_last,
+				  is_directory: !is_last || is_directory })
+	})
+    }
+    /// Returns every `/`-separated segment of the underlying string, exactly
+    /// as written. Unlike [`components`](#method.components), this includes
+    /// the empty segment before the `/` of an absolute path and after the `/`
+    /// of a directory, as well as any `.`, `..`, or empty segments in the
+    /// middle. Only useful for inspecting un-normalized input, e.g. a `Path`
+    /// made with `from_str_preverified`; a valid path has no surprises.
+    pub fn raw_components(&self) -> PathComponents<'_> {
+	PathComponents::new(self.inner.split('/'))
+    }
     /// Returns `Some(...)` giving the path to the parent directory of this
     /// path if there is one, `None` if the path is "" or "/".
     pub fn parent(&self) -> &Path {
 	Path::from_str_preverified(self.inner.trim_end_matches('/')
 				 .trim_end_matches(|x| x != '/'))
     }
+    /// Returns an iterator over this path and each of its ancestors, the same
+    /// as calling [`parent`](#method.parent) repeatedly. Ends with `/` for an
+    /// absolute path, or `""` for a relative one.
+    ///
+    /// Each step only looks at the component being removed, so walking the
+    /// whole chain takes time proportional to the length of the path.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert_eq!(p!("/a/b/c").ancestors().collect::<Vec<_>>(),
+    ///            [p!("/a/b/c"), p!("/a/b/"), p!("/a/"), p!("/")]);
+    /// ```
+    pub fn ancestors(&self) -> Ancestors<'_> {
+	Ancestors { inner: Some(&self.inner) }
+    }
+    /// Returns an iterator over the leading parts of this path, from
+    /// shallowest to deepest: the reverse of
+    /// [`ancestors`](#method.ancestors), except that the root (or the empty
+    /// path) isn't included. Every prefix but the last is a directory; the
+    /// last is this path itself.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert_eq!(p!("/a/b/c").prefixes().collect::<Vec<_>>(),
+    ///            [p!("/a/"), p!("/a/b/"), p!("/a/b/c")]);
+    /// ```
+    pub fn prefixes(&self) -> Prefixes<'_> {
+	Prefixes { path: &self.inner,
+		   pos: if self.is_absolute() { 1 } else { 0 } }
+    }
+    /// Splits this path after its first `n` components, into a directory
+    /// that's absolute if this path is, and the rest as a relative path.
+    /// Returns `None` if there aren't `n` components. Splitting a file after
+    /// its last component gives the whole path (which isn't a directory) and
+    /// an empty remainder.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert_eq!(p!("/plugins/fnord/data/x").split_at_depth(2),
+    ///            Some((p!("/plugins/fnord/"), p!("data/x"))));
+    /// assert_eq!(p!("/plugins/fnord/data/x").split_at_depth(5), None);
+    /// ```
+    pub fn split_at_depth(&self, n: usize) -> Option<(&Path, &Path)> {
+	let mut pos = if self.is_absolute() { 1 } else { 0 };
+	for _ in 0 .. n {
+	    if pos >= self.inner.len() { return None }
+	    pos = match self.inner[pos..].find('/') {
+		Some(x) => pos + x + 1,
+		None => self.inner.len(),
+	    };
+	}
+	Some((Path::from_str_preverified(&self.inner[..pos]),
+	      Path::from_str_preverified(&self.inner[pos..])))
+    }
     /// Returns `Some(...)` if the last component of this `Path` has a "dot
     /// extension", `None` if it does not. If multiple extensions are present,
     /// only the *last* is returned.
@@ -341,6 +743,50 @@ impl Path {
 	    final_component.inner.split('.').last()
 	} else { None }
     }
+    /// Returns this path with the last extension of its last component
+    /// removed, borrowed from this path. If there's no extension to remove,
+    /// returns this path unchanged. Directories are also returned unchanged,
+    /// as are names that would become invalid without their extension (e.g.
+    /// `foo .txt`, since `foo ` is not a valid name).
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert_eq!(p!("/a/b.tar.gz").strip_extension(), p!("/a/b.tar"));
+    /// assert_eq!(p!("/a/b").strip_extension(), p!("/a/b"));
+    /// ```
+    pub fn strip_extension(&self) -> &Path {
+	if self.is_directory() { return self }
+	let name_start = self.inner.rfind('/').map(|x| x + 1).unwrap_or(0);
+	match self.inner[name_start..].rfind('.') {
+	    Some(dot) if dot > 0 => {
+		let stem = &self.inner[name_start..name_start + dot];
+		if check_component(stem).is_ok() {
+		    Path::from_str_preverified(&self.inner[..name_start + dot])
+		}
+		else { self }
+	    },
+	    _ => self,
+	}
+    }
+    /// Returns this path without its trailing `/`, borrowed from this path,
+    /// e.g. `/foo/` becomes `/foo`. Paths that aren't directories are
+    /// returned unchanged. So are paths whose last component can't be a
+    /// file: the root (`/`), the empty path, and paths ending in `../`. (See
+    /// [`PathBuf::make_dir_into_file`](struct.PathBuf.html#method.make_dir_into_file)
+    /// for the owned version, which panics on those instead.)
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert_eq!(p!("/foo/").trim_trailing_slash(), p!("/foo"));
+    /// assert_eq!(p!("/").trim_trailing_slash(), p!("/"));
+    /// ```
+    pub fn trim_trailing_slash(&self) -> &Path {
+	if self.as_str() == "/" || self.inner.ends_with("../") { return self }
+	match self.inner.strip_suffix('/') {
+	    Some(x) => Path::from_str_preverified(x),
+	    None => self,
+	}
+    }
     /// If the given path is a prefix of this path, returns an absolute path
     /// containing the parts of this path minus the prefix. For example:
     ///
@@ -352,14 +798,330 @@ impl Path {
     ///
     /// This does work with relative paths, but if `other` is not a path to a
     /// directory, this will never work!
+    ///
+    /// Edge cases: if `other` is "/", any absolute path is returned unchanged.
+    /// If `other` is the same directory as this path, the result is "/". If
+    /// `other` is "", the result is always `None`, since there's no way to
+    /// borrow an absolute path from a relative one.
     pub fn with_prefix_absolute(&self, other: &Path) -> Option<&Path> {
-        if !other.is_directory() { return None }
+        if !other.is_directory() || other.inner.is_empty() { return None }
         match self.inner.strip_prefix(&other.inner[..other.inner.len()-1]) {
             None => None,
             Some(x) if !x.starts_with('/') => None,
             Some(x) => Some(Path::from_str_preverified(x))
         }
     }
+    /// Returns true if this path is a directory that contains `other`, at any
+    /// depth. A path is not its own ancestor. This is a pure string
+    /// operation; the filesystem is not consulted. Leading `..`s lead out of
+    /// a directory, not into it, so `""` is not an ancestor of `../x`, nor
+    /// `../` of `../../x`.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert!(p!("/foo/").is_ancestor_of(p!("/foo/bar/baz")));
+    /// assert!(!p!("/foo/").is_ancestor_of(p!("/foo/")));
+    /// assert!(!p!("/foo/").is_ancestor_of(p!("/foobar")));
+    /// ```
+    pub fn is_ancestor_of(&self, other: &Path) -> bool {
+	if !self.is_directory() || self.is_absolute() != other.is_absolute() {
+	    return false
+	}
+	match other.inner.strip_prefix(&self.inner) {
+	    Some(rest) => !rest.is_empty() && !rest.starts_with("../"),
+	    None => false,
+	}
+    }
+    /// Returns the longest directory that both paths are in (or are), as a
+    /// slice of this path. Only whole components count, so `/ab/c` and
+    /// `/abd/c` have only `/` in common. Two absolute paths always have at
+    /// least `/` in common; a relative path and any other path may have only
+    /// `""`.
+    ///
+    /// This is a pure string operation. Leading `..`s are compared like any
+    /// other component, so `../a` and `../../b` are said to share `../`.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert_eq!(p!("/gfx/tiles/grass.png").common_prefix(p!("/gfx/ui/")),
+    ///            p!("/gfx/"));
+    /// assert_eq!(p!("/ab/c").common_prefix(p!("/abd/c")), p!("/"));
+    /// assert_eq!(p!("a/b").common_prefix(p!("/a/b")), p!(""));
+    /// ```
+    pub fn common_prefix<'a>(&'a self, other: &Path) -> &'a Path {
+	let end = self.inner.bytes().zip(other.inner.bytes())
+	    .enumerate()
+	    .take_while(|(_, (a, b))| a == b)
+	    .filter(|(_, (a, _))| *a == b'/')
+	    .last()
+	    .map(|(i, _)| i + 1).unwrap_or(0);
+	Path::from_str_preverified(&self.inner[..end])
+    }
+    /// Returns true if joining this path onto `base` (as
+    /// [`PathBuf::try_join`](struct.PathBuf.html#method.try_join) would)
+    /// would fail with `EscapedRoot`, i.e. if it has more leading `..`s than
+    /// `base` has components. Checks without building the joined path, so
+    /// it's cheap to reject a user-supplied path up front.
+    ///
+    /// Only an absolute `base` can be escaped. An absolute `self` can't be
+    /// joined at all, but doesn't escape anything, so gives false. This is a
+    /// pure string operation; the filesystem is not consulted.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert!(p!("../../x").would_escape(p!("/a/")));
+    /// assert!(!p!("../x").would_escape(p!("/a/b/")));
+    /// ```
+    pub fn would_escape(&self, base: &Path) -> bool {
+	if self.is_absolute() || !base.is_absolute() { return false }
+	let dotdots = self.components()
+	    .take_while(|x| x.as_str() == "..").count();
+	dotdots > base.components().count()
+    }
+    /// Compares two paths in the order the VFS uses: component by component,
+    /// with a directory coming just before a file of the same name. (Where
+    /// one path has the same components as the other and then some, the
+    /// shorter path comes first. An absolute path comes before a relative
+    /// path with the same components.) This is the order that `ls` returns.
+    ///
+    /// This is a total order, consistent with `==`, so it's safe to sort
+    /// with. It's *not* the same as the derived `Ord`, which is raw codepoint
+    /// order.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// # use std::cmp::Ordering;
+    /// assert_eq!(p!("foo/").dir_aware_cmp(p!("foo")), Ordering::Less);
+    /// assert_eq!(p!("foo/").cmp(p!("foo")), Ordering::Greater);
+    /// ```
+    pub fn dir_aware_cmp(&self, other: &Path) -> Ordering {
+	self.components().map(Path::as_str)
+	    .cmp(other.components().map(Path::as_str))
+	    .then_with(|| other.is_directory().cmp(&self.is_directory()))
+	    .then_with(|| other.is_absolute().cmp(&self.is_absolute()))
+    }
+    /// Returns true if the two paths are the same, except possibly for whether
+    /// they end with `/`. `/foo` and `/foo/` are equal by this measure, but
+    /// still unequal as far as `==` is concerned; this doesn't change normal
+    /// equality.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert!(p!("/foo").eq_ignore_trailing_slash(p!("/foo/")));
+    /// assert!(!p!("/foo").eq_ignore_trailing_slash(p!("/foobar/")));
+    /// assert_ne!(p!("/foo"), p!("/foo/"));
+    /// ```
+    pub fn eq_ignore_trailing_slash(&self, other: &Path) -> bool {
+	fn strip(s: &str) -> &str {
+	    // "/" stays "/", it mustn't turn into ""
+	    if s.len() > 1 { s.strip_suffix('/').unwrap_or(s) } else { s }
+	}
+	strip(&self.inner) == strip(&other.inner)
+    }
+    /// Returns true if `other` is a directory that contains this path, at any
+    /// depth. The mirror image of [`is_ancestor_of`](#method.is_ancestor_of).
+    pub fn is_descendant_of(&self, other: &Path) -> bool {
+	other.is_ancestor_of(self)
+    }
+    /// Returns true if this path matches the given glob pattern. This is a
+    /// pure string operation; the filesystem is not consulted.
+    ///
+    /// The pattern is a path in which `*` matches any run of characters
+    /// within a single component, `?` matches exactly one character, and a
+    /// component consisting only of `**` matches zero or more whole
+    /// components. Since `*` and `?` aren't allowed in ordinary paths, make
+    /// patterns with [`try_glob_from_str`](#method.try_glob_from_str).
+    /// Matching is done codepoint by codepoint. The pattern and the path must
+    /// agree on whether they are absolute, and on whether they denote a
+    /// directory.
+    ///
+    /// ```
+    /// # use psilo_vfs::{p, Path};
+    /// let pattern = Path::try_glob_from_str("/a/**/*.png").unwrap();
+    /// assert!(p!("/a/b/c.png").matches_glob(&pattern));
+    /// let pattern = Path::try_glob_from_str("/a/*.png").unwrap();
+    /// assert!(!p!("/a/b/c.png").matches_glob(&pattern));
+    /// ```
+    pub fn matches_glob(&self, pattern: &Path) -> bool {
+	if pattern.is_absolute() != self.is_absolute()
+	    || pattern.is_directory() != self.is_directory() {
+	    return false
+	}
+	components_match_glob(self.components(), pattern.components())
+    }
+    /// Returns an ASCII-only rendition of this path, for log tags, temporary
+    /// file prefixes, and the like. Combining marks are dropped (so accented
+    /// letters lose their accents), any other non-ASCII character becomes
+    /// `_`, and `/` separators are kept as they are.
+    ///
+    /// This is lossy, and the result is not necessarily a valid path.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert_eq!(p!("/resumé/naïve").to_ascii_lossy(), "/resume/naive");
+    /// ```
+    pub fn to_ascii_lossy(&self) -> String {
+	let mut ret = String::with_capacity(self.inner.len());
+	for c in self.inner.chars() {
+	    if c.is_ascii() { ret.push(c) }
+	    else if !is_combining_mark(c) { ret.push('_') }
+	}
+	ret
+    }
+    /// Splits this path after its first component. Returns `None` if the path
+    /// has no components (it is "" or "/").
+    ///
+    /// The first half is everything up to and including the first component,
+    /// including the leading `/` of an absolute path and the `/` after the
+    /// component if there is one. The second half is the rest of the path, as
+    /// a relative path. Concatenating the two halves gives the original path.
+    /// No allocation takes place.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert_eq!(p!("/plugins/fnord/data").split_first(),
+    ///            Some((p!("/plugins/"), p!("fnord/data"))));
+    /// ```
+    pub fn split_first(&self) -> Option<(&Path, &Path)> {
+	let start = if self.is_absolute() { 1 } else { 0 };
+	if self.inner.len() <= start { return None }
+	let split = match self.inner[start..].find('/') {
+	    Some(i) => start + i + 1,
+	    None => self.inner.len(),
+	};
+	Some((Path::from_str_preverified(&self.inner[..split]),
+	      Path::from_str_preverified(&self.inner[split..])))
+    }
+    /// Splits this path before its last component. Returns `None` if the path
+    /// has no components (it is "" or "/").
+    ///
+    /// The first half is the parent directory (as returned by
+    /// [`parent`](#method.parent)), the second half is the final component,
+    /// keeping its trailing `/` if this path denotes a directory.
+    /// Concatenating the two halves gives the original path. No allocation
+    /// takes place.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert_eq!(p!("/plugins/fnord/").split_last(),
+    ///            Some((p!("/plugins/"), p!("fnord/"))));
+    /// ```
+    pub fn split_last(&self) -> Option<(&Path, &Path)> {
+	let trimmed = self.inner.strip_suffix('/').unwrap_or(&self.inner);
+	if trimmed.is_empty() { return None }
+	let split = trimmed.rfind('/').map(|i| i + 1).unwrap_or(0);
+	Some((Path::from_str_preverified(&self.inner[..split]),
+	      Path::from_str_preverified(&self.inner[split..])))
+    }
+    /// Returns true if the last component of this path is `name`. `name` is
+    /// normalized the same way paths are. A `name` ending in `/` matches only
+    /// a directory, and one without matches only a file. A `name` that isn't
+    /// a single valid component never matches.
+    ///
+    /// This is cheaper than building a `Path` to compare against.
+    ///
+    /// ```
+    /// # use psilo_vfs::p;
+    /// assert!(p!("/a/b/config.toml").ends_with_component("config.toml"));
+    /// assert!(p!("/a/b/").ends_with_component("b/"));
+    /// assert!(!p!("/a/b/").ends_with_component("b"));
+    /// ```
+    pub fn ends_with_component(&self, name: &str) -> bool {
+	let (name, want_directory) = match name.strip_suffix('/') {
+	    Some(name) => (name, true),
+	    None => (name, false),
+	};
+	if want_directory != self.is_directory() || name.contains('/')
+	    || name == "." || name == ".." {
+	    return false
+	}
+	let last = match self.split_last() {
+	    Some((_, last)) => last.inner.strip_suffix('/')
+		.unwrap_or(&last.inner),
+	    None => return false,
+	};
+	let normalized;
+	let name = if name.is_ascii() { name } else {
+	    let mut buf = String::with_capacity(name.len());
+	    buf.extend(name.nfd());
+	    normalized = buf;
+	    &normalized
+	};
+	name == last && check_component_with(name, true, false).is_ok()
+    }
+}
+
+/// Matches one component against one glob pattern component, supporting `*`
+/// and `?`. When the pattern stops matching, the most recent `*` is made to
+/// swallow one more character and matching resumes from there; earlier `*`s
+/// never need revisiting, so this is at worst quadratic.
+fn component_matches_glob(name: &str, pattern: &str) -> bool {
+    let (mut n, mut p) = (0, 0);
+    // (position in pattern after the last `*`, position in name it resumes)
+    let mut star = None;
+    loop {
+	match pattern[p..].chars().next() {
+	    Some('*') => {
+		p += 1;
+		star = Some((p, n));
+		continue
+	    },
+	    Some(pc) => if let Some(nc) = name[n..].chars().next() {
+		if pc == '?' || pc == nc {
+		    p += pc.len_utf8();
+		    n += nc.len_utf8();
+		    continue
+		}
+	    },
+	    None => if n == name.len() { return true },
+	}
+	match star {
+	    Some((star_p, star_n)) => match name[star_n..].chars().next() {
+		Some(c) => {
+		    p = star_p;
+		    n = star_n + c.len_utf8();
+		    star = Some((p, n));
+		},
+		None => return false,
+	    },
+	    None => return false,
+	}
+    }
+}
+
+/// Matches a list of components against a list of glob pattern components,
+/// supporting `**`. Backtracks the same way `component_matches_glob` does,
+/// a whole component at a time.
+fn components_match_glob<'a>(mut names: PathComponents<'a>,
+			     mut patterns: PathComponents<'a>) -> bool {
+    let mut star: Option<(PathComponents, PathComponents)> = None;
+    loop {
+	let mut next_names = names.clone();
+	let mut next_patterns = patterns.clone();
+	match next_patterns.next().map(Path::as_str) {
+	    Some("**") => {
+		star = Some((next_patterns.clone(), names.clone()));
+		patterns = next_patterns;
+		continue
+	    },
+	    Some(pattern) => if let Some(name) = next_names.next() {
+		if component_matches_glob(name.as_str(), pattern) {
+		    names = next_names;
+		    patterns = next_patterns;
+		    continue
+		}
+	    },
+	    None => if next_names.next().is_none() { return true },
+	}
+	match star.as_mut() {
+	    Some((star_patterns, star_names)) => {
+		if star_names.next().is_none() { return false }
+		names = star_names.clone();
+		patterns = star_patterns.clone();
+	    },
+	    None => return false,
+	}
+    }
 }
 
 impl Display for Path {
@@ -394,7 +1156,7 @@ impl AsRef<str> for Path {
 impl ToOwned for Path {
     type Owned = PathBuf;
     fn to_owned(&self) -> PathBuf {
-	PathBuf { inner: self.to_string() }
+	PathBuf { inner: Inner::from(&self.inner) }
     }
 }
 
@@ -411,7 +1173,60 @@ impl PartialEq<str> for Path {
     }
 }
 
+/// An iterator over a `Path` and its ancestors. See
+/// [`Path::ancestors`](struct.Path.html#method.ancestors).
+pub struct Ancestors<'a> {
+    /// `None` once the root (or the empty path) has been returned.
+    inner: Option<&'a str>,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = &'a Path;
+    fn next(&mut self) -> Option<&'a Path> {
+	let ret = self.inner?;
+	self.inner = if ret == "/" || ret.is_empty() { None }
+	else {
+	    let trimmed = ret.strip_suffix('/').unwrap_or(ret);
+	    Some(&ret[..trimmed.rfind('/').map(|x| x + 1).unwrap_or(0)])
+	};
+	Some(Path::from_str_preverified(ret))
+    }
+}
+
+/// An iterator over the leading parts of a `Path`. See
+/// [`Path::prefixes`](struct.Path.html#method.prefixes).
+pub struct Prefixes<'a> {
+    path: &'a str,
+    /// Where the next component starts.
+    pos: usize,
+}
+
+impl<'a> Iterator for Prefixes<'a> {
+    type Item = &'a Path;
+    fn next(&mut self) -> Option<&'a Path> {
+	if self.pos >= self.path.len() { return None }
+	self.pos = match self.path[self.pos..].find('/') {
+	    Some(i) => self.pos + i + 1,
+	    None => self.path.len(),
+	};
+	Some(Path::from_str_preverified(&self.path[..self.pos]))
+    }
+}
+
+/// One component of a `Path`, as returned by
+/// [`Path::components_typed`](struct.Path.html#method.components_typed).
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct TypedComponent<'a> {
+    /// The component itself, with no `/`.
+    pub name: &'a Path,
+    /// True if this is the last component of the path.
+    pub is_last: bool,
+    /// True if this component names a directory.
+    pub is_directory: bool,
+}
+
 /// An iterator over the components of a `Path`.
+#[derive(Clone)]
 pub struct PathComponents<'a> {
     inner: str::Split<'a, char>,
 }
@@ -449,18 +1264,27 @@ impl<'a> DoubleEndedIterator for PathComponents<'a> {
 #[repr(transparent)]
 #[derive(PartialEq,Eq,PartialOrd,Ord,Clone)]
 pub struct PathBuf {
-    inner: String
+    inner: Inner
 }
 
+/// What a `PathBuf` keeps its path in. With the `compact_str` feature, a
+/// short path (up to 24 bytes on 64-bit targets) is kept inline, with no heap
+/// allocation. Only a handful of `String` methods are used, and
+/// `CompactString` has them all.
+#[cfg(not(feature = "compact_str"))]
+type Inner = String;
+#[cfg(feature = "compact_str")]
+type Inner = compact_str::CompactString;
+
 impl PathBuf {
     /// Creates a new, empty `PathBuf`.
     pub fn new() -> PathBuf {
-	PathBuf { inner: String::new() }
+	PathBuf { inner: Inner::default() }
     }
     /// Creates a new `PathBuf` with a given initial capacity in its underlying
     /// `String`.
     pub fn with_capacity(capacity: usize) -> PathBuf {
-	PathBuf { inner: String::with_capacity(capacity) }
+	PathBuf { inner: Inner::with_capacity(capacity) }
     }
     /// Creates a new `PathBuf` from a `&str`. Panics if the passed path is
     /// invalid in any way. Convenient, but fragile.
@@ -472,10 +1296,119 @@ impl PathBuf {
     pub fn try_from_str(s: &str) -> Result<PathBuf, PathFromStrError> {
 	Path::try_from_str(s).map(Cow::into_owned)
     }
+    /// Creates a new `PathBuf` from UTF-8 bytes. Returns an error if the
+    /// bytes aren't UTF-8, or if they are but the path is invalid in any way.
+    pub fn try_from_utf8(bytes: &[u8]) -> Result<PathBuf, PathFromUtf8Error> {
+	let s = std::str::from_utf8(bytes)
+	    .map_err(PathFromUtf8Error::NotUtf8)?;
+	PathBuf::try_from_str(s).map_err(PathFromUtf8Error::InvalidPath)
+    }
+    /// Assembles a path out of individual components, such as
+    /// `["users", user_id, "save"]`. Each part must be exactly one valid
+    /// component: not empty, not `.` or `..`, and with no slashes in it. The
+    /// parts are normalized as `try_from_str` would.
+    ///
+    /// With no parts, the result is `/` or the empty path, whatever `is_dir`
+    /// says.
+    ///
+    /// ```
+    /// # use psilo_vfs::PathBuf;
+    /// let path = PathBuf::from_components(true, false,
+    ///                                     ["users", "1234", "save"]);
+    /// assert_eq!(path.unwrap().as_str(), "/users/1234/save");
+    /// assert!(PathBuf::from_components(true, false, ["../etc"]).is_err());
+    /// ```
+    pub fn from_components<'a, I>(absolute: bool, is_dir: bool, parts: I)
+				  -> Result<PathBuf, PathFromStrError>
+    where I: IntoIterator<Item=&'a str> {
+	let mut ret = PathBuf::new();
+	if absolute { ret.inner.push('/') }
+	for part in parts {
+	    ret.push_component(part)?;
+	    ret.inner.push('/');
+	}
+	if !is_dir && ret.inner.len() > 1 { ret.inner.pop(); }
+	Ok(ret)
+    }
+    /// Normalizes `part` and adds it to the end, if it's exactly one valid
+    /// component. Afterwards, on error, the path is garbage.
+    fn push_component(&mut self, part: &str) -> Result<(), PathFromStrError> {
+	if part.is_empty() { return Err(PathFromStrError::DoubleSlash) }
+	let start = self.inner.len();
+	self.inner.extend(part.nfd());
+	check_component(&self.inner[start..])
+    }
+    /// Replaces every component of this path with what `f` returns for it,
+    /// such as to lowercase every name. The results are validated and
+    /// normalized as in
+    /// [`from_components`](#method.from_components), and the path stays
+    /// absolute or relative, and a directory or a file, as it was. Leading
+    /// `..` components are left as they are, and aren't given to `f`.
+    ///
+    /// If any result isn't exactly one valid component, returns the error,
+    /// and leaves this path unchanged.
+    ///
+    /// ```
+    /// # use psilo_vfs::PathBuf;
+    /// let mut path = PathBuf::from_str("/Maps/E1M1.map");
+    /// path.map_components(|x| x.to_lowercase()).unwrap();
+    /// assert_eq!(path.as_str(), "/maps/e1m1.map");
+    /// ```
+    pub fn map_components(&mut self, mut f: impl FnMut(&str) -> String)
+			  -> Result<(), PathFromStrError> {
+	let mut ret = PathBuf::with_capacity(self.inner.len());
+	if self.is_absolute() { ret.inner.push('/') }
+	for component in self.components() {
+	    if component == ".." { ret.inner.push_str("../"); continue }
+	    ret.push_component(&f(component.as_str()))?;
+	    ret.inner.push('/');
+	}
+	if !self.is_directory() && ret.inner.len() > 1 { ret.inner.pop(); }
+	*self = ret;
+	Ok(())
+    }
+    /// Decodes the path part of a URL, as made by
+    /// [`Path::to_url_path`](struct.Path.html#method.to_url_path), and
+    /// validates (and normalizes) the result as `try_from_str` would. Any
+    /// percent escapes are decoded, whether or not they were needed, but an
+    /// escaped `/` is never taken as a separator.
+    pub fn from_url_path(s: &str) -> Result<PathBuf, PathFromUrlError> {
+	let hex = |b: u8| (b as char).to_digit(16);
+	let mut decoded = Vec::with_capacity(s.len());
+	let mut bytes = s.bytes();
+	while let Some(b) = bytes.next() {
+	    if b != b'%' { decoded.push(b); continue }
+	    let hi = bytes.next().and_then(hex);
+	    let lo = bytes.next().and_then(hex);
+	    match (hi, lo) {
+		(Some(hi), Some(lo)) => {
+		    let b = (hi * 16 + lo) as u8;
+		    if b == b'/' {
+			return Err(PathFromUrlError::InvalidPath
+				   (PathFromStrError::InvalidChar))
+		    }
+		    decoded.push(b);
+		},
+		_ => return Err(PathFromUrlError::BadEscape),
+	    }
+	}
+	let s = std::str::from_utf8(&decoded)
+	    .map_err(PathFromUrlError::NotUtf8)?;
+	PathBuf::try_from_str(s).map_err(PathFromUrlError::InvalidPath)
+    }
     /// Borrows this `PathBuf`'s contents as a `&Path`.
     pub fn as_path(&self) -> &Path {
 	self.as_ref()
     }
+    /// Converts this `PathBuf` into the `String` it wraps, without copying.
+    /// (With the `compact_str` feature, a short path has to be copied into a
+    /// new `String`.)
+    pub fn into_string(self) -> String {
+	#[cfg(not(feature = "compact_str"))]
+	{ self.inner }
+	#[cfg(feature = "compact_str")]
+	{ self.inner.into_string() }
+    }
     /// Attempts to extend `self` by applying a relative path to it. The path
     /// *must* be relative. Panics on failure. Convenient but fragile.
     /// `some_path.join(foo)` is basically equivalent to
@@ -511,6 +1444,20 @@ impl PathBuf {
 	    Err(PathJoinError::BasePathNotDir)
 	}
     }
+    /// Attempts to extend `self` by applying each of the given relative paths
+    /// in turn, stopping at the first one that fails. On failure, `self` is
+    /// left unchanged.
+    pub fn try_join_all<'a>(&mut self, parts: impl IntoIterator<Item=&'a Path>)
+			    -> Result<&mut Self, PathJoinError> {
+	let original = self.inner.clone();
+	for part in parts {
+	    if let Err(x) = self.try_join(part) {
+		self.inner = original;
+		return Err(x)
+	    }
+	}
+	Ok(self)
+    }
     /// If the given path is relative, attempts to extend `self` by applying
     /// this path. If the given path is absolute, replaces `self` with the new
     /// path. Panics on failure. Convenient but fragile.
@@ -546,10 +1493,75 @@ impl PathBuf {
 	    && self.inner.len() > 0 { self.inner.pop(); }
 	true
     }
+    /// Appends `.` and the given extension to the last component of this
+    /// path, e.g. turning `save.dat` into `save.dat.bak`. Any existing
+    /// extension is kept. If this path denotes a directory, the extension is
+    /// appended to the directory's name, before the trailing `/` (`saves/`
+    /// becomes `saves.bak/`).
+    ///
+    /// Returns an error, leaving the path untouched, if the last component
+    /// would no longer be valid. In particular, "" and "/" have no last
+    /// component, so appending an extension to either one gives
+    /// `InvalidStartChar`.
+    pub fn append_extension(&mut self, ext: &str)
+			    -> Result<(), PathFromStrError> {
+	let name_end = if self.inner.ends_with('/') { self.inner.len() - 1 }
+	else { self.inner.len() };
+	let name_start = self.inner[..name_end].rfind('/')
+	    .map(|i| i + 1).unwrap_or(0);
+	let mut name = String::with_capacity(name_end - name_start
+					     + ext.len() + 1);
+	name.push_str(&self.inner[name_start..name_end]);
+	name.push('.');
+	name.extend(ext.nfd());
+	check_component(&name)?;
+	self.inner.replace_range(name_start..name_end, &name);
+	Ok(())
+    }
+    /// If this path is `old` or something inside it, replaces that part of the
+    /// path with `new`. Returns true if a replacement was made. Both `old` and
+    /// `new` must be directories; if they aren't, nothing is replaced. Nor is
+    /// anything replaced if this path only starts with `old` because of
+    /// leading `..`s, which lead out of `old` (see
+    /// [`is_ancestor_of`](struct.Path.html#method.is_ancestor_of)).
+    ///
+    /// ```
+    /// # use psilo_vfs::{p, PathBuf};
+    /// let mut path = PathBuf::from_str("/old/a/b");
+    /// assert!(path.replace_prefix(p!("/old/"), p!("/new/deeper/")));
+    /// assert_eq!(path.as_str(), "/new/deeper/a/b");
+    /// ```
+    pub fn replace_prefix(&mut self, old: &Path, new: &Path) -> bool {
+	if !old.is_directory() || !new.is_directory()
+	    || !(self.as_str() == old.as_str() || old.is_ancestor_of(self)) {
+		return false
+	    }
+	self.inner.replace_range(..old.inner.len(), &new.inner);
+	true
+    }
+    /// Converts this path to Unicode normal form D, in place. If the path is
+    /// already normalized (which is always the case for paths built through
+    /// the public API), this does nothing and allocates nothing.
+    ///
+    /// This is a safety net for use after bulk string manipulation that might
+    /// have let non-normalized text in.
+    pub fn renormalize(&mut self) {
+	if is_nfd_quick(self.inner.chars()) == IsNormalized::Yes { return }
+	// this string might grow slightly beyond this, hope that's OK
+	let mut ret = Inner::with_capacity(self.inner.len()+1);
+	ret.extend(self.inner.nfd());
+	self.inner = ret;
+    }
     /// Invokes `reserve` on the internal `String`.
     pub fn reserve(&mut self, s: usize) { self.inner.reserve(s) }
-    /// Invokes `reserve_exact` on the internal `String`.
-    pub fn reserve_exact(&mut self, s: usize) { self.inner.reserve_exact(s) }
+    /// Invokes `reserve_exact` on the internal `String`. (With the
+    /// `compact_str` feature, which has no such thing, invokes `reserve`.)
+    pub fn reserve_exact(&mut self, s: usize) {
+	#[cfg(not(feature = "compact_str"))]
+	self.inner.reserve_exact(s);
+	#[cfg(feature = "compact_str")]
+	self.inner.reserve(s);
+    }
     /// Invokes `shrink_to_fit` on the internal `String`.
     pub fn shrink_to_fit(&mut self) { self.inner.shrink_to_fit() }
     /// Given a path to a file (e.g. `foo`), converts it into a path to a
@@ -558,6 +1570,20 @@ impl PathBuf {
         assert!(!self.is_directory());
         self.inner.push('/');
     }
+    /// Given a path to a directory (e.g. `foo/`), converts it into a path to
+    /// a file with the same name (e.g. `foo`).
+    ///
+    /// Panics if the path isn't a directory, or if it has no file form: the
+    /// root (`/`), the empty path, and paths ending in `../` can't name a
+    /// file, because they have no last component to name (or, in the `..`
+    /// case, one that can't be a file).
+    pub fn make_dir_into_file(&mut self) {
+        assert!(self.is_directory());
+        assert!(self.inner != "/" && !self.inner.is_empty()
+                && !self.inner.ends_with("../"),
+                "{:?} has no file form", self.inner);
+        self.inner.pop();
+    }
 }
 
 impl Borrow<Path> for PathBuf {
@@ -597,6 +1623,69 @@ impl Debug for PathBuf {
     }
 }
 
+/// Builds a path one component at a time, for code that wants to spell a
+/// path out rather than format a string. Each component is checked (and
+/// normalized) as it's added, as in
+/// [`PathBuf::from_components`](struct.PathBuf.html#method.from_components).
+/// The first invalid component is remembered, and `build` returns it.
+///
+/// A path built with `file` ends with that file; one that never calls
+/// `file` is a directory. Adding anything after the file is a bug in the
+/// calling code, and panics.
+///
+/// ```
+/// # use psilo_vfs::PathBuilder;
+/// let path = PathBuilder::absolute().dir("plugins").dir("fnord")
+///     .file("config.toml").build();
+/// assert_eq!(path.unwrap().as_str(), "/plugins/fnord/config.toml");
+/// assert!(PathBuilder::absolute().dir("..").file("passwd").build().is_err());
+/// ```
+#[derive(Debug)]
+pub struct PathBuilder {
+    path: PathBuf,
+    error: Option<PathFromStrError>,
+    has_file: bool,
+}
+
+impl PathBuilder {
+    /// Starts building an absolute path, at `/`.
+    pub fn absolute() -> PathBuilder {
+	PathBuilder { path: PathBuf::from_str("/"), error: None,
+		      has_file: false }
+    }
+    /// Starts building a relative path, with no components yet.
+    pub fn relative() -> PathBuilder {
+	PathBuilder { path: PathBuf::new(), error: None, has_file: false }
+    }
+    /// Adds a directory.
+    pub fn dir(self, name: &str) -> PathBuilder {
+	self.push(name, true)
+    }
+    /// Adds the file at the end of the path.
+    pub fn file(self, name: &str) -> PathBuilder {
+	self.push(name, false)
+    }
+    fn push(mut self, name: &str, is_dir: bool) -> PathBuilder {
+	assert!(!self.has_file, "PathBuilder: nothing can come after the \
+				 file");
+	self.has_file = !is_dir;
+	if self.error.is_some() { return self }
+	if let Err(x) = self.path.push_component(name) {
+	    self.error = Some(x);
+	}
+	else if is_dir { self.path.inner.push('/') }
+	self
+    }
+    /// Returns the finished path, or the error from the first invalid
+    /// component.
+    pub fn build(self) -> Result<PathBuf, PathFromStrError> {
+	match self.error {
+	    Some(x) => Err(x),
+	    None => Ok(self.path),
+	}
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -621,6 +1710,292 @@ mod test {
 		   .collect::<Vec<_>>(),
 		   &["sword", "go", ""]);
     }
+    #[test] fn components_typed() {
+	// path, then (name, is_last, is_directory) for each component
+	type Case = (&'static str, &'static [(&'static str, bool, bool)]);
+	const CASES: &[Case] = &[
+	    ("/a/b/", &[("a", false, true), ("b", true, true)]),
+	    ("/a/b", &[("a", false, true), ("b", true, false)]),
+	    ("a", &[("a", true, false)]),
+	    ("/", &[]),
+	    ("", &[]),
+	];
+	for &(path, expected) in CASES.iter() {
+	    let got = Path::from_str_preverified(path).components_typed()
+		.map(|x| (x.name.as_str(), x.is_last, x.is_directory))
+		.collect::<Vec<_>>();
+	    assert_eq!(got, expected, "{:?}", path);
+	}
+    }
+    #[test] fn ancestors() {
+	const PATHS: &[&str] = &[
+	    "/a/b/c", "/a/b/c/", "/a", "/a/", "/", "a/b/c", "a/b/", "a", "",
+	    "../a/b", "../../",
+	];
+	for path in PATHS.iter() {
+	    let path = Path::from_str_preverified(path);
+	    let mut expected = vec![path];
+	    let mut cur = path;
+	    while cur != "/" && !cur.is_empty() {
+		cur = cur.parent();
+		expected.push(cur);
+	    }
+	    assert_eq!(path.ancestors().collect::<Vec<_>>(), expected,
+		       "{:?}", path);
+	}
+    }
+    #[test] fn prefixes() {
+	const PATHS: &[&str] = &[
+	    "/a/b/c", "/a/b/c/", "/a", "/a/", "/", "a/b/c", "a/b/", "a", "",
+	    "../a/b", "../../",
+	];
+	for path in PATHS.iter() {
+	    let path = Path::from_str_preverified(path);
+	    let mut expected = path.ancestors()
+		.filter(|x| *x != "/" && !x.is_empty()).collect::<Vec<_>>();
+	    expected.reverse();
+	    let got = path.prefixes().collect::<Vec<_>>();
+	    assert_eq!(got, expected, "{:?}", path);
+	    if let Some((_, dirs)) = got.split_last() {
+		assert!(dirs.iter().all(|x| x.is_directory()), "{:?}", path);
+	    }
+	}
+    }
+    #[test] fn split_at_depth() {
+	type Split = Option<(&'static str, &'static str)>;
+	const SPLITS: &[(&str, usize, Split)] = &[
+	    ("/plugins/fnord/data/x", 0, Some(("/", "plugins/fnord/data/x"))),
+	    ("/plugins/fnord/data/x", 1, Some(("/plugins/", "fnord/data/x"))),
+	    ("/plugins/fnord/data/x", 2, Some(("/plugins/fnord/", "data/x"))),
+	    ("/plugins/fnord/data/x", 4, Some(("/plugins/fnord/data/x", ""))),
+	    ("/plugins/fnord/data/x", 5, None),
+	    ("/plugins/fnord/", 2, Some(("/plugins/fnord/", ""))),
+	    ("/plugins/fnord/", 3, None),
+	    ("plugins/fnord/x", 0, Some(("", "plugins/fnord/x"))),
+	    ("plugins/fnord/x", 2, Some(("plugins/fnord/", "x"))),
+	    ("../a/b/", 1, Some(("../", "a/b/"))),
+	    ("/", 0, Some(("/", ""))),
+	    ("/", 1, None),
+	    ("", 0, Some(("", ""))),
+	    ("", 1, None),
+	];
+	for &(path, n, expected) in SPLITS.iter() {
+	    let path = Path::from_str_preverified(path);
+	    let got = path.split_at_depth(n);
+	    assert_eq!(got.map(|(a, b)| (a.as_str(), b.as_str())), expected,
+		       "{:?} at {}", path, n);
+	    if let Some((prefix, rest)) = got {
+		assert_eq!(prefix.is_absolute(), path.is_absolute());
+		assert!(rest.is_relative());
+		assert_eq!(prefix.components().count(), n);
+	    }
+	}
+    }
+    #[test] fn strip_extension() {
+	const CASES: &[(&str, &str)] = &[
+	    ("/a/b.tar.gz", "/a/b.tar"),
+	    ("/a/b.tar", "/a/b"),
+	    ("/a/b", "/a/b"),
+	    ("/a.d/b", "/a.d/b"),
+	    ("/a.d/", "/a.d/"),
+	    ("b.txt", "b"),
+	    ("b", "b"),
+	    ("/a/b .txt", "/a/b .txt"),
+	    ("/a/b..txt", "/a/b..txt"),
+	    ("/", "/"),
+	    ("", ""),
+	];
+	for &(path, expected) in CASES.iter() {
+	    let path = Path::from_str_preverified(path);
+	    let stripped = path.strip_extension();
+	    assert_eq!(stripped.as_str(), expected, "{:?}", path);
+	    assert!(path.as_str().starts_with(stripped.as_str()));
+	}
+    }
+    #[test] fn replace_prefix() {
+	const CASES: &[(&str, &str, &str, Option<&str>)] = &[
+	    ("/old/a/b", "/old/", "/new/deeper/", Some("/new/deeper/a/b")),
+	    ("/old/", "/old/", "/new/", Some("/new/")),
+	    ("/old/a/", "/", "/new/", Some("/new/old/a/")),
+	    ("/old/a", "/old/", "rel/", Some("rel/a")),
+	    ("a/b", "", "/abs/", Some("/abs/a/b")),
+	    ("/older/a", "/old/", "/new/", None),
+	    ("/old", "/old/", "/new/", None),
+	    ("/old/a", "/old", "/new/", None),
+	    ("/old/a", "/old/", "/new", None),
+	    ("/old/a", "", "/new/", None),
+	    ("old/a", "/old/", "/new/", None),
+	    ("../x", "../", "b/", Some("b/x")),
+	    ("../x", "", "/abs/", None),
+	    ("../x", "", "b/", None),
+	    ("../../x", "../", "b/", None),
+	    ("/old", "/old", "/new/", None),
+	];
+	for &(path, old, new, expected) in CASES.iter() {
+	    let mut buf = PathBuf::from_str(path);
+	    let replaced = buf.replace_prefix(Path::from_str_preverified(old),
+					      Path::from_str_preverified(new));
+	    assert_eq!(replaced, expected.is_some(), "{:?} {:?} {:?}",
+		       path, old, new);
+	    assert_eq!(buf.as_str(), expected.unwrap_or(path),
+		       "{:?} {:?} {:?}", path, old, new);
+	}
+    }
+    #[test] fn raw_components() {
+	let raw = Path::from_str_preverified("a/./b/../c");
+	assert_eq!(raw.raw_components().collect::<Vec<_>>(),
+		   &["a", ".", "b", "..", "c"]);
+	assert_eq!(Path::from_str("a/./b/../c").components()
+		   .collect::<Vec<_>>(),
+		   &["a", "c"]);
+	let raw = Path::from_str_preverified("/a/./b/../c/");
+	assert_eq!(raw.raw_components().collect::<Vec<_>>(),
+		   &["", "a", ".", "b", "..", "c", ""]);
+	assert_eq!(raw.components().collect::<Vec<_>>(),
+		   &["a", ".", "b", "..", "c"]);
+	assert_eq!(Path::from_str_preverified("").raw_components()
+		   .collect::<Vec<_>>(), &[""]);
+    }
+    #[test] fn ancestry() {
+	const CASES: &[(&str, &str, bool)] = &[
+	    ("/", "/foo", true),
+	    ("/", "/foo/bar/", true),
+	    ("/foo/", "/foo/bar", true),
+	    ("/foo/", "/foo/bar/baz", true),
+	    ("foo/", "foo/bar", true),
+	    ("", "foo", true),
+	    // not proper
+	    ("/", "/", false),
+	    ("/foo/", "/foo/", false),
+	    ("", "", false),
+	    // not on a component boundary
+	    ("/foo", "/foobar", false),
+	    ("/foo", "/foo/bar", false),
+	    ("/fo/", "/foo/bar", false),
+	    // wrong way around
+	    ("/foo/bar", "/foo/", false),
+	    // mixing absolute and relative
+	    ("", "/foo", false),
+	    ("foo/", "/foo/bar", false),
+	    // leading ".."s climb out, not in
+	    ("../", "../x", true),
+	    ("../", "../../", false),
+	    ("../", "../../x", false),
+	    ("", "../x", false),
+	    ("", "../", false),
+	];
+	for &(a, b, expected) in CASES.iter() {
+	    let a = Path::from_str_preverified(a);
+	    let b = Path::from_str_preverified(b);
+	    assert_eq!(a.is_ancestor_of(b), expected,
+		       "{:?}.is_ancestor_of({:?})", a, b);
+	    assert_eq!(b.is_descendant_of(a), expected,
+		       "{:?}.is_descendant_of({:?})", b, a);
+	}
+    }
+    #[test] fn eq_ignore_trailing_slash() {
+	const CASES: &[(&str, &str, bool)] = &[
+	    ("/foo", "/foo/", true),
+	    ("/foo/", "/foo/", true),
+	    ("/foo", "/foo", true),
+	    ("foo/bar", "foo/bar/", true),
+	    ("/", "/", true),
+	    ("/foo", "/foobar", false),
+	    ("/foo/", "/foobar/", false),
+	    ("/foo", "foo/", false),
+	    ("/", "", false),
+	];
+	for &(a, b, expected) in CASES.iter() {
+	    let a = Path::from_str_preverified(a);
+	    let b = Path::from_str_preverified(b);
+	    assert_eq!(a.eq_ignore_trailing_slash(b), expected, "{:?} {:?}", a, b);
+	    assert_eq!(b.eq_ignore_trailing_slash(a), expected, "{:?} {:?}", b, a);
+	}
+    }
+    #[test] fn dir_aware_order() {
+	const RAW: &[&str] = &[
+	    "/foo", "/foo-bar", "/foo/", "/foo/bar", "foo", "foo-bar/", "foo/",
+	];
+	const DIR_AWARE: &[&str] = &[
+	    "/foo/", "foo/", "/foo", "foo", "/foo/bar", "foo-bar/", "/foo-bar",
+	];
+	let mut paths: Vec<&Path> = DIR_AWARE.iter().rev()
+	    .map(|x| Path::from_str_preverified(x)).collect();
+	paths.sort();
+	assert_eq!(paths, RAW);
+	paths.sort_by(|a, b| a.dir_aware_cmp(b));
+	assert_eq!(paths, DIR_AWARE);
+	for a in paths.iter() {
+	    for b in paths.iter() {
+		assert_eq!(a.dir_aware_cmp(b) == Ordering::Equal, a == b,
+			   "{:?} {:?}", a, b);
+		assert_eq!(a.dir_aware_cmp(b), b.dir_aware_cmp(a).reverse());
+	    }
+	}
+    }
+    #[test] fn into_string() {
+	let path = PathBuf::from_str("/some/long/path/to/a/file");
+	let ptr = path.as_str().as_ptr();
+	let s = path.into_string();
+	assert_eq!(s, "/some/long/path/to/a/file");
+	assert_eq!(s.as_ptr(), ptr);
+    }
+    #[test] fn to_boxed() {
+	let path = Path::from_str_preverified("/some/dir/");
+	let boxed = path.to_boxed();
+	assert_eq!(&*boxed, path);
+	assert_eq!(boxed.as_str(), "/some/dir/");
+	assert!(boxed.is_directory());
+	assert_eq!(&*Path::from_str_preverified("").to_boxed(),
+		   Path::from_str_preverified(""));
+    }
+    #[test] fn max_escape() {
+	const CASES: &[(&str, usize, bool)] = &[
+	    ("a", 0, true),
+	    ("../a", 0, false),
+	    ("../a", 1, true),
+	    ("../../../", 3, true),
+	    ("../../../", 2, false),
+	    ("../../../../../../../../a", 7, false),
+	    ("../../../../../../../../a", 8, true),
+	    // counted after normalization
+	    ("a/../../b", 1, true),
+	    ("a/../../../b", 1, false),
+	    ("../a/b/../../../c", 1, false),
+	    ("../a/b/../../../c", 2, true),
+	    ("/a/b", 0, true),
+	];
+	for &(path, max, ok) in CASES.iter() {
+	    match Path::try_from_str_max_escape(path, max) {
+		Ok(x) => {
+		    assert!(ok, "{:?} with max {} should fail", path, max);
+		    assert_eq!(x, Path::from_str(path));
+		},
+		Err(x) => {
+		    assert!(!ok, "{:?} with max {} should pass", path, max);
+		    assert_eq!(x, PathFromStrError::TooManyParents);
+		},
+	    }
+	}
+	// other errors come through unchanged
+	assert_eq!(Path::try_from_str_max_escape("../NUL", 5),
+		   Err(PathFromStrError::ReservedName));
+    }
+    #[test] fn into_io_error() {
+	fn parse(s: &str) -> io::Result<PathBuf> {
+	    let mut ret = PathBuf::from_str("/");
+	    ret.try_join(&Path::try_from_str(s)?)?;
+	    Ok(ret)
+	}
+	let err = parse("foo/NUL").unwrap_err();
+	assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+	assert_eq!(err.to_string(),
+		   PathFromStrError::ReservedName.to_string());
+	let err = parse("../foo").unwrap_err();
+	assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+	assert_eq!(err.to_string(), PathJoinError::EscapedRoot.to_string());
+	assert_eq!(parse("foo").unwrap().as_str(), "/foo");
+    }
     #[test] fn normalize_good() {
 	const PAIRS_TO_CHECK: &[(&str, &str)] = &[
 	    ("foo/./bar", "foo/bar"),
@@ -629,6 +2004,18 @@ mod test {
 	    ("/foo/../bar", "/bar"),
 	    ("foo/../../bar", "../bar"),
 	    ("tesuto/COM0", "tesuto/COM0"),
+	    ("tesuto/LPT0.txt", "tesuto/LPT0.txt"),
+	    ("tesuto/CONIN", "tesuto/CONIN"),
+	    ("tesuto/CLOCK", "tesuto/CLOCK"),
+	    ("tesuto/COM\u{2074}", "tesuto/COM\u{2074}"),
+	    ("tesuto/con$", "tesuto/con$"),
+	    // a trailing `.` is the directory it's in
+	    ("foo/.", "foo/"),
+	    ("/.", "/"),
+	    ("../.", "../"),
+	    // combining marks are put in canonical order, not just decomposed
+	    ("\u{00e9}\u{0323}", "e\u{0323}\u{0301}"),
+	    ("e\u{0301}\u{0323}", "e\u{0323}\u{0301}"),
 	];
 	for (big, small) in PAIRS_TO_CHECK.iter() {
 	    assert_eq!(&Path::from_str(big).inner, *small);
@@ -637,10 +2024,26 @@ mod test {
     #[test] fn normalize_bad() {
 	const PAIRS_TO_CHECK: &[(&str, PathFromStrError)] = &[
 	    ("/foo/../../bar", PathFromStrError::EscapedRoot),
+	    ("/../bar", PathFromStrError::EscapedRoot),
+	    ("foo//bar", PathFromStrError::DoubleSlash),
+	    ("/foo//", PathFromStrError::DoubleSlash),
+	    ("///", PathFromStrError::DoubleSlash),
 	    ("asdf/NUL", PathFromStrError::ReservedName),
 	    ("asdf/COM4", PathFromStrError::ReservedName),
 	    ("asdf/COM5.test", PathFromStrError::ReservedName),
 	    ("asdf/jkl/Lpt6.printer", PathFromStrError::ReservedName),
+	    ("asdf/CONIN$", PathFromStrError::ReservedName),
+	    ("asdf/conout$.log", PathFromStrError::ReservedName),
+	    ("asdf/Clock$", PathFromStrError::ReservedName),
+	    ("asdf/COM\u{00B9}", PathFromStrError::ReservedName),
+	    ("asdf/lpt\u{00B3}.txt", PathFromStrError::ReservedName),
+	    ("C:\\foo", PathFromStrError::LooksLikeOsPath),
+	    ("c:/foo/bar", PathFromStrError::LooksLikeOsPath),
+	    ("D:", PathFromStrError::LooksLikeOsPath),
+	    ("\\\\server\\share\\foo", PathFromStrError::LooksLikeOsPath),
+	    // not at the start, so just the usual complaint
+	    ("foo/C:\\bar", PathFromStrError::InvalidChar),
+	    ("CD:/foo", PathFromStrError::InvalidChar),
 	];
 	for (big, small) in PAIRS_TO_CHECK.iter() {
 	    match Path::try_from_str(big) {
@@ -671,6 +2074,27 @@ mod test {
 	    assert_eq!(a.inner, *r);
 	}
     }
+    #[test] fn join_all() {
+	let mut base = PathBuf::from_str("/base/");
+	base.try_join_all(["a/", "b/", "c"].iter()
+			  .map(|x| Path::from_str_preverified(x))).unwrap();
+	assert_eq!(base.as_str(), "/base/a/b/c");
+	let mut base = PathBuf::from_str("/base/");
+	base.try_join_all([]).unwrap();
+	assert_eq!(base.as_str(), "/base/");
+	const FAILURES: &[(&[&str], PathJoinError)] = &[
+	    (&["a/", "/b/", "c"], PathJoinError::PathNotRelative),
+	    (&["a/", "../../../c"], PathJoinError::EscapedRoot),
+	    (&["a/", "b", "c"], PathJoinError::BasePathNotDir),
+	];
+	for &(parts, ref error) in FAILURES.iter() {
+	    let mut base = PathBuf::from_str("/base/");
+	    assert_eq!(base.try_join_all(parts.iter()
+					 .map(|x| Path::from_str_preverified(x)))
+		       .unwrap_err(), *error, "{:?}", parts);
+	    assert_eq!(base.as_str(), "/base/", "{:?}", parts);
+	}
+    }
     #[test] fn joins_bad() {
 	const JOINS_TO_CHECK: &[(&str, &str, PathJoinError)] = &[
 	    ("/test/toast", "natto", PathJoinError::BasePathNotDir),
@@ -697,6 +2121,8 @@ mod test {
 	    ("/asdf/../foxes", false),
 	    ("resume\u{0301}", true),
 	    ("resum\u{00e9}", false),
+	    ("e\u{0323}\u{0301}", true),
+	    ("e\u{0301}\u{0323}", false),
 	    ("../foxes", true),
 	];
 	for (src, kept) in PATHS_TO_CHECK.iter() {
@@ -717,4 +2143,491 @@ mod test {
 	assert_eq!(Path::from_str("resume\u{0301}"),
 		   Path::from_str("resum\u{00e9}"));
     }
+    #[test] fn split_first() {
+	const SPLITS_TO_CHECK: &[(&str, Option<(&str, &str)>)] = &[
+	    ("/a/b/c", Some(("/a/", "b/c"))),
+	    ("/a/b/c/", Some(("/a/", "b/c/"))),
+	    ("a/b/c", Some(("a/", "b/c"))),
+	    ("/a", Some(("/a", ""))),
+	    ("a/", Some(("a/", ""))),
+	    ("/", None),
+	    ("", None),
+	];
+	for (path, expected) in SPLITS_TO_CHECK.iter() {
+	    let result = Path::from_str_preverified(path).split_first()
+		.map(|(a, b)| (a.as_str(), b.as_str()));
+	    assert_eq!(result, *expected, "splitting {:?}", path);
+	}
+    }
+    #[test] fn split_last() {
+	const SPLITS_TO_CHECK: &[(&str, Option<(&str, &str)>)] = &[
+	    ("/a/b/c", Some(("/a/b/", "c"))),
+	    ("/a/b/c/", Some(("/a/b/", "c/"))),
+	    ("a/b/c", Some(("a/b/", "c"))),
+	    ("/a", Some(("/", "a"))),
+	    ("a/", Some(("", "a/"))),
+	    ("/", None),
+	    ("", None),
+	];
+	for (path, expected) in SPLITS_TO_CHECK.iter() {
+	    let result = Path::from_str_preverified(path).split_last()
+		.map(|(a, b)| (a.as_str(), b.as_str()));
+	    assert_eq!(result, *expected, "splitting {:?}", path);
+	}
+    }
+    #[test] fn bounded() {
+	const PATHS_TO_CHECK: &[(&str, usize, usize,
+				 Result<(), PathFromStrError>)] = &[
+	    ("/abcd/efgh", 4, 255, Ok(())),
+	    ("/abcd/efghi", 4, 255, Err(PathFromStrError::ComponentTooLong)),
+	    ("/abcde/efgh", 4, 255, Err(PathFromStrError::ComponentTooLong)),
+	    ("/abc/efg/", 255, 9, Ok(())),
+	    ("/abc/efgh/", 255, 9, Err(PathFromStrError::PathTooLong)),
+	    // limits apply after normalization
+	    ("/abc/../efg", 3, 4, Ok(())),
+	    ("resum\u{00e9}", 7, 255, Err(PathFromStrError::ComponentTooLong)),
+	    ("resum\u{00e9}", 8, 8, Ok(())),
+	];
+	for (path, component, total, expected) in PATHS_TO_CHECK.iter() {
+	    let result = Path::try_from_str_bounded(path, *component, *total)
+		.map(|_| ());
+	    assert_eq!(result, *expected, "checking {:?}", path);
+	}
+    }
+    #[test] fn renormalize() {
+	// Bypass the normalization that every public constructor performs.
+	let mut nfc = PathBuf { inner: "/resum\u{00e9}/".into() };
+	nfc.renormalize();
+	assert_eq!(nfc.inner, "/resume\u{0301}/");
+	let mut nfd = PathBuf::from_str("/resume\u{0301}/");
+	let (ptr, capacity) = (nfd.inner.as_ptr(), nfd.inner.capacity());
+	nfd.renormalize();
+	assert_eq!(nfd.inner, "/resume\u{0301}/");
+	assert_eq!((nfd.inner.as_ptr(), nfd.inner.capacity()), (ptr, capacity));
+    }
+    #[test] fn globs() {
+	const GLOBS_TO_CHECK: &[(&str, &str, bool)] = &[
+	    ("/a/b/c.png", "/a/**/*.png", true),
+	    ("/a/c.png", "/a/**/*.png", true),
+	    ("/a/b/d/c.png", "/a/**/*.png", true),
+	    ("/a/b/c.png", "/a/?/c.png", true),
+	    ("/a/bb/c.png", "/a/?/c.png", false),
+	    ("/a/b/c.png", "/a/b/*.jpg", false),
+	    ("/a/b/c.png", "/a/*.png", false),
+	    ("/a/b/c.png", "a/b/c.png", false),
+	    ("/a/b/", "/a/*/", true),
+	    ("/a/b/", "/a/*", false),
+	    ("/a/b", "/a/*/", false),
+	    ("/resume\u{0301}", "/r?sum\u{00e9}", true),
+	    ("/resume\u{0301}", "/resum?", false),
+	    ("/abcbcd", "/a*bc*d", true),
+	    ("/abcbd", "/a*bc*bc", false),
+	    ("/a/x/b/y/b/c", "/a/**/b/**/c", true),
+	    ("/a/x/b/y/c", "/a/**/b/**/b/**/c", false),
+	    ("/a/b/c", "/**/**", true),
+	    ("", "*", false),
+	];
+	for (path, pattern, expected) in GLOBS_TO_CHECK.iter() {
+	    let glob = Path::try_glob_from_str(pattern).unwrap();
+	    assert_eq!(Path::from_str(path).matches_glob(&glob), *expected,
+		       "matching {:?} against {:?}", path, pattern);
+	}
+	// A naive backtracking matcher takes forever on these
+	let name = "a".repeat(100) + "b";
+	let pattern = "*a".repeat(30);
+	let pattern = Path::try_glob_from_str(&pattern).unwrap();
+	assert!(!Path::from_str(&name).matches_glob(&pattern));
+	let path = ["a"; 100].join("/");
+	let path = Path::from_str(&path);
+	let pattern = ["**/a"; 30].join("/") + "/b";
+	let pattern = Path::try_glob_from_str(&pattern).unwrap();
+	assert!(!path.matches_glob(&pattern));
+    }
+    #[test] fn glob_patterns() {
+	assert_eq!(Path::try_glob_from_str("/a/**/*.png").unwrap().as_str(),
+		   "/a/**/*.png");
+	assert_eq!(Path::try_glob_from_str("/a/../?").unwrap().as_str(), "/?");
+	assert_eq!(Path::try_glob_from_str("/a/*:b"),
+		   Err(PathFromStrError::InvalidChar));
+	assert_eq!(Path::try_from_str("/a/*.png"),
+		   Err(PathFromStrError::InvalidChar));
+    }
+    #[test] fn ascii_lossy() {
+	const PATHS_TO_CHECK: &[(&str, &str)] = &[
+	    ("resum\u{00e9}/na\u{00ef}ve", "resume/naive"),
+	    ("/resume\u{0301}/", "/resume/"),
+	    ("/\u{65e5}\u{672c}/file", "/__/file"),
+	    ("/plain/ascii", "/plain/ascii"),
+	];
+	for (path, expected) in PATHS_TO_CHECK.iter() {
+	    assert_eq!(Path::from_str(path).to_ascii_lossy(), *expected);
+	}
+    }
+    #[test] fn url_path() {
+	const ROUND_TRIPS: &[(&str, &str)] = &[
+	    ("/my saves/slot 1", "/my%20saves/slot%201"),
+	    ("/re\u{0301}sume\u{0301}/", "/re%CC%81sume%CC%81/"),
+	    ("\u{65E5}\u{672C}/a~b_c-d.e", "%E6%97%A5%E6%9C%AC/a~b_c-d.e"),
+	    ("/100%#+&=", "/100%25%23%2B%26%3D"),
+	    ("", ""),
+	];
+	for &(path, url) in ROUND_TRIPS.iter() {
+	    let path = Path::from_str_preverified(path);
+	    assert_eq!(path.to_url_path(), url);
+	    assert_eq!(PathBuf::from_url_path(url).unwrap().as_str(),
+		       path.as_str());
+	}
+	// Decoding normalizes, and doesn't care about case or needless
+	// escapes
+	assert_eq!(PathBuf::from_url_path("/r%C3%A9sum%c3%a9/%61").unwrap()
+		   .as_str(), "/re\u{0301}sume\u{0301}/a");
+	assert_eq!(PathBuf::from_url_path("/a/%2e%2e/b").unwrap().as_str(),
+		   "/b");
+	const BAD: &[&str] = &["/50%", "/%4", "/%zz", "/a%2Fb", "/%FF",
+			       "/a%3Ab", "/NUL"];
+	for &url in BAD.iter() {
+	    assert!(PathBuf::from_url_path(url).is_err(), "{}", url);
+	}
+	assert_eq!(PathBuf::from_url_path("/a%2Fb"),
+		   Err(PathFromUrlError::InvalidPath
+		       (PathFromStrError::InvalidChar)));
+	assert_eq!(PathBuf::from_url_path("/%4"),
+		   Err(PathFromUrlError::BadEscape));
+    }
+    #[test] fn from_components() {
+	const GOOD: &[(bool, bool, &[&str], &str)] = &[
+	    (true, false, &["users", "1234", "save"], "/users/1234/save"),
+	    (true, true, &["users", "1234"], "/users/1234/"),
+	    (false, false, &["r\u{00E9}sum\u{00E9}.txt"],
+	     "re\u{0301}sume\u{0301}.txt"),
+	    (false, true, &["a"], "a/"),
+	    (true, true, &[], "/"),
+	    (false, false, &[], ""),
+	];
+	for &(absolute, is_dir, parts, expected) in GOOD.iter() {
+	    let path = PathBuf::from_components(absolute, is_dir,
+						parts.iter().copied());
+	    assert_eq!(path.unwrap().as_str(), expected);
+	}
+	const BAD: &[(&[&str], PathFromStrError)] = &[
+	    (&["users", "a/b"], PathFromStrError::InvalidChar),
+	    (&["users", ""], PathFromStrError::DoubleSlash),
+	    (&[".."], PathFromStrError::InvalidStartChar),
+	    (&["."], PathFromStrError::InvalidStartChar),
+	    (&["saves", "NUL"], PathFromStrError::ReservedName),
+	    (&["backup~"], PathFromStrError::InvalidEndChar),
+	];
+	for &(parts, ref expected) in BAD.iter() {
+	    let path = PathBuf::from_components(true, false,
+						parts.iter().copied());
+	    assert_eq!(path.as_ref(), Err(expected));
+	}
+    }
+    #[test] fn path_builder() {
+	let path = PathBuilder::absolute().dir("plugins").dir("fnord")
+	    .file("config.toml").build();
+	assert_eq!(path.unwrap().as_str(), "/plugins/fnord/config.toml");
+	let path = PathBuilder::absolute().dir("plugins").build();
+	assert_eq!(path.unwrap().as_str(), "/plugins/");
+	assert_eq!(PathBuilder::absolute().build().unwrap().as_str(), "/");
+	let path = PathBuilder::relative().dir("r\u{00E9}sum\u{00E9}s")
+	    .file("a.txt").build();
+	assert_eq!(path.unwrap().as_str(), "re\u{0301}sume\u{0301}s/a.txt");
+	assert_eq!(PathBuilder::relative().build().unwrap().as_str(), "");
+	// The first bad component is the one reported
+	let path = PathBuilder::absolute().dir("saves").dir("a/b")
+	    .dir("..").file("NUL").build();
+	assert_eq!(path, Err(PathFromStrError::InvalidChar));
+	assert_eq!(PathBuilder::absolute().dir("").build(),
+		   Err(PathFromStrError::DoubleSlash));
+	assert_eq!(PathBuilder::absolute().file("backup~").build(),
+		   Err(PathFromStrError::InvalidEndChar));
+    }
+    #[test] #[should_panic] fn path_builder_after_file() {
+	let _ = PathBuilder::absolute().file("config.toml").dir("oops");
+    }
+    #[test] fn map_components() {
+	const LOWERCASED: &[(&str, &str)] = &[
+	    ("/Maps/E1M1.map", "/maps/e1m1.map"),
+	    ("/Maps/Secret/", "/maps/secret/"),
+	    ("Maps/E1M1.MAP", "maps/e1m1.map"),
+	    ("../../Maps/", "../../maps/"),
+	    ("/", "/"),
+	    ("", ""),
+	    ("/\u{00C9}T\u{00C9}", "/e\u{0301}te\u{0301}"),
+	];
+	for &(path, expected) in LOWERCASED.iter() {
+	    let mut path = PathBuf::from_str(path);
+	    path.map_components(|x| x.to_lowercase()).unwrap();
+	    assert_eq!(path.as_str(), expected);
+	}
+	const BAD: &[(&str, PathFromStrError)] = &[
+	    ("a/b", PathFromStrError::InvalidChar),
+	    ("", PathFromStrError::DoubleSlash),
+	    ("..", PathFromStrError::InvalidStartChar),
+	    ("nul", PathFromStrError::ReservedName),
+	];
+	for &(result, ref expected) in BAD.iter() {
+	    let mut path = PathBuf::from_str("/maps/e1m1.map");
+	    let mut calls = 0;
+	    assert_eq!(path.map_components(|x| {
+		calls += 1;
+		if calls == 2 { result.to_string() } else { x.to_string() }
+	    }).as_ref(), Err(expected));
+	    assert_eq!(path.as_str(), "/maps/e1m1.map");
+	}
+    }
+    #[test] fn append_extension() {
+	const APPENDS_TO_CHECK: &[(&str, &str, Result<&str, PathFromStrError>)]
+	    = &[
+		("save.dat", "bak", Ok("save.dat.bak")),
+		("/saves/slot1.dat", "bak", Ok("/saves/slot1.dat.bak")),
+		("/saves/", "old", Ok("/saves.old/")),
+		("/saves/slot1", "r\u{00e9}sum\u{00e9}",
+		 Ok("/saves/slot1.re\u{0301}sume\u{0301}")),
+		("/saves/slot1", "", Err(PathFromStrError::InvalidEndChar)),
+		("/saves/slot1", "bak~", Err(PathFromStrError::InvalidEndChar)),
+		("/saves/slot1", "b/ak", Err(PathFromStrError::InvalidChar)),
+		("/", "bak", Err(PathFromStrError::InvalidStartChar)),
+		("", "bak", Err(PathFromStrError::InvalidStartChar)),
+		("../", "bak", Err(PathFromStrError::InvalidStartChar)),
+	    ];
+	for (path, ext, expected) in APPENDS_TO_CHECK.iter() {
+	    let mut buf = PathBuf::from_str(path);
+	    let result = buf.append_extension(ext);
+	    match expected {
+		Ok(expected) => {
+		    assert_eq!(result, Ok(()), "appending {:?} to {:?}",
+			       ext, path);
+		    assert_eq!(buf.inner, *expected);
+		},
+		Err(expected) => {
+		    assert_eq!(result.as_ref(), Err(expected),
+			       "appending {:?} to {:?}", ext, path);
+		    assert_eq!(buf.inner, *path);
+		},
+	    }
+	}
+    }
+    /// Edits that take a path back and forth across 24 bytes, where the
+    /// `compact_str` feature switches between inline and heap storage. Run
+    /// with and without the feature; the results must be the same.
+    #[test] fn short_and_long_paths() {
+	let mut path = PathBuf::from_str("/gfx/");
+	path.join(&Path::from_str("tiles/grass.png"));
+	assert_eq!(path.as_str(), "/gfx/tiles/grass.png");
+	path.append_extension("backup").unwrap();
+	assert_eq!(path.as_str(), "/gfx/tiles/grass.png.backup");
+	assert!(path.up_one_level());
+	path.join(&Path::from_str("a"));
+	assert_eq!(path.as_str(), "/gfx/tiles/a");
+	assert!(path.replace_prefix(&Path::from_str("/gfx/"),
+				    &Path::from_str("/graphics/hires/")));
+	assert_eq!(path.as_str(), "/graphics/hires/tiles/a");
+	path.make_file_into_dir();
+	path.join(&Path::from_str("r\u{00E9}sum\u{00E9}"));
+	assert_eq!(path.as_str(),
+		   "/graphics/hires/tiles/a/re\u{0301}sume\u{0301}");
+	while path.up_one_level() {}
+	assert_eq!(path.as_str(), "/");
+	path.shrink_to_fit();
+	path.reserve_exact(100);
+	assert!(path.inner.capacity() >= 101);
+	assert_eq!(path.clone().into_string(), "/");
+	let long = "/a/much/longer/path/that/is/not/going/to/fit/inline";
+	assert_eq!(PathBuf::from_str(long).into_string(), long);
+	assert_eq!(Path::from_str("/x").into_owned(), PathBuf::from_str("/x"));
+    }
+    #[test] fn would_escape() {
+	const CASES: &[(&str, &str, bool)] = &[
+	    ("../../x", "/a/", true),
+	    ("../x", "/a/b/", false),
+	    ("../../x", "/a/b/", false),
+	    ("../../../x", "/a/b/", true),
+	    ("../", "/", true),
+	    ("x", "/", false),
+	    ("", "/", false),
+	    ("../x", "/a", false),
+	    ("../../x", "/a", true),
+	    ("../../x", "a/", false),
+	    ("../../x", "", false),
+	    ("/x", "/", false),
+	];
+	for &(path, base, expected) in CASES.iter() {
+	    let path = Path::from_str_preverified(path);
+	    let base = Path::from_str_preverified(base);
+	    assert_eq!(path.would_escape(base), expected, "{:?} {:?}",
+		       path, base);
+	    // Agrees with actually joining
+	    if path.is_relative() {
+		let joined = base.to_owned().try_join(path).map(|_| ());
+		assert_eq!(joined == Err(PathJoinError::EscapedRoot), expected,
+			   "{:?} {:?}", path, base);
+	    }
+	}
+    }
+    #[test] fn common_prefix() {
+	const CASES: &[(&str, &str, &str)] = &[
+	    ("/gfx/tiles/grass.png", "/gfx/tiles/dirt.png", "/gfx/tiles/"),
+	    ("/gfx/tiles/grass.png", "/gfx/ui/button.png", "/gfx/"),
+	    ("/a/b/c/d", "/a/b/c/e/f/", "/a/b/c/"),
+	    ("/ab/c", "/abd/c", "/"),
+	    ("/abd/c", "/ab/c", "/"),
+	    ("/a/b", "/a/b/", "/a/"),
+	    ("/a/b/", "/a/b/", "/a/b/"),
+	    ("/a/b", "/a/b", "/a/"),
+	    ("/a", "/b", "/"),
+	    ("/", "/a/", "/"),
+	    ("a/b/c", "a/b/d", "a/b/"),
+	    ("a/b", "b/a", ""),
+	    ("a/", "/a/", ""),
+	    ("", "a/", ""),
+	];
+	for &(a, b, expected) in CASES.iter() {
+	    let a = Path::from_str_preverified(a);
+	    let b = Path::from_str_preverified(b);
+	    assert_eq!(a.common_prefix(b).as_str(), expected, "{:?} {:?}", a, b);
+	}
+    }
+    #[test] fn with_prefix_absolute() {
+	const PREFIXES_TO_CHECK: &[(&str, &str, Option<&str>)] = &[
+	    ("/foo/bar", "/foo/", Some("/bar")),
+	    ("/foo/bar/", "/foo/", Some("/bar/")),
+	    ("/foobar", "/foo/", None),
+	    ("/foo/bar", "/foo", None),
+	    // other is root
+	    ("/foo/bar", "/", Some("/foo/bar")),
+	    ("/", "/", Some("/")),
+	    ("foo/bar", "/", None),
+	    // other is empty
+	    ("/foo/bar", "", None),
+	    ("foo/bar", "", None),
+	    ("", "", None),
+	    // self is other
+	    ("/foo/", "/foo/", Some("/")),
+	    ("/foo", "/foo/", None),
+	    ("foo/", "foo/", Some("/")),
+	];
+	for (path, prefix, expected) in PREFIXES_TO_CHECK.iter() {
+	    let result = Path::from_str_preverified(path)
+		.with_prefix_absolute(Path::from_str_preverified(prefix))
+		.map(Path::as_str);
+	    assert_eq!(result, *expected, "removing {:?} from {:?}",
+		       prefix, path);
+	}
+    }
+    #[test] fn permissive() {
+	const CASES: &[(&str, bool)] = &[
+	    ("/data!", true),
+	    ("/save~", true),
+	    ("/dir^/file", true),
+	    ("/data.", false),
+	    ("/data ", false),
+	    ("/.data!", false),
+	];
+	for &(path, ok) in CASES.iter() {
+	    assert!(Path::try_from_str(path).is_err(), "{:?}", path);
+	    assert_eq!(Path::try_from_str_permissive(path).is_ok(), ok,
+		       "{:?}", path);
+	}
+    }
+    #[test] fn ends_with_component() {
+	const CASES: &[(&str, &str, bool)] = &[
+	    ("/a/b/config.toml", "config.toml", true),
+	    ("/a/b/config.toml", "onfig.toml", false),
+	    ("/a/b/config.toml", "b/config.toml", false),
+	    ("/a/b/config.toml", "config.toml/", false),
+	    ("/a/b/", "b/", true),
+	    ("/a/b/", "b", false),
+	    ("b", "b", true),
+	    ("/", "", false),
+	    ("/", "/", false),
+	    ("/r\u{00E9}sum\u{00E9}", "r\u{00E9}sum\u{00E9}", true),
+	];
+	for &(path, name, expected) in CASES.iter() {
+	    let path = Path::from_str(path);
+	    assert_eq!(path.ends_with_component(name), expected,
+		       "{:?} ending with {:?}", path, name);
+	}
+    }
+    #[test] fn utf8() {
+	assert_eq!(Path::from_str_preverified("/a/b").as_bytes(), b"/a/b");
+	assert_eq!(PathBuf::try_from_utf8(b"/caf\xC3\xA9").unwrap().as_str(),
+		   "/cafe\u{0301}");
+	assert!(matches!(PathBuf::try_from_utf8(b"/caf\xE9"),
+			 Err(PathFromUtf8Error::NotUtf8(_))));
+	assert_eq!(PathBuf::try_from_utf8(b"/a:b"),
+		   Err(PathFromUtf8Error::InvalidPath(
+		       PathFromStrError::InvalidChar)));
+    }
+    #[test] fn make_dir_into_file() {
+	let mut path = PathBuf::from_str("/foo/");
+	path.make_dir_into_file();
+	assert_eq!(path.as_str(), "/foo");
+	path.make_file_into_dir();
+	assert_eq!(path.as_str(), "/foo/");
+    }
+    #[test] #[should_panic]
+    fn make_dir_into_file_on_file() {
+	PathBuf::from_str("/foo").make_dir_into_file();
+    }
+    #[test] #[should_panic]
+    fn make_dir_into_file_on_root() {
+	PathBuf::from_str("/").make_dir_into_file();
+    }
+    #[test] #[should_panic]
+    fn make_dir_into_file_on_dotdot() {
+	PathBuf::from_str("../").make_dir_into_file();
+    }
+    #[test] fn from_os_like_str() {
+	const CASES: &[(&str, Result<&str, PathFromStrError>)] = &[
+	    ("foo\\bar", Ok("foo/bar")),
+	    ("foo\\..\\bar", Ok("bar")),
+	    ("\\foo\\bar\\", Ok("/foo/bar/")),
+	    ("foo/bar", Ok("foo/bar")),
+	    ("foo\\bar:baz", Err(PathFromStrError::InvalidChar)),
+	    ("foo\\.bar", Err(PathFromStrError::InvalidStartChar)),
+	    ("C:\\foo", Err(PathFromStrError::LooksLikeOsPath)),
+	    ("\\\\server\\share", Err(PathFromStrError::LooksLikeOsPath)),
+	];
+	for (input, expected) in CASES.iter() {
+	    let result = Path::from_os_like_str(input);
+	    assert_eq!(result.as_ref().map(|x| x.as_str()),
+		       expected.as_ref().map(|x| *x), "{:?}", input);
+	}
+    }
+    #[test] fn component() {
+	const CASES: &[(&str, &[&str])] = &[
+	    ("/users/1234/saves/quick", &["users", "1234", "saves", "quick"]),
+	    ("/users/1234/", &["users", "1234"]),
+	    ("relative/path", &["relative", "path"]),
+	    ("/", &[]),
+	    ("", &[]),
+	];
+	for &(path, components) in CASES.iter() {
+	    let path = Path::from_str_preverified(path);
+	    for i in 0 ..= components.len() {
+		assert_eq!(path.component(i).map(Path::as_str),
+			   components.get(i).copied(), "{:?}[{}]", path, i);
+		assert_eq!(path.component_from_end(i).map(Path::as_str),
+			   components.iter().rev().nth(i).copied(),
+			   "{:?}[-{}]", path, i);
+	    }
+	}
+    }
+    #[test] fn trim_trailing_slash() {
+	const CASES: &[(&str, &str)] = &[
+	    ("/foo/", "/foo"),
+	    ("/foo", "/foo"),
+	    ("/foo/bar/", "/foo/bar"),
+	    ("foo/", "foo"),
+	    ("/", "/"),
+	    ("", ""),
+	    ("../", "../"),
+	];
+	for &(path, expected) in CASES.iter() {
+	    assert_eq!(Path::from_str_preverified(path).trim_trailing_slash()
+		       .as_str(), expected);
+	}
+    }
 }
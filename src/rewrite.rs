@@ -0,0 +1,196 @@
+use crate::*;
+
+use std::io::{self, ErrorKind};
+
+/// A two-way mapping between the names a source's files have in the VFS
+/// ("virtual" names) and the names the source itself uses ("physical" names).
+/// See [`VFS::mount_with_rewrite`](struct.VFS.html#method.mount_with_rewrite).
+///
+/// Both directions work one component at a time; the names passed in and
+/// returned are single components, with no `/`.
+pub trait NameRewrite : Send + Sync {
+    /// Converts a virtual name into a physical one, for `open` and `update`.
+    /// Returns `None` if there's no such physical name, in which case the
+    /// file is treated as not existing.
+    fn to_physical(&self, name: &Path) -> Option<PathBuf>;
+    /// Converts a physical name into a virtual one, for `ls`. Returns `None`
+    /// to leave the name out of the listing, e.g. because `to_physical` could
+    /// never produce it.
+    fn to_virtual(&self, name: &Path) -> Option<PathBuf>;
+}
+
+/// A `NameRewrite` for a source that stores every name in lowercase. Any
+/// capitalization of a name finds the same file. Physical names that aren't
+/// all lowercase can't be reached, so they're left out of listings.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct Lowercase;
+
+impl NameRewrite for Lowercase {
+    fn to_physical(&self, name: &Path) -> Option<PathBuf> {
+        PathBuf::try_from_str(&name.as_str().to_lowercase()).ok()
+    }
+    fn to_virtual(&self, name: &Path) -> Option<PathBuf> {
+        if name.as_str().to_lowercase() == name.as_str() {
+            Some(name.to_owned())
+        }
+        else { None }
+    }
+}
+
+/// Wraps a source, rewriting every name that passes through.
+pub(crate) struct RewriteSource {
+    pub(crate) inner: Box<dyn VFSSource>,
+    pub(crate) rewrite: Box<dyn NameRewrite>,
+}
+
+impl RewriteSource {
+    fn to_physical(&self, path: &Path) -> io::Result<PathBuf> {
+        let mut ret = String::with_capacity(path.len());
+        if path.is_absolute() { ret.push('/') }
+        for component in path.components_typed() {
+            let name = self.rewrite.to_physical(component.name)
+                .ok_or_else(|| io::Error::from(ErrorKind::NotFound))?;
+            ret.push_str(name.as_str());
+            if component.is_directory { ret.push('/') }
+        }
+        PathBuf::try_from_str(&ret)
+            .map_err(|x| io::Error::new(ErrorKind::InvalidData, x))
+    }
+}
+
+impl VFSSource for RewriteSource {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        self.inner.open(&self.to_physical(path)?)
+    }
+    fn open_stream(&self, path: &Path) -> io::Result<Box<dyn DataStream>> {
+        self.inner.open_stream(&self.to_physical(path)?)
+    }
+    fn open_range(&self, path: &Path, start: u64, len: u64)
+                  -> io::Result<Box<dyn DataFile>> {
+        self.inner.open_range(&self.to_physical(path)?, start, len)
+    }
+    fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut ret: Vec<PathBuf> = self.inner.ls(&self.to_physical(path)?)?
+            .into_iter().filter_map(|entry| {
+                let name = entry.as_str().strip_suffix('/');
+                let mut ret = self.rewrite.to_virtual(
+                    Path::from_str_preverified(name.unwrap_or(&entry)))?;
+                if name.is_some() { ret.make_file_into_dir() }
+                Some(ret)
+            }).collect();
+        ret.sort_by(|a, b| a.dir_aware_cmp(b));
+        ret.dedup();
+        Ok(ret)
+    }
+    fn dir_exists(&self, dir: &Path) -> io::Result<bool> {
+        match self.to_physical(dir) {
+            Ok(x) => self.inner.dir_exists(&x),
+            Err(x) if x.kind() == ErrorKind::NotFound => Ok(false),
+            Err(x) => Err(x),
+        }
+    }
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        match self.to_physical(path) {
+            Ok(x) => self.inner.exists(&x),
+            Err(x) if x.kind() == ErrorKind::NotFound => Ok(false),
+            Err(x) => Err(x),
+        }
+    }
+    fn update(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.inner.update(&self.to_physical(path)?, data)
+    }
+    /// The inner source's spec would describe it without the rewrite, so
+    /// there's nothing to give.
+    fn spec(&self) -> Option<MountSpec> { None }
+    fn prepare_update(&self, path: &Path, data: &[u8])
+                      -> io::Result<Box<dyn PreparedUpdate + '_>> {
+        self.inner.prepare_update(&self.to_physical(path)?, data)
+    }
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+    fn physical_path(&self, path: &Path) -> Option<std::path::PathBuf> {
+        self.inner.physical_path(&self.to_physical(path).ok()?)
+    }
+    fn flush(&self) -> io::Result<()> {
+        self.inner.flush()
+    }
+    fn replace_dir(&self, dir: &Path, entries: &[(PathBuf, Vec<u8>)])
+                   -> io::Result<()> {
+        let entries = entries.iter().map(|(path, data)| {
+            Ok((self.to_physical(path)?, data.clone()))
+        }).collect::<io::Result<Vec<_>>>()?;
+        self.inner.replace_dir(&self.to_physical(dir)?, &entries)
+    }
+}
+
+#[cfg(all(test, feature = "rom"))]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    const fn fsp(i: &str) -> &Path { Path::from_str_preverified(i) }
+    #[test] fn lowercase() {
+        let vfs = VFS::new();
+        vfs.mount_with_rewrite(PathBuf::from_str("/"),
+                               Box::new(crate::rom::Source::new(&[
+            (fsp("/textures/"), b""),
+            (fsp("/textures/grass.png"), b"green"),
+            (fsp("/textures/Sky.png"), b"unreachable"),
+            (fsp("/README"), b"unreachable"),
+        ])), Box::new(Lowercase)).unwrap();
+        for path in ["/textures/grass.png", "/Textures/Grass.PNG",
+                     "/TEXTURES/GRASS.PNG"] {
+            assert_eq!(vfs.read_as_string(fsp(path)).unwrap(), "green",
+                       "{:?}", path);
+        }
+        assert_eq!(vfs.open(fsp("/textures/Sky.png")).err().unwrap().kind(),
+                   ErrorKind::NotFound);
+        assert_eq!(vfs.ls(fsp("/")).unwrap(),
+                   &[PathBuf::from_str("textures/")]);
+        assert_eq!(vfs.ls(fsp("/Textures/")).unwrap(),
+                   &[PathBuf::from_str("grass.png")]);
+    }
+    /// Records every write it's asked to make, and does nothing else.
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+    struct NoUpdate;
+    impl PreparedUpdate for NoUpdate {
+        fn commit(self: Box<Self>) -> io::Result<()> { Ok(()) }
+    }
+    impl VFSSource for Recorder {
+        fn open(&self, _: &Path) -> io::Result<Box<dyn DataFile>> {
+            Err(io::Error::from(ErrorKind::NotFound))
+        }
+        fn ls(&self, _: &Path) -> io::Result<Vec<PathBuf>> { Ok(vec![]) }
+        fn update(&self, path: &Path, _: &[u8]) -> io::Result<()> {
+            self.0.lock().unwrap().push(format!("update {}", path));
+            Ok(())
+        }
+        fn prepare_update(&self, path: &Path, _: &[u8])
+                          -> io::Result<Box<dyn PreparedUpdate + '_>> {
+            self.0.lock().unwrap().push(format!("prepare {}", path));
+            Ok(Box::new(NoUpdate))
+        }
+        fn replace_dir(&self, dir: &Path, entries: &[(PathBuf, Vec<u8>)])
+                       -> io::Result<()> {
+            for (path, _) in entries.iter() {
+                self.0.lock().unwrap().push(format!("replace {}{}", dir,
+                                                    path));
+            }
+            Ok(())
+        }
+    }
+    #[test] fn lowercase_writes() {
+        let log = Arc::default();
+        let vfs = VFS::new();
+        vfs.mount_with_rewrite(PathBuf::from_str("/"),
+                               Box::new(Recorder(Arc::clone(&log))),
+                               Box::new(Lowercase)).unwrap();
+        let mut transaction = vfs.transaction();
+        transaction.update(fsp("/Saves/Slot1.sav"), b"");
+        transaction.commit().unwrap();
+        vfs.replace_dir(fsp("/Saves/"), [(PathBuf::from_str("Slot2.sav"),
+                                          vec![])]).unwrap();
+        assert_eq!(*log.lock().unwrap(), ["prepare /saves/slot1.sav",
+                                          "replace /saves/slot2.sav"]);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::*;
+
+use std::fmt::{self, Formatter};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+/// Paths are serialized as strings for human-readable formats (JSON, TOML,
+/// etc.), and as their UTF-8 bytes for compact ones (bincode, postcard,
+/// etc.), which saves some formats from escaping or re-validating them.
+impl Serialize for Path {
+    fn serialize<S: Serializer>(&self, serializer: S)
+                                -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(self.as_str())
+        }
+        else {
+            serializer.serialize_bytes(self.as_bytes())
+        }
+    }
+}
+
+impl Serialize for PathBuf {
+    fn serialize<S: Serializer>(&self, serializer: S)
+                                -> Result<S::Ok, S::Error> {
+        self.as_path().serialize(serializer)
+    }
+}
+
+/// Accepts either a string or UTF-8 bytes, whatever the format. The path is
+/// validated (and normalized) the same way `PathBuf::try_from_str` does.
+impl<'de> Deserialize<'de> for PathBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+                                         -> Result<PathBuf, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(PathVisitor)
+        }
+        else {
+            deserializer.deserialize_bytes(PathVisitor)
+        }
+    }
+}
+
+struct PathVisitor;
+
+impl de::Visitor<'_> for PathVisitor {
+    type Value = PathBuf;
+    fn expecting(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        write!(fmt, "a Psilo-VFS path")
+    }
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<PathBuf, E> {
+        PathBuf::try_from_str(v).map_err(E::custom)
+    }
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<PathBuf, E> {
+        PathBuf::try_from_utf8(v).map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test] fn json() {
+        let path = PathBuf::from_str("/saves/r\u{00E9}sum\u{00E9}");
+        let json = serde_json::to_string(&path).unwrap();
+        assert_eq!(json, "\"/saves/re\u{0301}sume\u{0301}\"");
+        let back: PathBuf = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, path);
+        // Normalized on the way in
+        let back: PathBuf = serde_json::from_str("\"/saves/./a/../b\"")
+            .unwrap();
+        assert_eq!(back.as_str(), "/saves/b");
+        assert!(serde_json::from_str::<PathBuf>("\"/a:b\"").is_err());
+    }
+    #[test] fn bincode() {
+        let path = PathBuf::from_str("/saves/slot1");
+        let encoded = bincode::serialize(&path).unwrap();
+        // A length, then the bytes
+        assert_eq!(&encoded[8..], b"/saves/slot1");
+        let back: PathBuf = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(back, path);
+        // A string is encoded the same way, so it's accepted too
+        let encoded = bincode::serialize("/a/./b").unwrap();
+        let back: PathBuf = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(back.as_str(), "/a/b");
+        let encoded = bincode::serialize(&b"/caf\xE9"[..]).unwrap();
+        assert!(bincode::deserialize::<PathBuf>(&encoded).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::*;
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Cursor, ErrorKind, Write},
+    sync::{Arc, Mutex},
+};
+use tempfile::NamedTempFile;
+
+enum Entry {
+    /// Small enough to keep in memory.
+    Memory(Arc<[u8]>),
+    /// Too big to keep in memory, so it lives in a temporary file instead.
+    Spilled(NamedTempFile),
+}
+
+/// A writable source that starts out empty and holds whatever is written to
+/// it. Small files are kept in memory. Files larger than a threshold are
+/// "spilled" into temporary files on disk, so that large generated data
+/// doesn't have to stay in memory. Nothing survives the source being dropped;
+/// the temporary files are deleted when the source is (or when the file is
+/// replaced).
+pub struct Source {
+    threshold: usize,
+    files: Mutex<BTreeMap<PathBuf, Entry>>,
+}
+
+impl Source {
+    /// Creates a new, empty source. Files up to `threshold` bytes long will be
+    /// kept in memory, larger files will be spilled to disk.
+    pub fn new(threshold: usize) -> Source {
+        Source { threshold, files: Mutex::new(BTreeMap::new()) }
+    }
+}
+
+/// Returns true if any of the given files is inside the given directory.
+fn any_inside(files: &BTreeMap<PathBuf, Entry>, dir: &Path) -> bool {
+    files.range(dir.to_owned()..).next()
+        .map(|(x, _)| x.starts_with(dir.as_str())).unwrap_or(false)
+}
+
+impl VFSSource for Source {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        let files = self.files.lock().unwrap();
+        match files.get(path) {
+            Some(Entry::Memory(data))
+                => Ok(Box::new(Cursor::new(data.clone()))),
+            Some(Entry::Spilled(file)) => Ok(Box::new(file.reopen()?)),
+            None => {
+                let mut dir = path.to_owned();
+                dir.make_file_into_dir();
+                if any_inside(&files, &dir) {
+                    Err(io::Error::from(ErrorKind::IsADirectory))
+                }
+                else {
+                    Err(io::Error::from(ErrorKind::NotFound))
+                }
+            },
+        }
+    }
+    fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        debug_assert!(path.is_absolute() && path.is_directory());
+        let files = self.files.lock().unwrap();
+        let mut ret: Vec<PathBuf> = vec![];
+        // Everything inside this directory is contiguous in the map.
+        for (file, _) in files.range(path.to_owned()..) {
+            let rest = match file.as_str().strip_prefix(path.as_str()) {
+                Some(x) => x,
+                None => break,
+            };
+            let name = match rest.find('/') {
+                Some(i) => &rest[..=i],
+                None => rest,
+            };
+            if ret.last().map(|x| x.as_str() != name).unwrap_or(true) {
+                ret.push(PathBuf::from_str(name));
+            }
+        }
+        if ret.is_empty() && path.as_str() != "/" {
+            let file = &path.as_str()[..path.len()-1];
+            if files.contains_key(Path::from_str_preverified(file)) {
+                return Err(io::Error::from(ErrorKind::NotADirectory))
+            }
+            return Err(io::Error::from(ErrorKind::NotFound))
+        }
+        ret.sort_by(|a, b| a.dir_aware_cmp(b));
+        Ok(ret)
+    }
+    fn update(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        debug_assert!(path.is_absolute() && !path.is_directory());
+        let entry = if data.len() > self.threshold {
+            let mut file = NamedTempFile::new()?;
+            file.write_all(data)?;
+            file.flush()?;
+            Entry::Spilled(file)
+        }
+        else {
+            Entry::Memory(data.into())
+        };
+        let mut files = self.files.lock().unwrap();
+        let mut dir = path.to_owned();
+        dir.make_file_into_dir();
+        if any_inside(&files, &dir) {
+            return Err(io::Error::from(ErrorKind::IsADirectory))
+        }
+        let mut parent = path.parent();
+        while parent.as_str() != "/" {
+            let file = &parent.as_str()[..parent.len()-1];
+            if files.contains_key(Path::from_str_preverified(file)) {
+                return Err(io::Error::from(ErrorKind::NotADirectory))
+            }
+            parent = parent.parent();
+        }
+        files.insert(path.to_owned(), entry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    const fn fsp(i: &str) -> &Path { Path::from_str_preverified(i) }
+    #[test] fn spill() {
+        let small = b"short and sweet";
+        let big = [0x5Au8; 1000];
+        let source = Source::new(small.len());
+        source.update(fsp("/small"), small).unwrap();
+        source.update(fsp("/deep/inside/big"), &big).unwrap();
+        let spilled_path = match source.files.lock().unwrap()
+            .get(fsp("/deep/inside/big")) {
+                Some(Entry::Spilled(file)) => file.path().to_owned(),
+                _ => panic!("big file should have been spilled"),
+            };
+        assert!(matches!(source.files.lock().unwrap().get(fsp("/small")),
+                         Some(Entry::Memory(..))));
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/scratch/"), Box::new(source)).unwrap();
+        assert_eq!(vfs.read_as_bytes(fsp("/scratch/small")).unwrap(), small);
+        assert_eq!(vfs.read_as_bytes(fsp("/scratch/deep/inside/big")).unwrap(),
+                   big);
+        assert_eq!(vfs.ls(fsp("/scratch/")).unwrap(),
+                   &[PathBuf::from_str("deep/"), PathBuf::from_str("small")]);
+        assert_eq!(vfs.ls(fsp("/scratch/deep/")).unwrap(),
+                   &[PathBuf::from_str("inside/")]);
+        assert_eq!(vfs.ls(fsp("/scratch/small/")).unwrap_err().kind(),
+                   ErrorKind::NotADirectory);
+        assert_eq!(vfs.open(fsp("/scratch/deep")).err().unwrap().kind(),
+                   ErrorKind::IsADirectory);
+        assert_eq!(vfs.update(fsp("/scratch/small/nope"), b"").unwrap_err()
+                   .kind(), ErrorKind::NotADirectory);
+        assert!(spilled_path.exists());
+        drop(vfs);
+        assert!(!spilled_path.exists());
+    }
+}
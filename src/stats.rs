@@ -0,0 +1,156 @@
+use crate::*;
+
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{Arc, Mutex},
+};
+
+/// Wraps a source, counting the calls made to it for each path, such as to
+/// find out which assets a game actually loads. Everything is passed on to
+/// the wrapped source unchanged.
+///
+/// Every call is counted, whether it succeeds or not, so a VFS probing this
+/// mount for a file it doesn't have counts as an open. Paths are the ones
+/// the source sees, relative to its own mount point.
+pub struct Source {
+    inner: Box<dyn VFSSource>,
+    stats: AccessStats,
+}
+
+/// How many times each kind of call was made for one path. See
+/// [`StatsSource`](struct.StatsSource.html).
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct AccessCounts {
+    /// Calls to `open`, `open_stream`, and `open_range`.
+    pub opens: u64,
+    /// Calls to `ls`.
+    pub listings: u64,
+    /// Calls to `update` and `prepare_update`, and, for a directory,
+    /// `replace_dir`.
+    pub updates: u64,
+}
+
+/// A handle on the counts kept by a [`StatsSource`](struct.StatsSource.html),
+/// which can still be used after the source has been mounted.
+#[derive(Clone,Default)]
+pub struct AccessStats(Arc<Mutex<BTreeMap<PathBuf, AccessCounts>>>);
+
+impl AccessStats {
+    /// Returns the counts so far, for every path that has been used.
+    pub fn snapshot(&self) -> BTreeMap<PathBuf, AccessCounts> {
+        self.0.lock().unwrap().clone()
+    }
+    /// Forgets all the counts so far.
+    pub fn reset(&self) {
+        self.0.lock().unwrap().clear()
+    }
+    fn count(&self, path: &Path, f: impl FnOnce(&mut AccessCounts)) {
+        let mut counts = self.0.lock().unwrap();
+        match counts.get_mut(path) {
+            Some(x) => f(x),
+            None => {
+                let mut x = AccessCounts::default();
+                f(&mut x);
+                counts.insert(path.to_owned(), x);
+            },
+        }
+    }
+}
+
+impl Source {
+    /// Wraps the given source, with all counts starting at zero.
+    pub fn new(inner: Box<dyn VFSSource>) -> Source {
+        Source { inner, stats: AccessStats::default() }
+    }
+    /// Returns a handle on this source's counts. Get one of these before
+    /// mounting the source.
+    pub fn stats(&self) -> AccessStats {
+        self.stats.clone()
+    }
+    /// Returns the counts so far, for every path that has been used.
+    pub fn snapshot(&self) -> BTreeMap<PathBuf, AccessCounts> {
+        self.stats.snapshot()
+    }
+}
+
+impl VFSSource for Source {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        self.stats.count(path, |x| x.opens += 1);
+        self.inner.open(path)
+    }
+    fn open_stream(&self, path: &Path) -> io::Result<Box<dyn DataStream>> {
+        self.stats.count(path, |x| x.opens += 1);
+        self.inner.open_stream(path)
+    }
+    fn open_range(&self, path: &Path, start: u64, len: u64)
+                  -> io::Result<Box<dyn DataFile>> {
+        self.stats.count(path, |x| x.opens += 1);
+        self.inner.open_range(path, start, len)
+    }
+    fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        self.stats.count(path, |x| x.listings += 1);
+        self.inner.ls(path)
+    }
+    fn dir_exists(&self, dir: &Path) -> io::Result<bool> {
+        self.inner.dir_exists(dir)
+    }
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        self.inner.exists(path)
+    }
+    fn update(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.stats.count(path, |x| x.updates += 1);
+        self.inner.update(path, data)
+    }
+    fn prepare_update(&self, path: &Path, data: &[u8])
+                      -> io::Result<Box<dyn PreparedUpdate + '_>> {
+        self.stats.count(path, |x| x.updates += 1);
+        self.inner.prepare_update(path, data)
+    }
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+    fn physical_path(&self, path: &Path) -> Option<std::path::PathBuf> {
+        self.inner.physical_path(path)
+    }
+    fn flush(&self) -> io::Result<()> {
+        self.inner.flush()
+    }
+    fn replace_dir(&self, dir: &Path, entries: &[(PathBuf, Vec<u8>)])
+                   -> io::Result<()> {
+        self.stats.count(dir, |x| x.updates += 1);
+        self.inner.replace_dir(dir, entries)
+    }
+}
+
+#[cfg(all(test, feature = "rom"))]
+mod test {
+    use super::*;
+    const fn fsp(i: &str) -> &Path { Path::from_str_preverified(i) }
+    #[test] fn stats() {
+        let source = Source::new(Box::new(crate::rom::Source::new(&[
+            (fsp("/maps/"), b""),
+            (fsp("/maps/e1m1.map"), b"first map"),
+            (fsp("/readme"), b"read me"),
+        ])));
+        let stats = source.stats();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/game/"), Box::new(source)).unwrap();
+        for _ in 0 .. 2 {
+            vfs.read_as_bytes(fsp("/game/maps/e1m1.map")).unwrap();
+        }
+        vfs.open_stream(fsp("/game/readme")).unwrap();
+        assert!(vfs.open(fsp("/game/nope")).is_err());
+        vfs.ls(fsp("/game/maps/")).unwrap();
+        let counts = |opens, listings| AccessCounts { opens, listings,
+                                                      updates: 0 };
+        assert_eq!(stats.snapshot().into_iter().collect::<Vec<_>>(), [
+            (PathBuf::from_str("/maps/"), counts(0, 1)),
+            (PathBuf::from_str("/maps/e1m1.map"), counts(2, 0)),
+            (PathBuf::from_str("/nope"), counts(1, 0)),
+            (PathBuf::from_str("/readme"), counts(1, 0)),
+        ]);
+        stats.reset();
+        assert!(stats.snapshot().is_empty());
+    }
+}
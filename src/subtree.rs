@@ -0,0 +1,96 @@
+use crate::*;
+
+use std::io;
+
+/// Wraps a source, showing only one directory of it, as if that directory
+/// were the source's root.
+/// See [`VFS::mount_subtree`](struct.VFS.html#method.mount_subtree).
+pub(crate) struct SubtreeSource {
+    pub(crate) inner: Box<dyn VFSSource>,
+    /// An absolute directory in `inner`.
+    pub(crate) subpath: PathBuf,
+}
+
+impl SubtreeSource {
+    fn to_inner(&self, path: &Path) -> PathBuf {
+        debug_assert!(path.is_absolute());
+        let mut ret = String::with_capacity(self.subpath.len() + path.len());
+        ret.push_str(self.subpath.as_str());
+        ret.push_str(&path.as_str()[1..]);
+        Path::from_str_preverified(&ret).to_owned()
+    }
+}
+
+impl VFSSource for SubtreeSource {
+    fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        self.inner.open(&self.to_inner(path))
+    }
+    fn open_stream(&self, path: &Path) -> io::Result<Box<dyn DataStream>> {
+        self.inner.open_stream(&self.to_inner(path))
+    }
+    fn open_range(&self, path: &Path, start: u64, len: u64)
+                  -> io::Result<Box<dyn DataFile>> {
+        self.inner.open_range(&self.to_inner(path), start, len)
+    }
+    fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        self.inner.ls(&self.to_inner(path))
+    }
+    fn dir_exists(&self, dir: &Path) -> io::Result<bool> {
+        self.inner.dir_exists(&self.to_inner(dir))
+    }
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        self.inner.exists(&self.to_inner(path))
+    }
+    fn update(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.inner.update(&self.to_inner(path), data)
+    }
+    fn prepare_update(&self, path: &Path, data: &[u8])
+                      -> io::Result<Box<dyn PreparedUpdate + '_>> {
+        self.inner.prepare_update(&self.to_inner(path), data)
+    }
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+    fn physical_path(&self, path: &Path) -> Option<std::path::PathBuf> {
+        self.inner.physical_path(&self.to_inner(path))
+    }
+    fn flush(&self) -> io::Result<()> {
+        self.inner.flush()
+    }
+    fn replace_dir(&self, dir: &Path, entries: &[(PathBuf, Vec<u8>)])
+                   -> io::Result<()> {
+        self.inner.replace_dir(&self.to_inner(dir), entries)
+    }
+}
+
+#[cfg(all(test, feature = "rom"))]
+mod test {
+    use super::*;
+    use std::io::ErrorKind;
+    const fn fsp(i: &str) -> &Path { Path::from_str_preverified(i) }
+    #[test] fn subtree() {
+        let vfs = VFS::new();
+        vfs.mount_subtree(PathBuf::from_str("/maps/"),
+                          Box::new(crate::rom::Source::new(&[
+            (fsp("/levels/"), b""),
+            (fsp("/levels/1.map"), b"first level"),
+            (fsp("/levels/secret/"), b""),
+            (fsp("/levels/secret/2.map"), b"second level"),
+            (fsp("/1.map"), b"not in the subtree"),
+        ])), fsp("/levels/")).unwrap();
+        assert_eq!(vfs.read_as_string(fsp("/maps/1.map")).unwrap(),
+                   "first level");
+        assert_eq!(vfs.read_as_string(fsp("/maps/secret/2.map")).unwrap(),
+                   "second level");
+        assert_eq!(vfs.open(fsp("/maps/levels/1.map")).err().unwrap().kind(),
+                   ErrorKind::NotFound);
+        assert_eq!(vfs.open(fsp("/1.map")).err().unwrap().kind(),
+                   ErrorKind::NotFound);
+        assert_eq!(vfs.ls(fsp("/maps/")).unwrap(),
+                   &[PathBuf::from_str("1.map"), PathBuf::from_str("secret/")]);
+        assert_eq!(vfs.mount_subtree(PathBuf::from_str("/"),
+                                     Box::new(crate::rom::Source::new(&[])),
+                                     fsp("levels/")).unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+    }
+}
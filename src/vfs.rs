@@ -1,33 +1,782 @@
 use crate::*;
 
 use std::{
+    borrow::Cow,
     cmp::Ordering,
-    io, io::{Cursor, ErrorKind, Seek, Read},
+    collections::{BTreeMap, hash_map::DefaultHasher},
+    hash::{Hash, Hasher},
+    io, io::{Cursor, ErrorKind, Seek, SeekFrom, Read},
     marker::Unpin,
-    sync::{Arc, RwLock},
+    ops::{BitAnd, BitOr, BitOrAssign},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock},
+    time::Duration,
 };
+use unicode_normalization::UnicodeNormalization;
 
 pub trait VFSSource : Send {
     /// Opens a given file for reading.
     ///
     /// Takes: an absolute path to a file.
+    ///
+    /// A source whose files can't be seeked in should implement `open_stream`
+    /// instead, and either read the whole file into memory here, or return
+    /// `Unsupported`.
     fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>>;
+    /// Opens a given file for reading from start to finish, with no seeking.
+    ///
+    /// Takes: an absolute path to a file.
+    ///
+    /// The default calls `open`. Sources whose files are naturally streams
+    /// (such as network responses) should override this, so that a caller
+    /// that only wants to read straight through doesn't have to wait for the
+    /// whole file.
+    fn open_stream(&self, path: &Path) -> io::Result<Box<dyn DataStream>> {
+        Ok(self.open(path)?)
+    }
+    /// Opens part of a given file: `len` bytes, starting `start` bytes in,
+    /// or fewer if the file ends first. The returned file holds only that
+    /// part, so its position 0 is `start` in the whole file. Used by
+    /// [`VFS::open_range`](struct.VFS.html#method.open_range).
+    ///
+    /// Takes: an absolute path to a file.
+    ///
+    /// The default opens the whole file, seeks to `start`, and reads the
+    /// part into memory. Sources that can get at part of a file directly,
+    /// such as archives that store files uncompressed, should override this.
+    fn open_range(&self, path: &Path, start: u64, len: u64)
+                  -> io::Result<Box<dyn DataFile>> {
+        let mut file = self.open(path)?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut buf = vec![];
+        file.take(len).read_to_end(&mut buf)?;
+        Ok(Box::new(Cursor::new(buf)))
+    }
     /// List files under a given directory.
     ///
     /// Takes: an absolute path to a directory.
     ///
-    /// Returns: one or more single-component relative paths.
+    /// Returns: one or more single-component relative paths, sorted by name,
+    /// ignoring any trailing `/`. (If a directory and a file have the same
+    /// name, the directory comes first. This is
+    /// [`Path::dir_aware_cmp`](struct.Path.html#method.dir_aware_cmp) order.)
+    /// The VFS merges the listings of several sources in one pass, relying on
+    /// this order. It will sort an unsorted listing before merging it, but
+    /// that's wasted effort.
     fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+    /// Tells whether a given directory exists, as cheaply as the source can.
+    /// The VFS asks before an update, so that an update into a missing
+    /// directory fails with `NotFound`.
+    ///
+    /// Takes: an absolute path to a directory.
+    ///
+    /// Returns: `false` if nothing is there. An error if something other
+    /// than a directory is there, or if the source can't tell; either way,
+    /// the VFS tries the update anyway, and lets the source report the
+    /// problem.
+    ///
+    /// The default lists the directory with `ls`. Sources that can check more
+    /// cheaply should override this, and sources that wrap other sources
+    /// should pass it along.
+    fn dir_exists(&self, dir: &Path) -> io::Result<bool> {
+        match self.ls(dir) {
+            Ok(_) => Ok(true),
+            Err(x) if x.kind() == ErrorKind::NotFound => Ok(false),
+            Err(x) => Err(x),
+        }
+    }
+    /// Tells whether `open` would find anything at a given path, as cheaply
+    /// as the source can, without reading the file. The VFS asks to find out
+    /// which mount a file would come from.
+    ///
+    /// Takes: an absolute path to a file.
+    ///
+    /// Returns: `false` if `open` would fail with `NotFound`, `true` if it
+    /// would do anything else (including fail because a directory is there).
+    /// An error if the source can't tell.
+    ///
+    /// The default looks for the file's name in a listing of its directory.
+    /// Sources that can check more cheaply should override this, and sources
+    /// that wrap other sources should pass it along.
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        let name = match path.component_from_end(0) {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+        match self.ls(path.parent()) {
+            // (a directory of the same name counts)
+            Ok(x) => Ok(x.iter().any(|x| x.eq_ignore_trailing_slash(name))),
+            Err(x) if x.kind() == ErrorKind::NotFound => Ok(false),
+            Err(x) => Err(x),
+        }
+    }
     /// Atomically replace the contents of a given file.
     ///
     /// Takes: an absolute path to a file.
     fn update(&self, path: &Path, data: &[u8]) -> io::Result<()>;
+    /// Describes this source, so that an equivalent one can be created later.
+    ///
+    /// Returns: `None` if this source can't be described by a `MountSpec`
+    /// (the default).
+    fn spec(&self) -> Option<MountSpec> { None }
+    /// Prepares to replace the contents of a given file, without making the
+    /// change visible until the returned `PreparedUpdate` is committed. Used
+    /// by [`Transaction`](struct.Transaction.html).
+    ///
+    /// Takes: an absolute path to a file.
+    ///
+    /// The default holds onto a copy of the data and calls `update` when
+    /// committed. That can't catch any problems ahead of time, so sources that
+    /// can do the bulk of the work up front should override this.
+    fn prepare_update(&self, path: &Path, data: &[u8])
+                      -> io::Result<Box<dyn PreparedUpdate + '_>> {
+        Ok(Box::new(BufferedUpdate { source: self, path: path.to_owned(),
+                                     data: data.to_vec() }))
+    }
+    /// Tells what this source can do, so that callers can check before
+    /// attempting something.
+    ///
+    /// The default is `READ | WRITE | LIST`, since those are the operations
+    /// every source implements (even if only by returning an error).
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::READ | Capabilities::WRITE | Capabilities::LIST
+    }
+    /// Finds where a given file really lives on the host filesystem, for
+    /// handing to something outside the VFS.
+    ///
+    /// Takes: an absolute path to a file.
+    ///
+    /// Returns: the path that `open` would read, if the file exists and is a
+    /// plain file on disk whose contents are exactly what `open` would give.
+    /// `None` otherwise (the default).
+    fn physical_path(&self, _path: &Path) -> Option<std::path::PathBuf> {
+        None
+    }
+    /// Makes sure that every write this source has accepted so far has
+    /// really happened, for sources that buffer writes. Called by
+    /// [`VFS::flush_all`](struct.VFS.html#method.flush_all), e.g. before the
+    /// program exits.
+    ///
+    /// The default does nothing, which is right for any source whose
+    /// `update` has finished its work by the time it returns. Sources that
+    /// wrap other sources should pass it along.
+    fn flush(&self) -> io::Result<()> { Ok(()) }
+    /// Replaces everything in a given directory with the given files, as
+    /// atomically as the source can manage. Used by
+    /// [`VFS::replace_dir`](struct.VFS.html#method.replace_dir).
+    ///
+    /// Takes: an absolute path to a directory, and the new files, as paths
+    /// relative to it.
+    ///
+    /// The default is not atomic: it writes each file with `update`, in
+    /// order. It can't remove anything, so if the directory has any file
+    /// that isn't being replaced, it returns `Unsupported` without writing
+    /// anything. Sources that can do better should override this.
+    fn replace_dir(&self, dir: &Path, entries: &[(PathBuf, Vec<u8>)])
+                   -> io::Result<()> {
+        let mut existing = vec![];
+        match walk_source(self, dir, &PathBuf::new(), &mut existing) {
+            Err(x) if x.kind() == ErrorKind::NotFound => (),
+            x => x?,
+        }
+        if let Some(leftover) = existing.iter()
+            .find(|x| !entries.iter().any(|(path, _)| path == *x)) {
+                let err = format!("can't remove {:?} from {:?}", leftover,
+                                  dir);
+                return Err(io::Error::new(ErrorKind::Unsupported, err))
+            }
+        for (path, data) in entries.iter() {
+            let mut full = dir.to_owned();
+            full.join(path);
+            self.update(&full, data)?;
+        }
+        Ok(())
+    }
+}
+
+/// A set of operations that a source supports. See
+/// [`VFSSource::capabilities`](trait.VFSSource.html#method.capabilities) and
+/// [`VFS::capabilities_at`](struct.VFS.html#method.capabilities_at).
+///
+/// Combine with `|`, intersect with `&`.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq,Hash)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// Files can be opened.
+    pub const READ: Capabilities = Capabilities(1 << 0);
+    /// Files can be updated.
+    pub const WRITE: Capabilities = Capabilities(1 << 1);
+    /// Directories can be listed.
+    pub const LIST: Capabilities = Capabilities(1 << 2);
+    /// Files can be deleted.
+    pub const DELETE: Capabilities = Capabilities(1 << 3);
+    /// Files can be renamed.
+    pub const RENAME: Capabilities = Capabilities(1 << 4);
+    /// Changes to files can be watched for.
+    pub const WATCH: Capabilities = Capabilities(1 << 5);
+    /// No capabilities at all.
+    pub const fn empty() -> Capabilities { Capabilities(0) }
+    /// Returns true if every capability in `other` is also in `self`.
+    pub const fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+    /// Returns true if there are no capabilities.
+    pub const fn is_empty(self) -> bool { self.0 == 0 }
+    /// Same as `self | other`, but usable in a `const`.
+    pub const fn union(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 | other.0)
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Capabilities;
+    fn bitor(self, other: Capabilities) -> Capabilities {
+        self.union(other)
+    }
+}
+
+impl BitOrAssign for Capabilities {
+    fn bitor_assign(&mut self, other: Capabilities) { self.0 |= other.0 }
+}
+
+impl BitAnd for Capabilities {
+    type Output = Capabilities;
+    fn bitand(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 & other.0)
+    }
+}
+
+/// An update that a source has prepared, but not yet made visible. Dropping
+/// it without committing abandons the update.
+pub trait PreparedUpdate {
+    /// Makes the update visible. This should be the part least likely to fail.
+    fn commit(self: Box<Self>) -> io::Result<()>;
+}
+
+/// The default `PreparedUpdate`. Does all the work at commit time.
+struct BufferedUpdate<'a, S: VFSSource + ?Sized> {
+    source: &'a S,
+    path: PathBuf,
+    data: Vec<u8>,
+}
+
+impl<S: VFSSource + ?Sized> PreparedUpdate for BufferedUpdate<'_, S> {
+    fn commit(self: Box<Self>) -> io::Result<()> {
+        self.source.update(&self.path, &self.data)
+    }
+}
+
+/// A description of a source, from which an equivalent source can be
+/// recreated. See [`VFS::to_specs`](struct.VFS.html#method.to_specs) and
+/// [`VFS::from_specs`](struct.VFS.html#method.from_specs).
+#[derive(Clone,Debug)]
+pub enum MountSpec {
+    /// An [`FsSource`](struct.FsSource.html) with the given parameters.
+    #[cfg(feature = "fs")]
+    Fs { base: std::path::PathBuf, read_only: bool,
+         normalization: NormalizationMode,
+         reserved_suffixes: bool, noatime: bool },
+    /// A [`RomSource`](struct.RomSource.html). ROM data is baked into the
+    /// executable, so there's nothing to describe; this just carries a copy
+    /// of the source itself.
+    #[cfg(feature = "rom")]
+    Rom { source: crate::rom::Source },
+}
+
+impl MountSpec {
+    /// Creates a new source according to this description.
+    pub fn into_source(self) -> io::Result<Box<dyn VFSSource>> {
+        match self {
+            #[cfg(feature = "fs")]
+            MountSpec::Fs { base, read_only, normalization,
+                            reserved_suffixes, noatime }
+            => Ok(Box::new(crate::fs::Source::new(base, read_only)?
+                           .with_normalization(normalization)
+                           .with_reserved_suffixes(reserved_suffixes)
+                           .with_noatime(noatime))),
+            #[cfg(feature = "rom")]
+            MountSpec::Rom { source } => Ok(Box::new(source)),
+        }
+    }
+}
+
+/// Merges several `ls` results into one, removing duplicates. If both "foo"
+/// and "foo/" are present, only "foo/" is kept, since the directory shadows
+/// the file.
+///
+/// Each listing should already be in listing order: sorted by name, ignoring
+/// any trailing `/`, with a directory coming just before a file of the same
+/// name. A listing that isn't will be sorted first.
+///
+/// The result is appended to `result`, which should be empty.
+pub(crate) fn merge_listings(listings: Vec<Vec<PathBuf>>,
+                             result: &mut Vec<PathBuf>) {
+    let mut total = 0;
+    let mut heads = Vec::with_capacity(listings.len());
+    for mut listing in listings.into_iter() {
+        if !listing.windows(2)
+            .all(|x| x[0].dir_aware_cmp(&x[1]) != Ordering::Greater) {
+                listing.sort_by(|a, b| a.dir_aware_cmp(b));
+            }
+        total += listing.len();
+        heads.push(listing.into_iter().peekable());
+    }
+    result.reserve(total);
+    loop {
+        // Take the lowest entry from the front of any listing...
+        let next = heads.iter_mut().enumerate()
+            .filter_map(|(i, head)| head.peek().map(|x| (i, x)))
+            .min_by(|(_, a), (_, b)| a.dir_aware_cmp(b))
+            .map(|(i, _)| i);
+        let entry = match next {
+            Some(i) => heads[i].next().unwrap(),
+            None => break,
+        };
+        // ...and keep it, unless it's a duplicate of the previous entry, or
+        // a file shadowed by the previous entry.
+        if let Some(last) = result.last() {
+            if *last == entry
+                || (last.is_directory()
+                    && &last.as_str()[..last.len()-1] == entry.as_str()) {
+                    continue
+                }
+        }
+        result.push(entry);
+    }
+}
+
+/// Adds every file under `dir` in `source` to `out`, as it would appear in
+/// the VFS with `dir` at `at`.
+fn walk_source<S: VFSSource + ?Sized>(source: &S, dir: &Path, at: &PathBuf,
+                                     out: &mut Vec<PathBuf>)
+                                     -> io::Result<()> {
+    for entry in source.ls(dir)? {
+        let mut path = dir.to_owned();
+        path.join(&entry);
+        let mut vfs_path = at.clone();
+        vfs_path.join(&entry);
+        if entry.is_directory() {
+            walk_source(source, &path, &vfs_path, out)?;
+        }
+        else {
+            out.push(vfs_path);
+        }
+    }
+    Ok(())
+}
+
+/// Which Unicode normal form paths are given to sources in. See
+/// [`VFS::set_normalization`](struct.VFS.html#method.set_normalization) and
+/// [`FsSource::with_normalization`](struct.FsSource.html#method.with_normalization).
+///
+/// Psilo-VFS paths are always in normal form D, and that doesn't change. But
+/// some backing stores (certain archive formats, some filesystems) hold names
+/// in normal form C, and compare them byte for byte, so an NFD lookup misses
+/// them. Names that come *out* of a source, via `ls`, are converted to
+/// normal form D regardless of mode, so that they compare the same way as
+/// every other path in the VFS.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub enum NormalizationMode {
+    /// Use paths exactly as they are in the VFS. (Default.)
+    #[default]
+    Nfd,
+    /// Convert paths to normal form C before handing them over.
+    Nfc,
+}
+
+impl NormalizationMode {
+    /// Converts a VFS path into this normal form. The result is only fit to
+    /// be handed to a source; in normal form C, it isn't a proper `Path`.
+    pub(crate) fn apply<'a>(self, path: &'a Path) -> Cow<'a, Path> {
+        match self {
+            NormalizationMode::Nfd => Cow::Borrowed(path),
+            NormalizationMode::Nfc => {
+                let nfc: String = path.as_str().nfc().collect();
+                Cow::Owned(Path::from_str_preverified(&nfc).to_owned())
+            },
+        }
+    }
+    /// Converts the names in a listing that came from a source into normal
+    /// form D. Names that already are (the usual case) are left alone.
+    fn unapply(listing: &mut [PathBuf]) {
+        for name in listing.iter_mut() {
+            name.renormalize();
+        }
+    }
+}
+
+/// Determines the order of the entries `ls` returns. See
+/// [`VFS::set_listing_order`](struct.VFS.html#method.set_listing_order).
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub enum ListingOrder {
+    /// By codepoint, so `file10` comes before `file2`. This is
+    /// [`Path::dir_aware_cmp`](struct.Path.html#method.dir_aware_cmp) order,
+    /// the order sources list in. This is the default.
+    #[default]
+    Codepoint,
+    /// As `Codepoint`, except that runs of ASCII digits are compared as
+    /// numbers, so `file2` comes before `file10`. Names that differ only in
+    /// leading zeroes (`file02` and `file2`) fall back to codepoint order.
+    Natural,
+}
+
+/// Compares two single-component paths as `ListingOrder::Natural` says to.
+fn natural_cmp(a: &Path, b: &Path) -> Ordering {
+    let is_digit = |x: &str| x.starts_with(|c: char| c.is_ascii_digit());
+    let digit_run = |x: &str| {
+        x.find(|c: char| !c.is_ascii_digit()).unwrap_or(x.len())
+    };
+    let (mut x, mut y) = (a.as_str().trim_end_matches('/'),
+                          b.as_str().trim_end_matches('/'));
+    loop {
+        if x.is_empty() || y.is_empty() {
+            return x.is_empty().cmp(&y.is_empty()).reverse()
+                .then_with(|| a.dir_aware_cmp(b))
+        }
+        let ordering = if is_digit(x) && is_digit(y) {
+            let (x_run, x_rest) = x.split_at(digit_run(x));
+            let (y_run, y_rest) = y.split_at(digit_run(y));
+            let (x_run, y_run) = (x_run.trim_start_matches('0'),
+                                  y_run.trim_start_matches('0'));
+            x = x_rest;
+            y = y_rest;
+            x_run.len().cmp(&y_run.len()).then_with(|| x_run.cmp(y_run))
+        }
+        else {
+            let (x_char, y_char) = (x.chars().next().unwrap(),
+                                    y.chars().next().unwrap());
+            x = &x[x_char.len_utf8()..];
+            y = &y[y_char.len_utf8()..];
+            x_char.cmp(&y_char)
+        };
+        if ordering != Ordering::Equal { return ordering }
+    }
+}
+
+/// Determines which mount wins when more than one mount provides the same
+/// file. See [`VFS::set_priority_mode`](struct.VFS.html#method.set_priority_mode).
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub enum ShadowMode {
+    /// Later mounts override earlier mounts. This is the default.
+    #[default]
+    LastWins,
+    /// Earlier mounts override later mounts. Useful for protecting base game
+    /// data from being accidentally overridden by mods.
+    FirstWins,
+}
+
+/// How many times, and how patiently, to retry an operation on a source that
+/// fails in a way that might be temporary. See
+/// [`VFS::set_retry_policy`](struct.VFS.html#method.set_retry_policy).
+///
+/// The default never retries.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct RetryPolicy {
+    /// How many times to try, in total. `0` is treated like `1`.
+    pub max_attempts: u32,
+    /// How long to wait before the first retry. Each later retry waits twice
+    /// as long as the one before.
+    pub backoff: Duration,
+    /// Which kinds of errors are worth retrying after. Any other error is
+    /// returned right away.
+    pub retryable_kinds: Vec<ErrorKind>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            backoff: Duration::ZERO,
+            retryable_kinds: vec![ErrorKind::TimedOut, ErrorKind::Interrupted],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Calls `f` until it succeeds, fails with an error that isn't
+    /// retryable, or runs out of attempts.
+    fn run<T>(&self, mut f: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut backoff = self.backoff;
+        let mut attempts = 1;
+        loop {
+            match f() {
+                Err(x) if attempts < self.max_attempts
+                    && self.retryable_kinds.contains(&x.kind()) => {
+                        std::thread::sleep(backoff);
+                        backoff = backoff.saturating_mul(2);
+                        attempts += 1;
+                    },
+                x => return x,
+            }
+        }
+    }
+}
+
+/// A write that a VFS in dry-run mode would have performed. See
+/// [`VFS::set_dry_run`](struct.VFS.html#method.set_dry_run).
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum DryRunOp {
+    /// `path` would have been replaced with `data`, by the mount at
+    /// `mount_point`.
+    Update { mount_point: PathBuf, path: PathBuf, data: Vec<u8> },
+    /// Everything in the directory `path` would have been replaced with
+    /// `entries` (given relative to `path`), by the mount at `mount_point`.
+    ReplaceDir { mount_point: PathBuf, path: PathBuf,
+                 entries: Vec<(PathBuf, Vec<u8>)> },
+}
+
+/// What mounting a source would change. See
+/// [`VFS::diff_with_added_mount`](struct.VFS.html#method.diff_with_added_mount).
+///
+/// Every list holds absolute paths to files, sorted in
+/// [`Path::dir_aware_cmp`](struct.Path.html#method.dir_aware_cmp) order.
+#[derive(Clone,Debug,Default,PartialEq,Eq)]
+pub struct MountDiff {
+    /// Files that don't exist now, and would be provided by the new source.
+    pub added: Vec<PathBuf>,
+    /// Files that exist now, and whose contents would come from the new
+    /// source instead.
+    pub overridden: Vec<PathBuf>,
+    /// Files that exist now, and would stay as they are.
+    pub unaffected: Vec<PathBuf>,
+}
+
+type Mount = (PathBuf, Arc<dyn VFSSource>);
+
+/// Iterates over the given mounts, from highest priority to lowest.
+fn by_priority(mounts: &[Mount], shadow_mode: ShadowMode)
+               -> Box<dyn Iterator<Item=&Mount> + '_> {
+    match shadow_mode {
+        ShadowMode::LastWins => Box::new(mounts.iter().rev()),
+        ShadowMode::FirstWins => Box::new(mounts.iter()),
+    }
+}
+
+/// How many locks `UpdateLocks` spreads paths over.
+const UPDATE_LOCK_STRIPES: usize = 16;
+
+/// Keeps updates of the same path from overlapping. Sources aren't expected
+/// to cope with two updates of one file at once (an `FsSource`'s renames
+/// would trip over each other), so the VFS makes sure they don't happen.
+///
+/// Paths are hashed onto a fixed set of locks. Updates of unrelated paths
+/// occasionally wait for each other, but updates of the same path never run
+/// at the same time.
+#[derive(Default)]
+struct UpdateLocks([Mutex<()>; UPDATE_LOCK_STRIPES]);
+
+impl UpdateLocks {
+    fn stripe(path: &Path) -> usize {
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        (hasher.finish() % UPDATE_LOCK_STRIPES as u64) as usize
+    }
+    /// Locks every given path. The locks are always taken in the same
+    /// order, so two callers locking overlapping sets of paths can't
+    /// deadlock.
+    fn lock<'a>(&self, paths: impl IntoIterator<Item=&'a Path>)
+                -> Vec<MutexGuard<'_, ()>> {
+        let mut stripes: Vec<usize> = paths.into_iter()
+            .map(UpdateLocks::stripe).collect();
+        stripes.sort_unstable();
+        stripes.dedup();
+        // The locks guard no data, so a panic while one was held (i.e. in a
+        // source) can't have left anything inconsistent
+        stripes.into_iter().map(|i| {
+            self.0[i].lock().unwrap_or_else(PoisonError::into_inner)
+        }).collect()
+    }
+    /// Locks every path at once, for changes that affect many files.
+    fn lock_all(&self) -> Vec<MutexGuard<'_, ()>> {
+        self.0.iter().map(|x| {
+            x.lock().unwrap_or_else(PoisonError::into_inner)
+        }).collect()
+    }
 }
 
 struct VFSInner {
-    mounts: Vec<(PathBuf, Box<dyn VFSSource>)>,
+    /// Shared with any outstanding `Snapshot`s. Copied on write.
+    mounts: Arc<Vec<Mount>>,
+    shadow_mode: ShadowMode,
+    listing_order: ListingOrder,
+    normalization: NormalizationMode,
+    /// `Some` in dry-run mode, holding the writes that weren't performed.
+    dry_run: Option<Mutex<Vec<DryRunOp>>>,
+    /// `Some` if `ls` results are being cached.
+    ls_cache: Option<Arc<Mutex<LsCache>>>,
+    retry_policy: Arc<RetryPolicy>,
+    /// See `VFS::set_search_prefixes`.
+    search_prefixes: Arc<Vec<PathBuf>>,
+    update_locks: UpdateLocks,
+}
+
+/// Everything a read needs from a `VFSInner`, taken so that the lock can be
+/// released before any source is called. Cheap to take: nothing is copied
+/// but some `Arc`s.
+struct Snapshot {
+    mounts: Arc<Vec<Mount>>,
+    shadow_mode: ShadowMode,
+    listing_order: ListingOrder,
+    normalization: NormalizationMode,
+    ls_cache: Option<Arc<Mutex<LsCache>>>,
+    retry_policy: Arc<RetryPolicy>,
+}
+
+impl Snapshot {
+    /// Iterates over the mounts, from highest priority to lowest.
+    fn by_priority(&self) -> Box<dyn Iterator<Item=&Mount> + '_> {
+        by_priority(&self.mounts, self.shadow_mode)
+    }
+}
+
+/// Cached `ls` results. See
+/// [`VFS::enable_ls_cache`](struct.VFS.html#method.enable_ls_cache).
+#[derive(Default)]
+struct LsCache {
+    /// Bumped on every invalidation. A listing is only cached if no
+    /// invalidation happened while it was being made, since it might have
+    /// been made from the old state.
+    generation: u64,
+    listings: BTreeMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl VFSInner {
+    /// Iterates over the mounts, from highest priority to lowest.
+    fn by_priority(&self) -> Box<dyn Iterator<Item=&Mount> + '_> {
+        by_priority(&self.mounts, self.shadow_mode)
+    }
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            mounts: self.mounts.clone(),
+            shadow_mode: self.shadow_mode,
+            listing_order: self.listing_order,
+            normalization: self.normalization,
+            ls_cache: self.ls_cache.clone(),
+            retry_policy: self.retry_policy.clone(),
+        }
+    }
+    /// Finds the mount that should handle an update of the given path, and
+    /// calls `f` with its mount point, its source, and the path within it.
+    /// That's the highest-priority mount containing the path that isn't
+    /// read-only. If it doesn't have the file's parent directory, the update
+    /// fails with `NotFound` rather than going to some lower mount.
+    fn for_update<'a, T, F>(&'a self, path: &Path, mut f: F) -> io::Result<T>
+    where F: FnMut(&'a Path, &'a dyn VFSSource, &Path) -> io::Result<T> {
+        if !path.is_absolute() {
+            let err = format!("attempt to update a non-absolute path: {:?}",
+                              path);
+            return Err(io::Error::new(ErrorKind::Other, err))
+        }
+        if path.is_directory() {
+            return Err(io::Error::from(ErrorKind::IsADirectory))
+        }
+        for (prefix, source) in self.by_priority() {
+            match path.with_prefix_absolute(prefix) {
+                None => (),
+                Some(suffix) => {
+                    let suffix = &*self.normalization.apply(suffix);
+                    if source.capabilities().contains(Capabilities::WRITE) {
+                        // (if the source can't tell, the update will)
+                        if let Ok(false) = self.retry_policy.run(|| {
+                            source.dir_exists(suffix.parent())
+                        }) {
+                            let err = format!("parent directory not found: \
+                                               {:?}", path.parent());
+                            return Err(io::Error::new(ErrorKind::NotFound,
+                                                      err))
+                        }
+                        match self.retry_policy.run(|| {
+                            f(prefix, source.as_ref(), suffix)
+                        }) {
+                            Err(x) if x.kind()
+                                == ErrorKind::ReadOnlyFilesystem => (),
+                            x => return x,
+                        }
+                    }
+                    self.check_shadow(path, prefix, source.as_ref())?;
+                },
+            }
+        }
+        Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+    }
+    /// Called for a mount that an update of the given path passes over. If
+    /// the mount has the file, it would go on shadowing anything written to a
+    /// lower one, so this fails with `ReadOnlyFilesystem`.
+    fn check_shadow(&self, path: &Path, prefix: &Path, source: &dyn VFSSource)
+                    -> io::Result<()> {
+        let suffix = match path.with_prefix_absolute(prefix) {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let suffix = &*self.normalization.apply(suffix);
+        match self.retry_policy.run(|| source.exists(suffix)) {
+            Ok(false) => Ok(()),
+            Ok(true) => {
+                let err = format!("{:?} is provided by a read-only mount at \
+                                   {:?}", path, prefix);
+                Err(io::Error::new(ErrorKind::ReadOnlyFilesystem, err))
+            },
+            Err(x) => Err(x),
+        }
+    }
+    /// In dry-run mode, works out where an update of the given path would
+    /// go, and returns the operation that would be performed there. Returns
+    /// `None` if not in dry-run mode.
+    fn dry_run_update(&self, path: &Path, data: &[u8])
+                      -> Option<io::Result<DryRunOp>> {
+        self.dry_run.as_ref()?;
+        Some(self.for_update(path, |prefix, source, _| {
+            if !source.capabilities().contains(Capabilities::WRITE) {
+                return Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+            }
+            Ok(DryRunOp::Update { mount_point: prefix.to_owned(),
+                                  path: path.to_owned(),
+                                  data: data.to_vec() })
+        }))
+    }
+    /// Throws away every cached listing, if there are any.
+    fn invalidate_ls_cache(&self) {
+        if let Some(cache) = self.ls_cache.as_ref() {
+            let mut cache = cache.lock().unwrap();
+            cache.generation += 1;
+            cache.listings.clear();
+        }
+    }
+    /// Adds the given operations to the dry-run log.
+    fn log_dry_run(&self, ops: impl IntoIterator<Item=DryRunOp>) {
+        if let Some(log) = self.dry_run.as_ref() {
+            log.lock().unwrap().extend(ops);
+        }
+    }
 }
 
+/// The virtual filesystem. Cloning a `VFS` gives another handle onto the same
+/// mounts.
+///
+/// The mounts are behind a lock. `open`, `open_all`, and `ls` (and the
+/// things built on them) only hold it long enough to take a snapshot of the
+/// mounts, and call into sources without it, so a slow source can't hold up
+/// mounting, or anything else. They see the mounts as they were when they
+/// started. Other operations take a read lock for their duration and call
+/// into sources while holding it. Mounting, and changing settings, take a
+/// write lock only long enough to make the change. No lock is held once an
+/// operation returns, so files returned by `open` hold no lock, and dropping
+/// one at any point has no effect on the VFS. If a source panics partway
+/// through an operation, any lock is released as the panic unwinds, and the
+/// VFS remains usable.
+///
+/// Updates of the same path, whether through `update`, a `Transaction`, or
+/// `replace_dir`, are made one at a time, in whatever order they arrive in.
+/// Sources don't have to cope with two updates of one file at once. Updates
+/// of different paths can still happen at the same time.
+///
+/// Once an update (or a `Transaction`, or a `replace_dir`) succeeds, `open`
+/// sees the new data, and `ls` sees the new file, with or without the `ls`
+/// cache. An update goes to the mount `open` will read the file from, so one
+/// that would be shadowed by a read-only mount that has the file fails with
+/// `ReadOnlyFilesystem` instead. (Changes made behind the VFS's back are
+/// another matter; see
+/// [`enable_ls_cache`](#method.enable_ls_cache).)
 #[derive(Clone)]
 pub struct VFS {
     inner: Arc<RwLock<VFSInner>>,
@@ -35,16 +784,65 @@ pub struct VFS {
 
 unsafe impl Send for VFS {}
 
-pub trait DataFile : Read + Seek {}
-impl<T: AsRef<[u8]> + Unpin> DataFile for Cursor<T> {}
+/// A file that can only be read from start to finish, as returned by
+/// [`VFS::open_stream`](struct.VFS.html#method.open_stream).
+///
+/// Every `Read` is a `DataStream`, including every `DataFile`. Anything that
+/// needs to seek, or to know the length of a file before reading it (`open`,
+/// `open_buffered`, `open_verified`, `DataFile::stream_len`), needs a
+/// `DataFile`.
+pub trait DataStream : Read {}
+impl<T: Read + ?Sized> DataStream for T {}
+
+pub trait DataFile : DataStream + Seek {
+    /// Returns the total length of the file, in bytes. Does not change the
+    /// current position.
+    ///
+    /// The default implementation seeks to the end and back again. Types that
+    /// know their length up front should override it.
+    ///
+    /// The standard library has an unstable `Seek::stream_len` with the same
+    /// name. Until that settles, call this as `DataFile::stream_len(&mut f)`
+    /// to avoid a name collision warning.
+    fn stream_len(&mut self) -> io::Result<u64> {
+        let pos = self.stream_position()?;
+        let len = self.seek(SeekFrom::End(0))?;
+        if pos != len {
+            self.seek(SeekFrom::Start(pos))?;
+        }
+        Ok(len)
+    }
+}
+impl<T: AsRef<[u8]> + Unpin> DataFile for Cursor<T> {
+    fn stream_len(&mut self) -> io::Result<u64> {
+        Ok(self.get_ref().as_ref().len() as u64)
+    }
+}
 
 #[cfg(feature = "stdpaths")]
 mod stdpaths;
 
+#[cfg(feature = "verify")]
+mod verify;
+
+#[cfg(feature = "tar")]
+mod archive;
+#[cfg(feature = "tar")]
+pub use archive::ArchiveFormat;
+
+
 impl VFS {
     pub fn new() -> VFS {
         VFS { inner: Arc::new(RwLock::new(VFSInner {
-            mounts: vec![]
+            mounts: Arc::default(),
+            shadow_mode: ShadowMode::LastWins,
+            listing_order: ListingOrder::Codepoint,
+            normalization: NormalizationMode::default(),
+            dry_run: None,
+            ls_cache: None,
+            retry_policy: Arc::new(RetryPolicy::default()),
+            search_prefixes: Arc::new(vec![PathBuf::from_str("/")]),
+            update_locks: UpdateLocks::default(),
         }))}
     }
     #[cfg(feature = "stdpaths")]
@@ -54,21 +852,229 @@ impl VFS {
         stdpaths::do_standard_mounts(&mut ret, unixy_name, humanish_name);
         ret
     }
-    pub fn mount(&mut self, point:PathBuf, source:Box<dyn VFSSource>)
+    /// Mounts a source at the given point. Later mounts override earlier
+    /// ones (unless [`set_priority_mode`](#method.set_priority_mode) says
+    /// otherwise).
+    ///
+    /// This only needs `&self`, so you can mount through any clone of a
+    /// `VFS`, and the mount will be visible through all of them.
+    pub fn mount(&self, point:PathBuf, source:Box<dyn VFSSource>)
         -> io::Result<()> {
-        if !point.is_absolute() {
-            let err = format!("attempt to mount at a non-absolute path: {:?}",
-                              point);
-            return Err(io::Error::new(ErrorKind::Other, err))
+        check_mount_point(&point)?;
+        let mut this = self.inner.write().unwrap();
+        Arc::make_mut(&mut this.mounts).push((point, source.into()));
+        this.invalidate_ls_cache();
+        Ok(())
+    }
+    /// Mounts several sources, in order, as if by calling `mount` on each
+    /// one, but all at once: every mount point is checked first, and if any
+    /// is unusable, nothing is mounted. Nothing using the VFS at the same
+    /// time can see some of the mounts without the rest.
+    pub fn mount_all(&self, mounts: Vec<(PathBuf, Box<dyn VFSSource>)>)
+        -> io::Result<()> {
+        for (point, _) in mounts.iter() {
+            check_mount_point(point)?;
         }
-        if !point.is_directory() {
-            return Err(io::Error::from(ErrorKind::NotADirectory))
+        let mut this = self.inner.write().unwrap();
+        Arc::make_mut(&mut this.mounts).extend(mounts.into_iter()
+            .map(|(point, source)| (point, source.into())));
+        this.invalidate_ls_cache();
+        Ok(())
+    }
+    /// Mounts a source at the given point, like `mount`, but with every name
+    /// passing between the VFS and the source converted by the given
+    /// [`NameRewrite`](trait.NameRewrite.html).
+    pub fn mount_with_rewrite(&self, point: PathBuf,
+                              source: Box<dyn VFSSource>,
+                              rewrite: Box<dyn NameRewrite>)
+        -> io::Result<()> {
+        self.mount(point, Box::new(crate::rewrite::RewriteSource {
+            inner: source, rewrite,
+        }))
+    }
+    /// Mounts one directory of a source at the given point, like `mount`,
+    /// but as if that directory were the source's root. For instance, with
+    /// a `source_subpath` of `/levels/` and a `point` of `/maps/`,
+    /// `/maps/1.map` is the source's `/levels/1.map`. Nothing outside
+    /// `source_subpath` can be reached.
+    ///
+    /// Returns `InvalidInput` if `source_subpath` isn't an absolute path to
+    /// a directory.
+    pub fn mount_subtree(&self, point: PathBuf, source: Box<dyn VFSSource>,
+                         source_subpath: &Path) -> io::Result<()> {
+        if !source_subpath.is_absolute() || !source_subpath.is_directory() {
+            let err = format!("attempt to mount a subtree that isn't an \
+                               absolute directory: {:?}", source_subpath);
+            return Err(io::Error::new(ErrorKind::InvalidInput, err))
         }
+        self.mount(point, Box::new(crate::subtree::SubtreeSource {
+            inner: source, subpath: source_subpath.to_owned(),
+        }))
+    }
+    /// Creates a new VFS with the given mounts, in order. This is the inverse
+    /// of [`to_specs`](#method.to_specs).
+    pub fn from_specs<I>(specs: I) -> io::Result<VFS>
+    where I: IntoIterator<Item=(PathBuf, MountSpec)> {
+        let ret = VFS::new();
+        for (point, spec) in specs {
+            ret.mount(point, spec.into_source()?)?;
+        }
+        Ok(ret)
+    }
+    /// Describes every mount in this VFS, in order, so that an equivalent VFS
+    /// can be created later with [`from_specs`](#method.from_specs).
+    ///
+    /// Returns an `Unsupported` error if any mounted source can't describe
+    /// itself (see [`VFSSource::spec`](trait.VFSSource.html#method.spec)).
+    pub fn to_specs(&self) -> io::Result<Vec<(PathBuf, MountSpec)>> {
+        let this = self.inner.read().unwrap();
+        this.mounts.iter().map(|(point, source)| {
+            match source.spec() {
+                Some(spec) => Ok((point.clone(), spec)),
+                None => {
+                    let err = format!("source mounted at {:?} can't be \
+                                       described by a MountSpec", point);
+                    Err(io::Error::new(ErrorKind::Unsupported, err))
+                },
+            }
+        }).collect()
+    }
+    /// Controls which mount wins when more than one mount provides the same
+    /// file. This affects which file `open` returns and which mount `update`
+    /// writes to. (`ls` is a union of every mount, so priority doesn't change
+    /// its results.) The default is `ShadowMode::LastWins`.
+    pub fn set_priority_mode(&self, mode: ShadowMode) {
+        let mut this = self.inner.write().unwrap();
+        this.shadow_mode = mode;
+        this.invalidate_ls_cache();
+    }
+    /// Controls the order of the entries `ls` (and everything built on it)
+    /// returns. The default is `ListingOrder::Codepoint`. This is purely
+    /// presentation: what's listed doesn't change, only the order.
+    pub fn set_listing_order(&self, order: ListingOrder) {
+        let mut this = self.inner.write().unwrap();
+        this.listing_order = order;
+        this.invalidate_ls_cache();
+    }
+    /// Sets which normal form paths are handed to sources in. See
+    /// [`NormalizationMode`](enum.NormalizationMode.html). The default is
+    /// `NormalizationMode::Nfd`.
+    ///
+    /// This applies to every mount, on top of anything the source does
+    /// itself. Paths given to and returned by the VFS are always in normal
+    /// form D; in `Nfc` mode, a file a source lists as `caf\u{E9}` is listed
+    /// by the VFS, and opened, as `cafe\u{301}`.
+    pub fn set_normalization(&self, mode: NormalizationMode) {
+        let mut this = self.inner.write().unwrap();
+        this.normalization = mode;
+        this.invalidate_ls_cache();
+    }
+    /// Sets how operations on sources that fail in a way that might be
+    /// temporary (such as a network timeout) are retried. See
+    /// [`RetryPolicy`](struct.RetryPolicy.html). By default, nothing is
+    /// retried.
+    ///
+    /// Retries happen per source, within a single VFS operation: if one
+    /// mount keeps timing out, the operation fails with its error, rather
+    /// than falling through to a lower-priority mount. Committing a prepared
+    /// update is never retried, since it may have partly happened. Waiting
+    /// between retries holds the VFS's read lock, which delays mounting (but
+    /// nothing else).
+    pub fn set_retry_policy(&self, policy: RetryPolicy) {
+        let mut this = self.inner.write().unwrap();
+        this.retry_policy = Arc::new(policy);
+    }
+    /// Sets the directories that [`open_searched`](#method.open_searched) and
+    /// [`ls_searched`](#method.ls_searched) look for relative paths in, in
+    /// order, e.g. `["/lang/fr/", "/lang/en/", "/"]`. Each one must be an
+    /// absolute directory. The default is just `["/"]`.
+    ///
+    /// Absolute paths bypass the search entirely, and `open` and `ls` aren't
+    /// affected at all.
+    pub fn set_search_prefixes(&self, prefixes: Vec<PathBuf>)
+                               -> io::Result<()> {
+        if let Some(bad) = prefixes.iter()
+            .find(|x| !x.is_absolute() || !x.is_directory()) {
+                let err = format!("search prefix is not an absolute \
+                                   directory: {:?}", bad);
+                return Err(io::Error::new(ErrorKind::InvalidInput, err))
+            }
         let mut this = self.inner.write().unwrap();
-        this.mounts.push((point, source));
+        this.search_prefixes = Arc::new(prefixes);
         Ok(())
     }
+    /// Starts caching the results of `ls` (and everything built on it), so
+    /// that listing the same directory again doesn't ask every mount again.
+    ///
+    /// The cache is emptied whenever anything done through the VFS could
+    /// change a listing: mounting, changing the priority mode, or any write,
+    /// whether it succeeds or not. Changes made behind the VFS's back (such
+    /// as another program writing to a directory an `FsSource` is mounted
+    /// on) can't be noticed; call
+    /// [`invalidate_ls_cache`](#method.invalidate_ls_cache) after those.
+    ///
+    /// Errors are never cached.
+    pub fn enable_ls_cache(&self) {
+        let mut this = self.inner.write().unwrap();
+        if this.ls_cache.is_none() {
+            this.ls_cache = Some(Arc::new(Mutex::new(LsCache::default())));
+        }
+    }
+    /// Stops caching the results of `ls`, and throws away the cache.
+    pub fn disable_ls_cache(&self) {
+        let mut this = self.inner.write().unwrap();
+        this.ls_cache = None;
+    }
+    /// Throws away every cached `ls` result, if `ls` caching is enabled.
+    pub fn invalidate_ls_cache(&self) {
+        let this = self.inner.read().unwrap();
+        this.invalidate_ls_cache();
+    }
+    /// Turns dry-run mode on or off. In dry-run mode, writes (`update` and
+    /// `Transaction::commit`) are checked as usual, but instead of being
+    /// performed, they're added to a log; see
+    /// [`take_dry_run_log`](#method.take_dry_run_log). Reads are unaffected,
+    /// so they won't see the writes that weren't performed.
+    ///
+    /// Checking a write includes finding the mount it would go to, which
+    /// means checking for parent directories as usual. A mount is considered
+    /// writable if its source's `capabilities` say so; a source that claims
+    /// `WRITE` but would actually refuse the write can't be caught without
+    /// really writing.
+    ///
+    /// Turning dry-run mode on starts a fresh log. Turning it off discards the
+    /// log.
+    pub fn set_dry_run(&self, dry_run: bool) {
+        let mut this = self.inner.write().unwrap();
+        this.dry_run = if dry_run { Some(Mutex::new(vec![])) } else { None };
+    }
+    /// Returns every write logged in dry-run mode since the last call, in the
+    /// order they were attempted, and clears the log. Returns an empty log if
+    /// not in dry-run mode.
+    pub fn take_dry_run_log(&self) -> Vec<DryRunOp> {
+        let this = self.inner.read().unwrap();
+        match this.dry_run.as_ref() {
+            Some(log) => std::mem::take(&mut *log.lock().unwrap()),
+            None => vec![],
+        }
+    }
     pub fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+        self.open_with(path, |source, suffix| source.open(suffix))
+    }
+    /// Opens a file for reading from start to finish, with no seeking. Picks
+    /// the same mount that `open` would, but calls its source's
+    /// [`open_stream`](trait.VFSSource.html#method.open_stream), so it also
+    /// works for sources that can't seek. Any file `open` can open can be
+    /// opened this way.
+    pub fn open_stream(&self, path: &Path)
+                       -> io::Result<Box<dyn DataStream>> {
+        self.open_with(path, |source, suffix| source.open_stream(suffix))
+    }
+    /// Finds the mount that should provide the given file, and opens it
+    /// there with `f`. Shared by `open` and `open_stream`.
+    fn open_with<T>(&self, path: &Path,
+                    f: impl Fn(&dyn VFSSource, &Path) -> io::Result<T>)
+                    -> io::Result<T> {
         if !path.is_absolute() {
             let err = format!("attempt to open a non-absolute path: {:?}",
                               path);
@@ -77,12 +1083,13 @@ impl VFS {
         if path.is_directory() {
             return Err(io::Error::from(ErrorKind::IsADirectory))
         }
-        let this = self.inner.read().unwrap();
-        for (prefix, source) in this.mounts.iter().rev() {
+        let this = self.inner.read().unwrap().snapshot();
+        for (prefix, source) in this.by_priority() {
             match path.with_prefix_absolute(prefix) {
                 None => (),
                 Some(suffix) => {
-                    match source.open(suffix) {
+                    let suffix = &*this.normalization.apply(suffix);
+                    match this.retry_policy.run(|| f(source.as_ref(), suffix)) {
                         Ok(x) => return Ok(x),
                         Err(x) if x.kind() == ErrorKind::NotFound => continue,
                         Err(x) => return Err(x)
@@ -92,7 +1099,171 @@ impl VFS {
         }
         Err(io::Error::from(ErrorKind::NotFound))
     }
+    /// Opens part of a file: `len` bytes, starting `start` bytes in, or fewer
+    /// if the file ends first. Position 0 in the returned file is `start` in
+    /// the whole file.
+    ///
+    /// Sources that can (see
+    /// [`VFSSource::open_range`](trait.VFSSource.html#method.open_range))
+    /// read just that part, without the rest of the file.
+    pub fn open_range(&self, path: &Path, start: u64, len: u64)
+                      -> io::Result<Box<dyn DataFile>> {
+        self.open_with(path, |source, path| {
+            source.open_range(path, start, len)
+        })
+    }
+    /// Opens every mount's version of a file, not just the one that `open`
+    /// would pick, so that they can be merged (e.g. a base config plus a
+    /// user's overrides). Returns each one along with its mount point, from
+    /// lowest priority to highest, so that the last one is the one `open`
+    /// would have returned.
+    ///
+    /// Returns `NotFound` if no mount has the file. Any other error from any
+    /// mount is returned right away.
+    pub fn open_all(&self, path: &Path)
+                    -> io::Result<Vec<(PathBuf, Box<dyn DataFile>)>> {
+        if !path.is_absolute() {
+            let err = format!("attempt to open a non-absolute path: {:?}",
+                              path);
+            return Err(io::Error::other(err))
+        }
+        if path.is_directory() {
+            return Err(io::Error::from(ErrorKind::IsADirectory))
+        }
+        let this = self.inner.read().unwrap().snapshot();
+        let mut ret = vec![];
+        for (prefix, source) in this.by_priority() {
+            if let Some(suffix) = path.with_prefix_absolute(prefix) {
+                let suffix = &*this.normalization.apply(suffix);
+                match this.retry_policy.run(|| source.open(suffix)) {
+                    Ok(x) => ret.push((prefix.clone(), x)),
+                    Err(x) if x.kind() == ErrorKind::NotFound => continue,
+                    Err(x) => return Err(x)
+                }
+            }
+        }
+        if ret.is_empty() {
+            return Err(io::Error::from(ErrorKind::NotFound))
+        }
+        ret.reverse();
+        Ok(ret)
+    }
+    /// Opens a file, looking for it in each of the search prefixes (see
+    /// [`set_search_prefixes`](#method.set_search_prefixes)) in turn if its
+    /// path is relative. The first prefix that has the file wins; later ones
+    /// are only tried if earlier ones give `NotFound`. An absolute path is
+    /// opened as `open` would.
+    ///
+    /// Returns `InvalidInput` if a relative path escapes a prefix with `..`.
+    pub fn open_searched(&self, path: &Path)
+                         -> io::Result<Box<dyn DataFile>> {
+        if path.is_absolute() { return self.open(path) }
+        let prefixes = self.inner.read().unwrap().search_prefixes.clone();
+        for prefix in prefixes.iter() {
+            let mut full = prefix.clone();
+            full.try_join(path)
+                .map_err(|x| io::Error::new(ErrorKind::InvalidInput, x))?;
+            match self.open(&full) {
+                Err(x) if x.kind() == ErrorKind::NotFound => continue,
+                x => return x,
+            }
+        }
+        Err(io::Error::from(ErrorKind::NotFound))
+    }
+    /// Opens a file, and if it's no bigger than `max_bytes`, reads the whole
+    /// thing into memory, closes it, and returns an in-memory file instead.
+    /// Seeking and reading small files that way costs no system calls. Larger
+    /// files are returned as `open` would return them.
+    pub fn open_buffered(&self, path: &Path, max_bytes: usize)
+                         -> io::Result<Box<dyn DataFile>> {
+        let mut file = self.open(path)?;
+        if DataFile::stream_len(file.as_mut())? > max_bytes as u64 {
+            return Ok(file)
+        }
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        Ok(Box::new(Cursor::new(buf)))
+    }
+    /// Opens a file, checking its SHA-256 hash against `expected` as it's
+    /// read. Reading to the end of a file whose hash doesn't match gives an
+    /// `InvalidData` error instead of the end of the file. Seeking is allowed;
+    /// seeking past data that hasn't been read yet reads it anyway, to keep
+    /// the hash going.
+    ///
+    /// Nothing is verified until the end of the file is reached. Don't act on
+    /// the data until then!
+    #[cfg(feature = "verify")]
+    pub fn open_verified(&self, path: &Path, expected: &[u8; 32])
+                         -> io::Result<Box<dyn DataFile>> {
+        Ok(Box::new(verify::VerifiedFile::new(self.open(path)?, *expected)))
+    }
+    /// Packs everything under the given directory into an archive, in
+    /// memory, and opens that. Entries are named relative to `dir`, and come
+    /// in the order [`walk_depth`](#method.walk_depth) gives them, with an
+    /// entry for each directory as well as each file.
+    ///
+    /// Every file is read in full before this returns, so it isn't cheap.
+    #[cfg(feature = "tar")]
+    pub fn open_as_archive(&self, dir: &Path, format: ArchiveFormat)
+                           -> io::Result<Box<dyn DataFile>> {
+        if !dir.is_absolute() || !dir.is_directory() {
+            let err = format!("attempt to archive something other than an \
+                               absolute directory: {:?}", dir);
+            return Err(io::Error::new(ErrorKind::InvalidInput, err))
+        }
+        let mut out = vec![];
+        match format {
+            ArchiveFormat::Tar => {
+                for relative in self.walk_depth(dir, usize::MAX)? {
+                    let data = if relative.is_directory() { vec![] }
+                    else {
+                        let mut path = dir.to_owned();
+                        path.join(&relative);
+                        self.read_as_bytes(&path)?
+                    };
+                    archive::write_tar_entry(&mut out, relative.as_str(),
+                                             &data);
+                }
+                archive::finish_tar(&mut out);
+            },
+        }
+        Ok(Box::new(Cursor::new(out)))
+    }
+    /// Lists the given directory, merging the listings of every mount that
+    /// has it. Directories that mount points are inside of are listed too,
+    /// even if no source has them.
+    ///
+    /// A directory that exists in at least one mount, or that a mount point
+    /// is inside of, is listed successfully, even if it's empty. Otherwise,
+    /// the result is `NotADirectory` if some mount has a file with the
+    /// directory's name, and `NotFound` if not. Any other error from any
+    /// mount is returned right away.
+    ///
+    /// Entries are in the order set by
+    /// [`set_listing_order`](#method.set_listing_order), codepoint order by
+    /// default.
     pub fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut buf = Vec::new();
+        self.ls_into(path, &mut buf)?;
+        Ok(buf)
+    }
+    /// As [`ls`](#method.ls), but only returns the subdirectories, leaving
+    /// out the files. Since a directory shadows a file with the same name,
+    /// nothing left out shares a name with anything returned.
+    pub fn ls_dirs(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut ret = self.ls(path)?;
+        ret.retain(|x| x.is_directory());
+        Ok(ret)
+    }
+    /// As [`ls`](#method.ls), but puts the listing into `buf` (clearing it
+    /// first) instead of a new `Vec`. Listing many directories with the same
+    /// `buf` saves reallocating the `Vec` each time. (Each entry is still its
+    /// own `PathBuf`, since that's what sources hand over.)
+    ///
+    /// If an error is returned, `buf` is left empty.
+    pub fn ls_into(&self, path: &Path, buf: &mut Vec<PathBuf>)
+                   -> io::Result<()> {
+        buf.clear();
         if !path.is_absolute() {
             let err = format!("attempt to list a non-absolute path: {:?}",
                               path);
@@ -103,8 +1274,20 @@ impl VFS {
                               path);
             return Err(io::Error::new(ErrorKind::Other, err))
         }
-        let this = self.inner.read().unwrap();
-        let mut result = vec![];
+        let this = self.inner.read().unwrap().snapshot();
+        let generation = match this.ls_cache.as_ref() {
+            Some(cache) => {
+                let cache = cache.lock().unwrap();
+                if let Some(listing) = cache.listings.get(path) {
+                    buf.extend(listing.iter().cloned());
+                    return Ok(())
+                }
+                Some(cache.generation)
+            },
+            None => None,
+        };
+        let mut listings = vec![];
+        let mut implied = vec![];
         let mut any_succeeded = false;
         let mut failed_with_not_dir = false;
         // Iterate through each mount...
@@ -114,7 +1297,9 @@ impl VFS {
                 None => (),
                 Some(suffix) => {
                     // ...then take the output of ls according to this mount...
-                    let mut res = match source.ls(suffix) {
+                    let suffix = &*this.normalization.apply(suffix);
+                    let mut res = match this.retry_policy
+                        .run(|| source.ls(suffix)) {
                         Ok(x) => x,
                         Err(x) if x.kind() == ErrorKind::NotFound => continue,
                         Err(x) if x.kind() == ErrorKind::NotADirectory => {
@@ -123,8 +1308,9 @@ impl VFS {
                         },
                         Err(x) => return Err(x)
                     };
-                    // ...and merge it into result.
-                    result.append(&mut res);
+                    // ...and set it aside to be merged.
+                    NormalizationMode::unapply(&mut res);
+                    listings.push(res);
                     any_succeeded = true;
                 }
             }
@@ -140,7 +1326,7 @@ impl VFS {
                             // that explicitly contains it.
                             let mut buf = x.to_owned();
                             buf.make_file_into_dir();
-                            result.push(buf);
+                            implied.push(buf);
                             any_succeeded = true;
                         },
                     }
@@ -148,7 +1334,7 @@ impl VFS {
             }
         }
         if !any_succeeded {
-            debug_assert!(result.len() == 0);
+            debug_assert!(listings.is_empty() && implied.is_empty());
             if failed_with_not_dir {
                 return Err(io::Error::from(ErrorKind::NotADirectory))
             }
@@ -156,56 +1342,414 @@ impl VFS {
                 return Err(io::Error::from(ErrorKind::NotFound))
             }
         }
-        // Sort and deduplicate. (In cases where "foo" and "foo/" both exist,
+        // Merge and deduplicate. (In cases where "foo" and "foo/" both exist,
         // remove "foo".)
-        result.sort_by(|a, b| {
-            if a.is_directory() && b.as_str() == &a.as_str()[..a.len()-1] {
-                Ordering::Less
+        listings.push(implied);
+        merge_listings(listings, buf);
+        if this.listing_order == ListingOrder::Natural {
+            buf.sort_by(|a, b| natural_cmp(a, b));
+        }
+        if let (Some(cache), Some(generation))
+            = (this.ls_cache.as_ref(), generation) {
+            let mut cache = cache.lock().unwrap();
+            if cache.generation == generation {
+                cache.listings.insert(path.to_owned(), buf.clone());
             }
-            else if b.is_directory() && a.as_str() == &b.as_str()[..b.len()-1]{
-                Ordering::Greater
+        }
+        Ok(())
+    }
+    /// Lists a directory, looking for it in each of the search prefixes (see
+    /// [`set_search_prefixes`](#method.set_search_prefixes)) if its path is
+    /// relative. The listings of every prefix that has the directory are
+    /// merged, the same way `ls` merges mounts, so the result is everything
+    /// [`open_searched`](#method.open_searched) could find in it. An absolute
+    /// path is listed as `ls` would.
+    ///
+    /// Returns `NotFound` if no prefix has the directory.
+    pub fn ls_searched(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        if path.is_absolute() { return self.ls(path) }
+        let prefixes = self.inner.read().unwrap().search_prefixes.clone();
+        let mut listings = vec![];
+        for prefix in prefixes.iter() {
+            let mut full = prefix.clone();
+            full.try_join(path)
+                .map_err(|x| io::Error::new(ErrorKind::InvalidInput, x))?;
+            match self.ls(&full) {
+                Ok(x) => listings.push(x),
+                Err(x) if x.kind() == ErrorKind::NotFound
+                    || x.kind() == ErrorKind::NotADirectory => continue,
+                Err(x) => return Err(x),
+            }
+        }
+        if listings.is_empty() {
+            return Err(io::Error::from(ErrorKind::NotFound))
+        }
+        let mut ret = vec![];
+        merge_listings(listings, &mut ret);
+        if self.inner.read().unwrap().listing_order == ListingOrder::Natural {
+            ret.sort_by(|a, b| natural_cmp(a, b));
+        }
+        Ok(ret)
+    }
+    /// Resolves `rel` relative to the directory `base`, like
+    /// [`PathBuf::try_join`](struct.PathBuf.html#method.try_join), except
+    /// that every `..` is checked against the VFS. Stepping out of a
+    /// directory with `..` requires that directory to actually be a
+    /// directory, so `a/file/../b` is an error if `a/file` is a file (or
+    /// doesn't exist at all), where a lexical join would quietly give `a/b`.
+    ///
+    /// `rel` is a `&str` rather than a `&Path`, since making a `Path` out of
+    /// it would resolve the `..`s lexically before we got a chance to check
+    /// them. It must be relative.
+    ///
+    /// Returns `NotADirectory` or `NotFound` if a checked directory isn't
+    /// one, `InvalidInput` if `rel` is absolute, invalid, or escapes the root.
+    pub fn resolve_relative(&self, base: &Path, rel: &str)
+                            -> io::Result<PathBuf> {
+        if !base.is_absolute() || !base.is_directory() {
+            let err = format!("resolving relative to something other than \
+                               an absolute directory: {:?}", base);
+            return Err(io::Error::other(err))
+        }
+        if rel.starts_with('/') {
+            return Err(io::Error::new(ErrorKind::InvalidInput,
+                                      PathJoinError::PathNotRelative))
+        }
+        let mut ret = base.as_str().to_string();
+        let mut ends_in_file = false;
+        let trimmed = rel.strip_suffix('/').unwrap_or(rel);
+        for component in trimmed.split('/') {
+            ends_in_file = false;
+            if component == "." || (component.is_empty() && rel.is_empty()) {
+                continue
+            }
+            else if component == ".." {
+                if ret == "/" {
+                    return Err(io::Error::new(ErrorKind::InvalidInput,
+                                              PathJoinError::EscapedRoot))
+                }
+                self.ls(Path::from_str_preverified(&ret))?;
+                ret.pop();
+                while !ret.ends_with('/') { ret.pop(); }
             }
             else {
-                a.cmp(b)
+                let name = Path::try_from_str(component)
+                    .map_err(|x| io::Error::new(ErrorKind::InvalidInput, x))?;
+                if name.is_empty() || name.components().count() != 1 {
+                    return Err(io::Error::from(ErrorKind::InvalidInput))
+                }
+                ret.push_str(name.as_str());
+                ret.push('/');
+                ends_in_file = true;
             }
-        });
-        result.dedup_by(|next, first| {
-            if first == next { return true }
-            if first.is_directory() && !next.is_directory() {
-                if &first.as_str()[..first.len()-1] == next.as_str() {
-                    return true
+        }
+        if ends_in_file && !rel.ends_with('/') { ret.pop(); }
+        Ok(Path::from_str_preverified(&ret).to_owned())
+    }
+    /// Lists everything under the given directory, descending into
+    /// subdirectories at most `max_depth` levels. `max_depth == 0` is the same
+    /// as `ls`. Each directory's listing follows the same union rules as `ls`.
+    ///
+    /// Returns: paths relative to `root`, in pre-order (each directory comes
+    /// just before its contents, and each listing is in `ls` order).
+    pub fn walk_depth(&self, root: &Path, max_depth: usize)
+                      -> io::Result<Vec<PathBuf>> {
+        let mut ret = vec![];
+        self.walk_into(root, &PathBuf::new(), max_depth, &mut ret)?;
+        Ok(ret)
+    }
+    /// Adds up the sizes of every file under the given directory, at any
+    /// depth. Where several mounts provide the same file, only the one that
+    /// `open` would pick is counted.
+    ///
+    /// This opens every file, so it isn't cheap.
+    pub fn total_size(&self, root: &Path) -> io::Result<u64> {
+        let mut total = 0;
+        for relative in self.walk_depth(root, usize::MAX)? {
+            if relative.is_directory() { continue }
+            let mut path = root.to_owned();
+            path.join(&relative);
+            total += DataFile::stream_len(self.open(&path)?.as_mut())?;
+        }
+        Ok(total)
+    }
+    fn walk_into(&self, root: &Path, relative: &Path, depth_left: usize,
+                 out: &mut Vec<PathBuf>) -> io::Result<()> {
+        let mut absolute = root.to_owned();
+        absolute.join(relative);
+        for entry in self.ls(&absolute)? {
+            let mut path = relative.to_owned();
+            path.join(&entry);
+            out.push(path.clone());
+            if depth_left > 0 && path.is_directory() {
+                self.walk_into(root, &path, depth_left - 1, out)?;
+            }
+        }
+        Ok(())
+    }
+    /// Works out what mounting `source` at `point` would change about the
+    /// files under `root`, without mounting it. Whether the new source's
+    /// files override existing ones depends on the priority mode; see
+    /// [`set_priority_mode`](#method.set_priority_mode).
+    ///
+    /// Only files are compared, not their contents: a file the new source
+    /// provides is "overridden" even if the new version is identical. This
+    /// walks every directory under `root`, in the VFS and in the new source,
+    /// so it isn't cheap.
+    pub fn diff_with_added_mount(&self, point: &Path, source: &dyn VFSSource,
+                                 root: &Path) -> io::Result<MountDiff> {
+        if !point.is_absolute() || !point.is_directory()
+            || !root.is_absolute() || !root.is_directory() {
+            let err = format!("diffing a mount at {:?} under {:?}: both must \
+                               be absolute directories", point, root);
+            return Err(io::Error::other(err))
+        }
+        let walked = self.walk_depth(root, usize::MAX);
+        let mut existing: Vec<PathBuf> = match walked {
+            Ok(x) => x.into_iter().filter(|x| !x.is_directory())
+                .map(|x| {
+                    let mut path = root.to_owned();
+                    path.join(&x);
+                    path
+                }).collect(),
+            Err(x) if x.kind() == ErrorKind::NotFound => vec![],
+            Err(x) => return Err(x),
+        };
+        // Where to start walking the new source, and where that is in the
+        // VFS
+        let mut incoming = vec![];
+        let start = if let Some(suffix) = root.with_prefix_absolute(point) {
+            Some((suffix, root))
+        } else if point.with_prefix_absolute(root).is_some() {
+            Some((Path::from_str_preverified("/"), point))
+        } else { None };
+        if let Some((start, at)) = start {
+            match walk_source(source, start, &at.to_owned(), &mut incoming) {
+                Err(x) if x.kind() == ErrorKind::NotFound => (),
+                x => x?,
+            }
+        }
+        existing.sort_by(|a, b| a.dir_aware_cmp(b));
+        incoming.sort_by(|a, b| a.dir_aware_cmp(b));
+        let overrides = self.inner.read().unwrap().shadow_mode
+            == ShadowMode::LastWins;
+        let mut ret = MountDiff::default();
+        for path in existing.iter() {
+            if overrides
+                && incoming.binary_search_by(|x| x.dir_aware_cmp(path)).is_ok()
+            {
+                ret.overridden.push(path.clone());
+            }
+            else {
+                ret.unaffected.push(path.clone());
+            }
+        }
+        for path in incoming.into_iter() {
+            if existing.binary_search_by(|x| x.dir_aware_cmp(&path)).is_err() {
+                ret.added.push(path);
+            }
+        }
+        Ok(ret)
+    }
+    /// Returns the combined capabilities of every mount that the given path
+    /// falls within. If nothing is mounted there, the result is empty.
+    pub fn capabilities_at(&self, path: &Path) -> Capabilities {
+        let this = self.inner.read().unwrap();
+        let mut ret = Capabilities::empty();
+        for (prefix, source) in this.mounts.iter() {
+            if path.with_prefix_absolute(prefix).is_some() {
+                ret |= source.capabilities();
+            }
+        }
+        ret
+    }
+    /// Returns how many mounts the given path falls within: the most that
+    /// `open` would have to try before finding the file, or giving up on it.
+    /// Meant for profiling. A path that can't be opened because it's relative
+    /// gives 0.
+    pub fn resolution_cost(&self, path: &Path) -> usize {
+        if !path.is_absolute() { return 0 }
+        let this = self.inner.read().unwrap();
+        this.mounts.iter()
+            .filter(|(prefix, _)| path.with_prefix_absolute(prefix).is_some())
+            .count()
+    }
+    /// Returns the real, host filesystem path of the given file, if the mount
+    /// that `open` would read it from has one; see
+    /// [`VFSSource::physical_path`](trait.VFSSource.html#method.physical_path).
+    /// Useful for handing a file to another program. Returns `None` if the
+    /// file doesn't exist, or if it comes from a source that doesn't keep its
+    /// files on disk (such as a `RomSource`).
+    pub fn physical_path(&self, path: &Path) -> Option<std::path::PathBuf> {
+        if !path.is_absolute() || path.is_directory() { return None }
+        let this = self.inner.read().unwrap();
+        for (prefix, source) in this.by_priority() {
+            if let Some(suffix) = path.with_prefix_absolute(prefix) {
+                let suffix = &*this.normalization.apply(suffix);
+                if let Some(ret) = source.physical_path(suffix) {
+                    return Some(ret)
+                }
+                match this.retry_policy.run(|| source.exists(suffix)) {
+                    Ok(false) => continue,
+                    // This mount wins (or can't say that it doesn't), and has
+                    // no physical path to give
+                    _ => return None,
                 }
             }
-            return false
-        });
-        Ok(result)
+        }
+        None
+    }
+    /// Returns the mount points of every mount that provides the given file,
+    /// from highest priority to lowest. The first one (if any) is the one
+    /// that `open` would read from. Useful for diagnosing which of several
+    /// mods is providing a file.
+    ///
+    /// Every relevant mount is asked to open the file. Any mount that fails
+    /// to open it, for any reason, is left out.
+    pub fn providers(&self, path: &Path) -> Vec<PathBuf> {
+        if !path.is_absolute() || path.is_directory() { return vec![] }
+        let this = self.inner.read().unwrap();
+        this.by_priority().filter(|(prefix, source)| {
+            match path.with_prefix_absolute(prefix) {
+                None => false,
+                Some(suffix) => {
+                    let suffix = &*this.normalization.apply(suffix);
+                    this.retry_policy.run(|| source.open(suffix)).is_ok()
+                },
+            }
+        }).map(|(prefix, _)| prefix.clone()).collect()
     }
     /// Attempts to atomically update the file with the given path.
     ///
-    /// NOTE: Only the *latest mount that contains the given path* will attempt
-    /// to update the file. If that source fails to update the file, the update
+    /// NOTE: Only the *highest priority mount that contains the given path*
+    /// (normally the latest, see
+    /// [`set_priority_mode`](#method.set_priority_mode)) will attempt to
+    /// update the file. If that source fails to update the file, the update
     /// will fail!
+    ///
+    /// Read-only mounts that don't have the file are passed over. If the
+    /// mount that would take the update doesn't have the file's parent
+    /// directory, the update fails with `NotFound`; lower mounts are not
+    /// tried. If a read-only mount has the file, the update fails with
+    /// `ReadOnlyFilesystem`, since `open` would still find the old one.
     pub fn update(&self, path: &Path, data: &[u8]) -> io::Result<()> {
-        if !path.is_absolute() {
-            let err = format!("attempt to open a non-absolute path: {:?}",
-                              path);
-            return Err(io::Error::new(ErrorKind::Other, err))
+        let this = self.inner.read().unwrap();
+        if let Some(op) = this.dry_run_update(path, data) {
+            this.log_dry_run([op?]);
+            return Ok(())
         }
-        if path.is_directory() {
-            return Err(io::Error::from(ErrorKind::IsADirectory))
+        let _locks = this.update_locks.lock([path]);
+        let ret = this.for_update(path, |_, source, suffix| {
+            source.update(suffix, data)
+        });
+        this.invalidate_ls_cache();
+        ret
+    }
+    /// Replaces everything in the given directory with the given files, as
+    /// atomically as the source allows: afterwards, the directory holds
+    /// exactly the given files (and whatever directories they're in), and
+    /// nothing else. The paths of the files are relative to the directory.
+    ///
+    /// Only the highest priority writable mount that contains the directory
+    /// is affected, as with `update`. Other mounts may still provide files
+    /// in the directory, but if a mount that's passed over has one of the
+    /// given files, the replacement fails with `ReadOnlyFilesystem`, as
+    /// `update` would. An `FsSource` does the whole replacement with
+    /// renames; other sources may fall back on something less atomic, or be
+    /// unable to do it at all (see
+    /// [`VFSSource::replace_dir`](trait.VFSSource.html#method.replace_dir)).
+    /// The directory can't be the mount point itself.
+    ///
+    /// Returns `InvalidInput` if `dir` isn't an absolute directory, or an
+    /// entry isn't a relative path to a file inside it.
+    pub fn replace_dir<I>(&self, dir: &Path, entries: I) -> io::Result<()>
+    where I: IntoIterator<Item=(PathBuf, Vec<u8>)> {
+        if !dir.is_absolute() || !dir.is_directory() {
+            let err = format!("attempt to replace something other than an \
+                               absolute directory: {:?}", dir);
+            return Err(io::Error::new(ErrorKind::InvalidInput, err))
+        }
+        let entries: Vec<(PathBuf, Vec<u8>)> = entries.into_iter().collect();
+        if let Some((bad, _)) = entries.iter().find(|(path, _)| {
+            !path.is_relative() || path.is_directory() || path.is_empty()
+                || path.would_escape(Path::from_str_preverified("/"))
+        }) {
+            let err = format!("replacement entry is not a relative path to \
+                               a file: {:?}", bad);
+            return Err(io::Error::new(ErrorKind::InvalidInput, err))
         }
         let this = self.inner.read().unwrap();
-        for (prefix, source) in this.mounts.iter().rev() {
-            match path.with_prefix_absolute(prefix) {
-                None => (),
-                Some(suffix) => match source.update(suffix, data) {
-                    Err(x) if x.kind() == ErrorKind::ReadOnlyFilesystem
-                        => continue,
-                    x => return x,
+        let _locks = if this.dry_run.is_some() { vec![] }
+        else { this.update_locks.lock_all() };
+        let paths: Vec<PathBuf> = entries.iter().map(|(path, _)| {
+            let mut ret = dir.to_owned();
+            ret.join(path);
+            ret
+        }).collect();
+        // Mounts that are passed over mustn't have any of the files, as with
+        // `update`. That includes ones mounted inside the directory.
+        let check_shadows = |prefix: &Path, source: &dyn VFSSource| {
+            paths.iter().try_for_each(|path| {
+                this.check_shadow(path, prefix, source)
+            })
+        };
+        let mut ret = Err(io::Error::from(ErrorKind::ReadOnlyFilesystem));
+        for (prefix, source) in this.by_priority() {
+            let suffix = match dir.with_prefix_absolute(prefix) {
+                Some(x) if source.capabilities()
+                    .contains(Capabilities::WRITE) => x,
+                _ => {
+                    check_shadows(prefix, source.as_ref())?;
+                    continue
                 },
+            };
+            if this.dry_run.is_some() {
+                this.log_dry_run([DryRunOp::ReplaceDir {
+                    mount_point: prefix.clone(), path: dir.to_owned(),
+                    entries,
+                }]);
+                return Ok(())
+            }
+            let suffix = this.normalization.apply(suffix);
+            ret = if this.normalization == NormalizationMode::Nfd {
+                source.replace_dir(&suffix, &entries)
+            } else {
+                let entries: Vec<(PathBuf, Vec<u8>)> = entries.iter()
+                    .map(|(path, data)| {
+                        (this.normalization.apply(path).into_owned(),
+                         data.clone())
+                    }).collect();
+                source.replace_dir(&suffix, &entries)
+            };
+            match ret.as_ref() {
+                Err(x) if x.kind() == ErrorKind::ReadOnlyFilesystem
+                    => check_shadows(prefix, source.as_ref())?,
+                _ => break,
             }
         }
-        Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+        this.invalidate_ls_cache();
+        ret
+    }
+    /// Starts a group of updates that should succeed or fail together. Stage
+    /// updates on the returned `Transaction`, then `commit` it.
+    pub fn transaction(&self) -> Transaction<'_> {
+        Transaction { vfs: self, updates: vec![] }
+    }
+    /// Flushes every mount's source (see
+    /// [`VFSSource::flush`](trait.VFSSource.html#method.flush)), so that
+    /// every write accepted so far has really happened. Call it before the
+    /// program exits if any source might buffer writes.
+    ///
+    /// Every mount is flushed, even if an earlier one fails. Returns the
+    /// first error, if any.
+    pub fn flush_all(&self) -> io::Result<()> {
+        let this = self.inner.read().unwrap().snapshot();
+        let mut ret = Ok(());
+        for (_, source) in this.mounts.iter() {
+            let res = source.flush();
+            if ret.is_ok() { ret = res }
+        }
+        ret
     }
     /// Convenience function that attempts to read the given file all at once.
     ///
@@ -218,6 +1762,15 @@ impl VFS {
 
         Ok(buf)
     }
+    /// Convenience function that reads part of the given file all at once.
+    /// See [`open_range`](#method.open_range).
+    pub fn read_range(&self, path: &Path, start: u64, len: u64)
+                      -> io::Result<Vec<u8>> {
+        let mut f = self.open_range(path, start, len)?;
+        let mut buf = vec![];
+        f.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
     /// Convenience function that attempts to read the given file all at once.
     ///
     /// Returns the result as a `String`, see also `read_as_bytes`.
@@ -229,4 +1782,912 @@ impl VFS {
 
         Ok(buf)
     }
+    /// As `read_as_string`, but if the file starts with a UTF-8 byte order
+    /// mark (`EF BB BF`, as some Windows editors like to write), it's left
+    /// out. Anything else, including a BOM that isn't at the very start, is
+    /// left alone.
+    ///
+    /// Returns `InvalidData` if the rest of the file isn't valid UTF-8.
+    pub fn read_as_string_no_bom(&self, path: &Path) -> io::Result<String> {
+        let mut buf = self.read_as_bytes(path)?;
+        if buf.starts_with(b"\xEF\xBB\xBF") {
+            buf.drain(..3);
+        }
+        String::from_utf8(buf)
+            .map_err(|x| io::Error::new(ErrorKind::InvalidData, x))
+    }
+    /// Reads up to `max_bytes` from the start of a file, as text, for a
+    /// quick look at it in a log or a debugger. Anything that isn't valid
+    /// UTF-8 (including a character cut off by the limit) becomes U+FFFD,
+    /// so this works on any file, text or not. Only the first `max_bytes`
+    /// are read.
+    pub fn head_lossy(&self, path: &Path, max_bytes: usize)
+                      -> io::Result<String> {
+        let mut buf = vec![];
+        self.open(path)?.take(max_bytes as u64).read_to_end(&mut buf)?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+    /// As `read_as_string`, but for a file in some other encoding, such as
+    /// Latin-1 or Shift-JIS text from an old tool. Returns `InvalidData` if
+    /// the file has anything the encoding can't decode. A byte order mark
+    /// isn't treated specially.
+    ///
+    /// Encodings come from `encoding_rs` (re-exported as
+    /// `psilo_vfs::encoding_rs`), e.g. `encoding_rs::SHIFT_JIS`. It follows
+    /// the WHATWG Encoding Standard, so what's usually meant by Latin-1 is
+    /// `encoding_rs::WINDOWS_1252`.
+    #[cfg(feature = "encoding")]
+    pub fn read_to_string_with_encoding(
+        &self, path: &Path, encoding: &'static encoding_rs::Encoding)
+        -> io::Result<String> {
+        let bytes = self.read_as_bytes(path)?;
+        match encoding
+            .decode_without_bom_handling_and_without_replacement(&bytes) {
+                Some(x) => Ok(x.into_owned()),
+                None => {
+                    let err = format!("file is not valid {}", encoding.name());
+                    Err(io::Error::new(ErrorKind::InvalidData, err))
+                },
+            }
+    }
+    /// As `read_to_string_with_encoding`, but anything that can't be decoded
+    /// becomes U+FFFD instead of an error.
+    #[cfg(feature = "encoding")]
+    pub fn read_to_string_with_encoding_lossy(
+        &self, path: &Path, encoding: &'static encoding_rs::Encoding)
+        -> io::Result<String> {
+        let bytes = self.read_as_bytes(path)?;
+        Ok(encoding.decode_without_bom_handling(&bytes).0.into_owned())
+    }
+}
+
+/// Returns an error if a source can't be mounted at the given point.
+fn check_mount_point(point: &Path) -> io::Result<()> {
+    if !point.is_absolute() {
+        let err = format!("attempt to mount at a non-absolute path: {:?}",
+                          point);
+        return Err(io::Error::new(ErrorKind::Other, err))
+    }
+    if !point.is_directory() {
+        return Err(io::Error::from(ErrorKind::NotADirectory))
+    }
+    Ok(())
+}
+
+/// A group of updates that should succeed or fail together. See
+/// [`VFS::transaction`](struct.VFS.html#method.transaction).
+///
+/// Nothing touches any source until `commit`. Dropping a `Transaction` without
+/// committing it abandons it.
+pub struct Transaction<'a> {
+    vfs: &'a VFS,
+    updates: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl Transaction<'_> {
+    /// Stages an update of the file with the given path. Staging a second
+    /// update of the same path replaces the first.
+    pub fn update(&mut self, path: &Path, data: &[u8]) {
+        match self.updates.iter_mut().find(|(x, _)| **x == *path) {
+            Some((_, old)) => *old = data.to_vec(),
+            None => self.updates.push((path.to_owned(), data.to_vec())),
+        }
+    }
+    /// Commits every staged update, each going where `VFS::update` would have
+    /// sent it.
+    ///
+    /// First, every update is prepared (see `VFSSource::prepare_update`). If
+    /// any preparation fails, all of them are abandoned and no file changes.
+    /// Only then is each update committed. A failure at this last step leaves
+    /// the earlier updates in place and abandons the later ones; this is as
+    /// close to atomic as we can get across sources and filesystems.
+    pub fn commit(self) -> io::Result<()> {
+        let this = self.vfs.inner.read().unwrap();
+        if this.dry_run.is_some() {
+            let ops = self.updates.iter()
+                .filter_map(|(path, data)| this.dry_run_update(path, data))
+                .collect::<io::Result<Vec<_>>>()?;
+            this.log_dry_run(ops);
+            return Ok(())
+        }
+        let _locks = this.update_locks.lock(self.updates.iter()
+                                            .map(|(path, _)| path.as_ref()));
+        let mut prepared = Vec::with_capacity(self.updates.len());
+        for (path, data) in self.updates.iter() {
+            prepared.push(this.for_update(path, |_, source, suffix| {
+                source.prepare_update(suffix, data)
+            })?);
+        }
+        let ret = prepared.into_iter().try_for_each(|update| update.commit());
+        this.invalidate_ls_cache();
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::MapSource;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+    /// A `DataFile` that only has the default `stream_len`.
+    struct PlainFile(Cursor<&'static [u8]>);
+    impl Read for PlainFile {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+    impl Seek for PlainFile {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+    impl DataFile for PlainFile {}
+    /// A source that has nothing but a root directory with a fixed listing.
+    struct ListingSource(&'static [&'static str]);
+    impl VFSSource for ListingSource {
+        fn open(&self, _: &Path) -> io::Result<Box<dyn DataFile>> {
+            Err(io::Error::from(ErrorKind::NotFound))
+        }
+        fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+            if path != "/" { return Err(io::Error::from(ErrorKind::NotFound)) }
+            Ok(self.0.iter().map(|x| PathBuf::from_str(x)).collect())
+        }
+        fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+            Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+        }
+    }
+    #[test] fn merged_listing() {
+        const LISTINGS: &[&[&str]] = &[
+            &["bar/", "foo", "zap"],
+            &["baz", "foo/", "zap"],
+            // not sorted, the merge has to cope
+            &["quux", "bar", "foo", "aardvark/"],
+        ];
+        let vfs = VFS::new();
+        for listing in LISTINGS.iter() {
+            vfs.mount(PathBuf::from_str("/"), Box::new(ListingSource(listing)))
+                .unwrap();
+        }
+        // Do it the way `ls` did before the single-pass merge: concatenate
+        // everything, sort by codepoint (putting "foo/" just ahead of
+        // "foo"), then dedup.
+        let mut expected: Vec<PathBuf> = LISTINGS.iter()
+            .flat_map(|x| x.iter()).map(|x| PathBuf::from_str(x)).collect();
+        expected.sort_by(|a, b| {
+            if a.is_directory() && b.as_str() == &a.as_str()[..a.len()-1] {
+                Ordering::Less
+            }
+            else if b.is_directory() && a.as_str() == &b.as_str()[..b.len()-1]{
+                Ordering::Greater
+            }
+            else {
+                a.cmp(b)
+            }
+        });
+        expected.dedup_by(|next, first| {
+            first == next || (first.is_directory() && !next.is_directory()
+                              && &first.as_str()[..first.len()-1]
+                              == next.as_str())
+        });
+        let result = vfs.ls(&Path::from_str("/")).unwrap();
+        assert_eq!(result, expected);
+        assert_eq!(result.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+                   &["aardvark/", "bar/", "baz", "foo/", "quux", "zap"]);
+        // Where a name continues with something that sorts before "/", the
+        // orders part ways. The codepoint sort put "foo-bar/" between "foo"
+        // and "foo/", and so failed to drop "foo"; `dir_aware_cmp` keeps
+        // "foo/" and "foo" together, ahead of "foo-bar/".
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(ListingSource(&["foo", "foo-bar"]))).unwrap();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(ListingSource(&["foo/", "foo-bar/"]))).unwrap();
+        let result = vfs.ls(&Path::from_str("/")).unwrap();
+        assert_eq!(result.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+                   &["foo/", "foo-bar/"]);
+    }
+    #[test] fn ls_dirs() {
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(ListingSource(&["bar/", "foo", "quux"]))).unwrap();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(ListingSource(&["baz", "foo/", "zap/"]))).unwrap();
+        vfs.mount(PathBuf::from_str("/mods/extra/"),
+                  Box::new(ListingSource(&["readme"]))).unwrap();
+        let result = vfs.ls_dirs(&Path::from_str("/")).unwrap();
+        assert_eq!(result.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+                   &["bar/", "foo/", "mods/", "zap/"]);
+        assert!(vfs.ls_dirs(&Path::from_str("/mods/extra/")).unwrap()
+                .is_empty());
+        assert_eq!(vfs.ls_dirs(&Path::from_str("/nope/")).unwrap_err().kind(),
+                   ErrorKind::NotFound);
+    }
+    /// Lists like a `ListingSource`, counts how many times it's been asked
+    /// to, and accepts (and ignores) every write.
+    struct CountingSource(&'static AtomicUsize, &'static [&'static str]);
+    impl VFSSource for CountingSource {
+        fn open(&self, _: &Path) -> io::Result<Box<dyn DataFile>> {
+            Err(io::Error::from(ErrorKind::NotFound))
+        }
+        fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+            self.0.fetch_add(1, AtomicOrdering::SeqCst);
+            ListingSource(self.1).ls(path)
+        }
+        fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    /// Holds onto every write until it's flushed, at which point they're
+    /// moved to the second list. Flushing fails if the first write was to
+    /// `/fail`.
+    struct BufferingSource(Arc<Mutex<(Vec<PathBuf>, Vec<PathBuf>)>>);
+    impl VFSSource for BufferingSource {
+        fn open(&self, _: &Path) -> io::Result<Box<dyn DataFile>> {
+            Err(io::Error::from(ErrorKind::NotFound))
+        }
+        fn ls(&self, _: &Path) -> io::Result<Vec<PathBuf>> {
+            Ok(vec![])
+        }
+        fn update(&self, path: &Path, _: &[u8]) -> io::Result<()> {
+            self.0.lock().unwrap().0.push(path.to_owned());
+            Ok(())
+        }
+        fn flush(&self) -> io::Result<()> {
+            let (pending, done) = &mut *self.0.lock().unwrap();
+            if pending.first().map(|x| x.as_str()) == Some("/fail") {
+                return Err(io::Error::other("flush failed"))
+            }
+            done.append(pending);
+            Ok(())
+        }
+    }
+    /// Serves every file as a stream of its own path, and can't seek.
+    struct StreamSource;
+    impl VFSSource for StreamSource {
+        fn open(&self, _: &Path) -> io::Result<Box<dyn DataFile>> {
+            Err(io::Error::from(ErrorKind::Unsupported))
+        }
+        fn open_stream(&self, path: &Path)
+                       -> io::Result<Box<dyn DataStream>> {
+            if path == "/missing" {
+                return Err(io::Error::from(ErrorKind::NotFound))
+            }
+            Ok(Box::new(io::repeat(b'!').take(3)
+                        .chain(Cursor::new(path.as_str().to_string()))))
+        }
+        fn ls(&self, _: &Path) -> io::Result<Vec<PathBuf>> {
+            Ok(vec![])
+        }
+        fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+            Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+        }
+    }
+    #[test] fn open_stream() {
+        let read = |mut stream: Box<dyn DataStream>| {
+            let mut buf = String::new();
+            stream.read_to_string(&mut buf).unwrap();
+            buf
+        };
+        /// Has every file, and they're all seekable.
+        struct SeekableSource;
+        impl VFSSource for SeekableSource {
+            fn open(&self, _: &Path) -> io::Result<Box<dyn DataFile>> {
+                Ok(Box::new(Cursor::new(b"seekable")))
+            }
+            fn ls(&self, _: &Path) -> io::Result<Vec<PathBuf>> {
+                Ok(vec![])
+            }
+            fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+                Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+            }
+        }
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(SeekableSource)).unwrap();
+        vfs.mount(PathBuf::from_str("/"), Box::new(StreamSource)).unwrap();
+        assert_eq!(read(vfs.open_stream(&Path::from_str("/net/a")).unwrap()),
+                   "!!!/net/a");
+        assert_eq!(vfs.open(&Path::from_str("/net/a")).err().unwrap().kind(),
+                   ErrorKind::Unsupported);
+        // Falls through to a seekable source's `open`
+        assert_eq!(read(vfs.open_stream(&Path::from_str("/missing"))
+                        .unwrap()), "seekable");
+        assert_eq!(vfs.open_stream(&Path::from_str("/net/")).err().unwrap()
+                   .kind(), ErrorKind::IsADirectory);
+    }
+    #[cfg(feature = "encoding")]
+    #[test] fn read_to_string_with_encoding() {
+        use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(MapSource::new(&[
+            ("/sjis.txt", b"\x93\xfa\x96\x7b\x8c\xea\x82\xcc\x83\x65\x83\x4c\
+                            \x83\x58\x83\x67"),
+            ("/truncated.txt", b"\x93\xfa\x96"),
+            ("/latin1.txt", b"\x93Caf\xE9\x94"),
+        ]))).unwrap();
+        let fsp = Path::from_str_preverified;
+        assert_eq!(vfs.read_to_string_with_encoding(fsp("/sjis.txt"),
+                                                    SHIFT_JIS).unwrap(),
+                   "\u{65E5}\u{672C}\u{8A9E}\u{306E}\u{30C6}\u{30AD}\
+                    \u{30B9}\u{30C8}");
+        assert_eq!(vfs.read_to_string_with_encoding(fsp("/truncated.txt"),
+                                                    SHIFT_JIS)
+                   .unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(vfs.read_to_string_with_encoding_lossy(
+            fsp("/truncated.txt"), SHIFT_JIS).unwrap(), "\u{65E5}\u{FFFD}");
+        assert_eq!(vfs.read_to_string_with_encoding(fsp("/latin1.txt"),
+                                                    WINDOWS_1252).unwrap(),
+                   "\u{201C}Caf\u{E9}\u{201D}");
+    }
+    #[test] fn open_range_fallback() {
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(MapSource::new(&[
+            ("/file", b"0123456789")]))).unwrap();
+        let file = Path::from_str_preverified("/file");
+        assert_eq!(vfs.read_range(file, 2, 3).unwrap(), b"234");
+        assert_eq!(vfs.read_range(file, 7, 10).unwrap(), b"789");
+        assert_eq!(vfs.read_range(file, 20, 1).unwrap(), b"");
+        let mut part = vfs.open_range(file, 5, 5).unwrap();
+        assert_eq!(DataFile::stream_len(part.as_mut()).unwrap(), 5);
+        assert_eq!(vfs.read_range(Path::from_str_preverified("/nope"), 0, 1)
+                   .unwrap_err().kind(), ErrorKind::NotFound);
+    }
+    #[test] fn normalization_modes() {
+        // One name stored in normal form C, and one in normal form D
+        let source = MapSource::new(&[("/nai\u{308}ve", b"nfd")]);
+        source.0.lock().unwrap().insert(
+            Path::from_str_preverified("/caf\u{E9}").to_owned(),
+            b"nfc".to_vec());
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(source)).unwrap();
+        const EXPECTATIONS: &[(NormalizationMode, &str, bool)] = &[
+            (NormalizationMode::Nfd, "/cafe\u{301}", false),
+            (NormalizationMode::Nfd, "/nai\u{308}ve", true),
+            (NormalizationMode::Nfc, "/cafe\u{301}", true),
+            (NormalizationMode::Nfc, "/nai\u{308}ve", false),
+        ];
+        for &(mode, path, found) in EXPECTATIONS.iter() {
+            vfs.set_normalization(mode);
+            assert_eq!(vfs.open(Path::from_str_preverified(path)).is_ok(),
+                       found, "{:?} in {:?} mode", path, mode);
+            // Either way, both are listed in normal form D
+            assert_eq!(vfs.ls(Path::from_str_preverified("/")).unwrap(),
+                       [PathBuf::from_str("cafe\u{301}"),
+                        PathBuf::from_str("nai\u{308}ve")]);
+        }
+        // Written in normal form C, so invisible in normal form D
+        let resume = Path::from_str_preverified("/re\u{301}sume\u{301}");
+        vfs.update(resume, b"written").unwrap();
+        assert_eq!(vfs.read_as_bytes(resume).unwrap(), b"written");
+        vfs.set_normalization(NormalizationMode::Nfd);
+        assert_eq!(vfs.read_as_bytes(resume).unwrap_err().kind(),
+                   ErrorKind::NotFound);
+    }
+    /// A `MapSource` that can't be written to.
+    struct ReadOnlyMapSource(MapSource);
+    impl VFSSource for ReadOnlyMapSource {
+        fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+            self.0.open(path)
+        }
+        fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+            self.0.ls(path)
+        }
+        fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+            Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+        }
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::READ | Capabilities::LIST
+        }
+    }
+    /// A `MapSource` that can't list anything.
+    struct UnlistableMapSource(MapSource);
+    impl VFSSource for UnlistableMapSource {
+        fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+            self.0.open(path)
+        }
+        fn ls(&self, _: &Path) -> io::Result<Vec<PathBuf>> {
+            Err(io::Error::from(ErrorKind::Unsupported))
+        }
+        fn update(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+            self.0.update(path, data)
+        }
+    }
+    #[test] fn write_without_listing() {
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(UnlistableMapSource(
+            MapSource::new(&[])))).unwrap();
+        let path = Path::from_str_preverified("/saves/1.sav");
+        vfs.update(path, b"save").unwrap();
+        assert_eq!(vfs.read_as_bytes(path).unwrap(), b"save");
+        let mut transaction = vfs.transaction();
+        transaction.update(path, b"new save");
+        transaction.commit().unwrap();
+        assert_eq!(vfs.read_as_bytes(path).unwrap(), b"new save");
+    }
+    #[test] fn exists() {
+        let source = MapSource::new(&[("/a/file", b"data")]);
+        for (path, expected) in [("/a/file", true), ("/a", true),
+                                 ("/a/nope", false), ("/nope/file", false)] {
+            assert_eq!(source.exists(Path::from_str_preverified(path))
+                       .unwrap(), expected, "{:?}", path);
+        }
+    }
+    #[test] fn physical_path_without_opening() {
+        let vfs = VFS::new();
+        let source = StatsSource::new(Box::new(MapSource::new(&[
+            ("/a/file", b"data")])));
+        let stats = source.stats();
+        vfs.mount(PathBuf::from_str("/"), Box::new(source)).unwrap();
+        for path in ["/a/file", "/a/missing", "/missing/file"] {
+            assert_eq!(vfs.physical_path(Path::from_str_preverified(path)),
+                       None);
+        }
+        assert!(stats.snapshot().values().all(|x| x.opens == 0));
+    }
+    #[test] fn update_missing_parent() {
+        let vfs = VFS::new();
+        let lower = MapSource::new(&[("/saves/1.sav", b"old save")]);
+        let lower_files = lower.0.clone();
+        vfs.mount(PathBuf::from_str("/"), Box::new(lower)).unwrap();
+        vfs.mount(PathBuf::from_str("/"), Box::new(MapSource::new(&[
+            ("/config.ini", b"config")]))).unwrap();
+        // The top mount would get the update, and has no "/saves/", so the
+        // update fails rather than landing in the lower one
+        let path = Path::from_str_preverified("/saves/2.sav");
+        let err = vfs.update(path, b"save").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(err.to_string().contains("\"/saves/\""),
+                "unexpected message: {}", err);
+        let mut transaction = vfs.transaction();
+        transaction.update(path, b"save");
+        assert_eq!(transaction.commit().unwrap_err().kind(),
+                   ErrorKind::NotFound);
+        assert_eq!(lower_files.lock().unwrap().len(), 1);
+        assert_eq!(vfs.read_as_bytes(path).unwrap_err().kind(),
+                   ErrorKind::NotFound);
+    }
+    #[test] fn read_after_write() {
+        let vfs = VFS::new();
+        vfs.enable_ls_cache();
+        vfs.mount(PathBuf::from_str("/"), Box::new(MapSource::new(&[
+            ("/config.ini", b"user config"),
+            ("/saves/1.sav", b"old save"),
+        ]))).unwrap();
+        vfs.mount(PathBuf::from_str("/"), Box::new(ReadOnlyMapSource(
+            MapSource::new(&[("/config.ini", b"shipped config"),
+                         ("/maps/start.map", b"map")])))).unwrap();
+        vfs.mount(PathBuf::from_str("/mods/"),
+                  Box::new(MapSource::new(&[("/mod.ini", b"mod")]))).unwrap();
+        let saves = Path::from_str_preverified("/saves/");
+        assert_eq!(vfs.ls(saves).unwrap().len(), 1);
+        for (path, data) in [("/saves/1.sav", &b"new save"[..]),
+                             ("/saves/2.sav", b"second save"),
+                             ("/mods/mod.ini", b"new mod")] {
+            let path = Path::from_str_preverified(path);
+            vfs.update(path, data).unwrap();
+            assert_eq!(vfs.read_as_bytes(path).unwrap(), data);
+        }
+        assert_eq!(vfs.ls(saves).unwrap(),
+                   &[PathBuf::from_str("1.sav"), PathBuf::from_str("2.sav")]);
+        let mut transaction = vfs.transaction();
+        transaction.update(Path::from_str_preverified("/saves/3.sav"), b"3");
+        transaction.commit().unwrap();
+        assert_eq!(vfs.read_as_bytes(Path::from_str_preverified(
+            "/saves/3.sav")).unwrap(), b"3");
+        assert_eq!(vfs.ls(saves).unwrap().len(), 3);
+        // The read-only mount's copy would hide the user's
+        let config = Path::from_str_preverified("/config.ini");
+        assert_eq!(vfs.update(config, b"new config").unwrap_err().kind(),
+                   ErrorKind::ReadOnlyFilesystem);
+        assert_eq!(vfs.read_as_string(config).unwrap(), "shipped config");
+        assert_eq!(vfs.open_all(config).unwrap().len(), 2);
+        let mut transaction = vfs.transaction();
+        transaction.update(config, b"new config");
+        assert_eq!(transaction.commit().unwrap_err().kind(),
+                   ErrorKind::ReadOnlyFilesystem);
+        // Likewise for `replace_dir`
+        let entry = |path: &str, data: &[u8]| (PathBuf::from_str(path),
+                                                data.to_vec());
+        let map = Path::from_str_preverified("/maps/start.map");
+        let err = vfs.replace_dir(Path::from_str_preverified("/maps/"), [
+            entry("start.map", b"new map")]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ReadOnlyFilesystem);
+        assert_eq!(vfs.read_as_string(map).unwrap(), "map");
+        vfs.replace_dir(saves, [entry("1.sav", b"1"), entry("2.sav", b"2"),
+                                entry("3.sav", b"3")]).unwrap();
+        assert_eq!(vfs.read_as_bytes(Path::from_str_preverified(
+            "/saves/2.sav")).unwrap(), b"2");
+    }
+    /// A read-only source that can't say what it has.
+    struct OpaqueSource;
+    impl VFSSource for OpaqueSource {
+        fn open(&self, _: &Path) -> io::Result<Box<dyn DataFile>> {
+            Err(io::Error::from(ErrorKind::PermissionDenied))
+        }
+        fn ls(&self, _: &Path) -> io::Result<Vec<PathBuf>> {
+            Err(io::Error::from(ErrorKind::PermissionDenied))
+        }
+        fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+            Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+        }
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::READ | Capabilities::LIST
+        }
+    }
+    #[test] fn shadow_check_errors() {
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(MapSource::new(&[])))
+            .unwrap();
+        vfs.mount(PathBuf::from_str("/"), Box::new(OpaqueSource)).unwrap();
+        // Whether the read-only mount would shadow the file is unknown, and
+        // the update says why, rather than guessing
+        let path = Path::from_str_preverified("/file");
+        assert_eq!(vfs.update(path, b"data").unwrap_err().kind(),
+                   ErrorKind::PermissionDenied);
+        assert_eq!(vfs.replace_dir(Path::from_str_preverified("/dir/"), [
+            (PathBuf::from_str("file"), b"data".to_vec())]).unwrap_err()
+                   .kind(), ErrorKind::PermissionDenied);
+    }
+    #[test] fn replace_dir_fallback() {
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(MapSource::new(&[
+            ("/dir/a", b"old a"), ("/dir/sub/b", b"old b")]))).unwrap();
+        let entry = |path: &str, data: &[u8]| (PathBuf::from_str(path),
+                                                data.to_vec());
+        let dir = Path::from_str_preverified("/dir/");
+        // Would leave "sub/b" behind
+        assert_eq!(vfs.replace_dir(dir, [entry("a", b"new a")]).unwrap_err()
+                   .kind(), ErrorKind::Unsupported);
+        assert_eq!(vfs.read_as_bytes(&Path::from_str("/dir/a")).unwrap(),
+                   b"old a");
+        vfs.replace_dir(dir, [entry("sub/b", b"new b"), entry("c", b"c"),
+                              entry("a", b"new a")]).unwrap();
+        assert_eq!(vfs.read_as_bytes(&Path::from_str("/dir/a")).unwrap(),
+                   b"new a");
+        assert_eq!(vfs.read_as_bytes(&Path::from_str("/dir/sub/b")).unwrap(),
+                   b"new b");
+        assert_eq!(vfs.ls(dir).unwrap().len(), 3);
+    }
+    #[test] fn listing_order() {
+        let listing = |vfs: &VFS| vfs.ls(&Path::from_str("/")).unwrap()
+            .iter().map(|x| x.as_str().to_string()).collect::<Vec<_>>();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(ListingSource(&["file10", "file2", "map/",
+                                           "map1", "map"]))).unwrap();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(ListingSource(&["file02", "file2", "file9b",
+                                           "file9a"]))).unwrap();
+        vfs.enable_ls_cache();
+        assert_eq!(listing(&vfs), &["file02", "file10", "file2", "file9a",
+                                    "file9b", "map/", "map1"]);
+        vfs.set_listing_order(ListingOrder::Natural);
+        assert_eq!(listing(&vfs), &["file02", "file2", "file9a", "file9b",
+                                    "file10", "map/", "map1"]);
+        vfs.set_listing_order(ListingOrder::Codepoint);
+        assert_eq!(listing(&vfs)[1], "file10");
+    }
+    #[test] fn natural_cmp() {
+        const SORTED: &[&str] = &["", "0", "001", "01", "1", "2", "10",
+                                  "a/", "a", "a0b", "a1/", "a1", "a2b", "a10",
+                                  "a10b", "a99999999999999999999",
+                                  "a100000000000000000000", "b"];
+        for pair in SORTED.windows(2) {
+            let (a, b) = (Path::from_str_preverified(pair[0]),
+                          Path::from_str_preverified(pair[1]));
+            assert_eq!(super::natural_cmp(a, b), Ordering::Less,
+                       "{:?} {:?}", a, b);
+            assert_eq!(super::natural_cmp(b, a), Ordering::Greater,
+                       "{:?} {:?}", a, b);
+            assert_eq!(super::natural_cmp(a, a), Ordering::Equal);
+        }
+    }
+    #[test] fn flush_all() {
+        let first = Arc::new(Mutex::new((vec![], vec![])));
+        let second = Arc::new(Mutex::new((vec![], vec![])));
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/a/"),
+                  Box::new(BufferingSource(first.clone()))).unwrap();
+        vfs.mount(PathBuf::from_str("/b/"),
+                  Box::new(ListingSource(&[]))).unwrap();
+        vfs.mount(PathBuf::from_str("/c/"),
+                  Box::new(BufferingSource(second.clone()))).unwrap();
+        vfs.update(&Path::from_str("/a/save"), b"").unwrap();
+        vfs.update(&Path::from_str("/c/config"), b"").unwrap();
+        assert_eq!(first.lock().unwrap().1.len(), 0);
+        vfs.flush_all().unwrap();
+        assert_eq!(first.lock().unwrap().1[0].as_str(), "/save");
+        assert_eq!(second.lock().unwrap().1[0].as_str(), "/config");
+        assert!(first.lock().unwrap().0.is_empty());
+        // A failure doesn't stop later mounts from being flushed
+        vfs.update(&Path::from_str("/a/fail"), b"").unwrap();
+        vfs.update(&Path::from_str("/c/options"), b"").unwrap();
+        assert_eq!(vfs.flush_all().unwrap_err().to_string(), "flush failed");
+        assert_eq!(second.lock().unwrap().1.len(), 2);
+    }
+    #[test] fn ls_cache() {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = || COUNT.load(AtomicOrdering::SeqCst);
+        let listing = |vfs: &VFS| vfs.ls(&Path::from_str("/")).unwrap()
+            .iter().map(|x| x.as_str().to_string()).collect::<Vec<_>>();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(CountingSource(&COUNT, &["a", "b"]))).unwrap();
+        vfs.enable_ls_cache();
+        assert_eq!(listing(&vfs), &["a", "b"]);
+        assert_eq!(listing(&vfs), &["a", "b"]);
+        assert_eq!(count(), 1);
+        // Mounting invalidates
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(ListingSource(&["c"]))).unwrap();
+        assert_eq!(listing(&vfs), &["a", "b", "c"]);
+        assert_eq!(count(), 2);
+        assert_eq!(listing(&vfs), &["a", "b", "c"]);
+        assert_eq!(count(), 2);
+        // So does writing (which lists the parent, once)
+        vfs.update(&Path::from_str("/d"), b"").unwrap();
+        assert_eq!(count(), 3);
+        assert_eq!(listing(&vfs), &["a", "b", "c"]);
+        assert_eq!(count(), 4);
+        // Errors aren't cached
+        assert!(vfs.ls(&Path::from_str("/nope/")).is_err());
+        assert!(vfs.ls(&Path::from_str("/nope/")).is_err());
+        assert_eq!(count(), 6);
+        vfs.invalidate_ls_cache();
+        assert_eq!(listing(&vfs), &["a", "b", "c"]);
+        assert_eq!(count(), 7);
+        vfs.disable_ls_cache();
+        assert_eq!(listing(&vfs), &["a", "b", "c"]);
+        assert_eq!(count(), 8);
+    }
+    /// Fails to open or update with `TimedOut` until it's been asked a given
+    /// number of times, then succeeds (or fails with `NotFound`, for paths
+    /// other than `/file`). Always lists successfully.
+    struct FlakySource {
+        calls: Arc<AtomicUsize>,
+        failures: usize,
+    }
+    impl FlakySource {
+        fn call(&self) -> io::Result<()> {
+            if self.calls.fetch_add(1, AtomicOrdering::SeqCst) < self.failures {
+                Err(io::Error::from(ErrorKind::TimedOut))
+            }
+            else { Ok(()) }
+        }
+    }
+    impl VFSSource for FlakySource {
+        fn open(&self, path: &Path) -> io::Result<Box<dyn DataFile>> {
+            self.call()?;
+            if path.as_str() != "/file" {
+                return Err(io::Error::from(ErrorKind::NotFound))
+            }
+            Ok(Box::new(Cursor::new(b"finally")))
+        }
+        fn ls(&self, _: &Path) -> io::Result<Vec<PathBuf>> {
+            Ok(vec![PathBuf::from_str("file")])
+        }
+        fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+            self.call()
+        }
+    }
+    #[test] fn retry() {
+        let flaky = |failures| FlakySource { calls: Arc::default(),
+                                             failures };
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(flaky(2))).unwrap();
+        assert_eq!(vfs.open(&Path::from_str("/file")).err().unwrap().kind(),
+                   ErrorKind::TimedOut);
+        let policy = RetryPolicy { max_attempts: 3,
+                                   backoff: Duration::from_millis(1),
+                                   ..RetryPolicy::default() };
+        for max_attempts in [2, 3] {
+            let vfs = VFS::new();
+            vfs.mount(PathBuf::from_str("/"), Box::new(flaky(2))).unwrap();
+            vfs.set_retry_policy(RetryPolicy { max_attempts,
+                                               ..policy.clone() });
+            let result = vfs.read_as_bytes(&Path::from_str("/file"));
+            if max_attempts == 3 { assert_eq!(result.unwrap(), b"finally") }
+            else {
+                assert_eq!(result.unwrap_err().kind(), ErrorKind::TimedOut)
+            }
+        }
+        let vfs = VFS::new();
+        let source = Box::new(flaky(0));
+        let calls = source.calls.clone();
+        vfs.mount(PathBuf::from_str("/"), source).unwrap();
+        vfs.set_retry_policy(policy.clone());
+        // Not retried
+        assert_eq!(vfs.open(&Path::from_str("/nope")).err().unwrap().kind(),
+                   ErrorKind::NotFound);
+        assert_eq!(calls.load(AtomicOrdering::SeqCst), 1);
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(flaky(2))).unwrap();
+        vfs.set_retry_policy(policy);
+        vfs.update(&Path::from_str("/file"), b"").unwrap();
+    }
+    #[test] fn ls_into_reuses_buffer() {
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"),
+                  Box::new(ListingSource(&["a", "b/", "c"]))).unwrap();
+        let mut buf = Vec::new();
+        vfs.ls_into(&Path::from_str("/"), &mut buf).unwrap();
+        assert_eq!(buf.len(), 3);
+        let capacity = buf.capacity();
+        let pointer = buf.as_ptr();
+        vfs.ls_into(&Path::from_str("/"), &mut buf).unwrap();
+        assert_eq!(buf.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+                   &["a", "b/", "c"]);
+        assert_eq!(buf.capacity(), capacity);
+        assert_eq!(buf.as_ptr(), pointer);
+        assert_eq!(vfs.ls_into(&Path::from_str("/b/"), &mut buf).unwrap_err()
+                   .kind(), ErrorKind::NotFound);
+        assert!(buf.is_empty());
+        assert_eq!(buf.capacity(), capacity);
+    }
+    #[test] fn deeply_nested_mount() {
+        const EXPECTATIONS: &[(&str, &[&str])] = &[
+            ("/", &["a/"]),
+            ("/a/", &["b/"]),
+            ("/a/b/", &["c/"]),
+            ("/a/b/c/", &["deep", "deeper/"]),
+        ];
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/a/b/c/"),
+                  Box::new(ListingSource(&["deep", "deeper/"]))).unwrap();
+        for (path, expected) in EXPECTATIONS.iter() {
+            let result = vfs.ls(Path::from_str_preverified(path))
+                .unwrap_or_else(|x| panic!("ls {:?} failed: {}", path, x));
+            assert_eq!(result.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+                       *expected, "ls {:?} gave wrong result", path);
+        }
+        assert_eq!(vfs.ls(Path::from_str_preverified("/b/")).unwrap_err()
+                   .kind(), ErrorKind::NotFound);
+        assert_eq!(vfs.ls(Path::from_str_preverified("/a/c/")).unwrap_err()
+                   .kind(), ErrorKind::NotFound);
+    }
+    /// A source that panics partway through a listing must not leave the
+    /// mounts locked.
+    #[test] fn abandoned_ls_releases_lock() {
+        struct PanickySource;
+        impl VFSSource for PanickySource {
+            fn open(&self, _: &Path) -> io::Result<Box<dyn DataFile>> {
+                Err(io::Error::from(ErrorKind::NotFound))
+            }
+            fn ls(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+                if path != "/" {
+                    return Err(io::Error::from(ErrorKind::NotFound))
+                }
+                panic!("source gave up mid-listing")
+            }
+            fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+                Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+            }
+        }
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(ListingSource(&["a"])))
+            .unwrap();
+        vfs.mount(PathBuf::from_str("/"), Box::new(PanickySource)).unwrap();
+        let result = std::panic::catch_unwind(|| {
+            vfs.ls(Path::from_str_preverified("/"))
+        });
+        assert!(result.is_err());
+        vfs.mount(PathBuf::from_str("/more/"), Box::new(ListingSource(&["b"])))
+            .unwrap();
+        assert_eq!(vfs.ls(Path::from_str_preverified("/more/")).unwrap(),
+                   &[PathBuf::from_str("b")]);
+    }
+    /// A source that's stuck partway through opening a file mustn't hold up
+    /// mounting, or opening files elsewhere.
+    #[test] fn slow_open_blocks_nothing() {
+        use std::sync::mpsc::{channel, Receiver, Sender};
+        struct SlowSource {
+            entered: Mutex<Sender<()>>,
+            release: Mutex<Receiver<()>>,
+        }
+        impl VFSSource for SlowSource {
+            fn open(&self, _: &Path) -> io::Result<Box<dyn DataFile>> {
+                self.entered.lock().unwrap().send(()).unwrap();
+                self.release.lock().unwrap().recv().unwrap();
+                Ok(Box::new(Cursor::new(b"eventually")))
+            }
+            fn ls(&self, _: &Path) -> io::Result<Vec<PathBuf>> {
+                Ok(vec![])
+            }
+            fn update(&self, _: &Path, _: &[u8]) -> io::Result<()> {
+                Err(io::Error::from(ErrorKind::ReadOnlyFilesystem))
+            }
+        }
+        let (entered_tx, entered_rx) = channel();
+        let (release_tx, release_rx) = channel();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/slow/"), Box::new(SlowSource {
+            entered: Mutex::new(entered_tx),
+            release: Mutex::new(release_rx),
+        })).unwrap();
+        let slow = {
+            let vfs = vfs.clone();
+            std::thread::spawn(move || {
+                vfs.read_as_bytes(&Path::from_str("/slow/file")).unwrap()
+            })
+        };
+        entered_rx.recv().unwrap();
+        vfs.mount(PathBuf::from_str("/fast/"),
+                  Box::new(FlakySource { calls: Arc::default(),
+                                         failures: 0 })).unwrap();
+        assert_eq!(vfs.read_as_bytes(&Path::from_str("/fast/file")).unwrap(),
+                   b"finally");
+        release_tx.send(()).unwrap();
+        assert_eq!(slow.join().unwrap(), b"eventually");
+    }
+    #[test] fn resolution_cost() {
+        let vfs = VFS::new();
+        for point in ["/", "/mods/", "/mods/extra/", "/music/", "/"] {
+            vfs.mount(PathBuf::from_str(point),
+                      Box::new(ListingSource(&[]))).unwrap();
+        }
+        const COSTS: &[(&str, usize)] = &[
+            ("/readme", 2),
+            ("/mods/", 3),
+            ("/mods/extra/map", 4),
+            ("/mods/extras", 3),
+            ("/music", 2),
+            ("/music/theme.ogg", 3),
+            ("mods/extra/map", 0),
+        ];
+        for &(path, expected) in COSTS.iter() {
+            assert_eq!(vfs.resolution_cost(&Path::from_str(path)), expected,
+                       "{}", path);
+        }
+    }
+    #[test] fn mount_all() {
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(ListingSource(&["base"])))
+            .unwrap();
+        let listing = |vfs: &VFS, path: &str| vfs.ls(&Path::from_str(path))
+            .map(|x| x.iter().map(|x| x.as_str().to_string())
+                 .collect::<Vec<_>>());
+        let batch = |points: &[&str]| points.iter().map(|x| {
+            (PathBuf::from_str(x),
+             Box::new(ListingSource(&["modded"])) as Box<dyn VFSSource>)
+        }).collect::<Vec<_>>();
+        for bad in ["mods/", "/mods/readme"] {
+            assert!(vfs.mount_all(batch(&["/", "/mods/", bad])).is_err());
+            assert_eq!(listing(&vfs, "/").unwrap(), &["base"]);
+            assert_eq!(listing(&vfs, "/mods/").unwrap_err().kind(),
+                       ErrorKind::NotFound);
+        }
+        vfs.mount_all(batch(&["/", "/mods/"])).unwrap();
+        assert_eq!(listing(&vfs, "/").unwrap(), &["base", "modded", "mods/"]);
+        assert_eq!(listing(&vfs, "/mods/").unwrap(), &["modded"]);
+    }
+    #[test] fn mount_through_clones() {
+        const LISTINGS: &[&[&str]] = &[
+            &["a1", "a2"], &["b1", "b2"], &["c1", "c2"], &["d1", "d2"],
+        ];
+        let vfs = VFS::new();
+        let threads: Vec<_> = LISTINGS.iter().map(|listing| {
+            let vfs = vfs.clone();
+            std::thread::spawn(move || {
+                vfs.mount(PathBuf::from_str("/"),
+                          Box::new(ListingSource(listing))).unwrap();
+            })
+        }).collect();
+        for thread in threads { thread.join().unwrap() }
+        let result = vfs.ls(&Path::from_str("/")).unwrap();
+        assert_eq!(result.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+                   &["a1", "a2", "b1", "b2", "c1", "c2", "d1", "d2"]);
+    }
+    #[test] fn stream_len_keeps_position() {
+        let mut files: Vec<Box<dyn DataFile>> = vec![
+            Box::new(Cursor::new(b"Lorem ipsum dolor sit amet" as &[u8])),
+            Box::new(PlainFile(Cursor::new(b"Lorem ipsum dolor sit amet"))),
+        ];
+        for file in files.iter_mut() {
+            let mut buf = [0u8; 6];
+            file.read_exact(&mut buf).unwrap();
+            assert_eq!(DataFile::stream_len(file.as_mut()).unwrap(), 26);
+            assert_eq!(file.stream_position().unwrap(), 6);
+            file.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"ipsum ");
+        }
+    }
 }
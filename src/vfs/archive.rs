@@ -0,0 +1,205 @@
+use std::io::Write;
+
+/// A kind of archive that
+/// [`VFS::open_as_archive`](struct.VFS.html#method.open_as_archive) can
+/// produce.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum ArchiveFormat {
+    /// A POSIX (ustar) tar file, uncompressed. Names that don't fit in the
+    /// ustar header, and files of 8GiB or more, get a pax extended header.
+    Tar,
+}
+
+const BLOCK: usize = 512;
+
+/// Writes one tar entry: a header, then the data, padded out to a whole
+/// block. `name` is relative, and ends with `/` for a directory.
+pub(crate) fn write_tar_entry(out: &mut Vec<u8>, name: &str, data: &[u8]) {
+    let is_dir = name.ends_with('/');
+    let size = data.len() as u64;
+    let split = split_ustar_name(name);
+    let mut pax = vec![];
+    if split.is_none() { pax_record(&mut pax, "path", name) }
+    if size > 0o77777777777 {
+        pax_record(&mut pax, "size", &size.to_string())
+    }
+    if !pax.is_empty() {
+        let pax_name = format!("PaxHeaders/{}", last_component(name));
+        let pax_name = truncate(&pax_name, 100);
+        write_tar_header(out, "", pax_name, b'x', pax.len() as u64);
+        write_padded(out, &pax);
+    }
+    let (prefix, short) = split.unwrap_or(("", truncate(name, 100)));
+    write_tar_header(out, prefix, short, if is_dir { b'5' } else { b'0' },
+                     size);
+    write_padded(out, data);
+}
+
+/// Ends a tar file, with two empty blocks.
+pub(crate) fn finish_tar(out: &mut Vec<u8>) {
+    out.resize(out.len() + BLOCK * 2, 0);
+}
+
+/// Splits a name into the ustar header's `prefix` and `name` fields, if it
+/// fits.
+fn split_ustar_name(name: &str) -> Option<(&str, &str)> {
+    if name.len() <= 100 { return Some(("", name)) }
+    // The prefix can't end in the middle of the final name, and a directory's
+    // own trailing `/` doesn't count as a place to split
+    let body = name.strip_suffix('/').unwrap_or(name);
+    body.match_indices('/').map(|(i, _)| i)
+        .find(|&i| i <= 155 && name.len() - i - 1 <= 100)
+        .map(|i| (&name[..i], &name[i+1..]))
+}
+
+fn last_component(name: &str) -> &str {
+    let body = name.strip_suffix('/').unwrap_or(name);
+    &body[body.rfind('/').map(|x| x + 1).unwrap_or(0)..]
+}
+
+/// Truncates to at most `len` bytes, on a character boundary.
+fn truncate(s: &str, mut len: usize) -> &str {
+    if s.len() <= len { return s }
+    while !s.is_char_boundary(len) { len -= 1 }
+    &s[..len]
+}
+
+/// Adds a pax record, which is prefixed with its own length, in decimal.
+fn pax_record(out: &mut Vec<u8>, key: &str, value: &str) {
+    // " key=value\n"
+    let rest = key.len() + value.len() + 3;
+    let mut len = rest + 1;
+    while len != rest + len.to_string().len() {
+        len = rest + len.to_string().len();
+    }
+    writeln!(out, "{} {}={}", len, key, value).unwrap();
+}
+
+fn write_tar_header(out: &mut Vec<u8>, prefix: &str, name: &str,
+                    typeflag: u8, size: u64) {
+    debug_assert!(prefix.len() <= 155 && name.len() <= 100);
+    let mut header = [0u8; BLOCK];
+    let mut field = |start: usize, len: usize, value: &[u8]| {
+        header[start .. start + value.len().min(len)]
+            .copy_from_slice(&value[..value.len().min(len)]);
+    };
+    let octal = |value: u64, len: usize| format!("{:0w$o}\0", value,
+                                                w = len - 1);
+    field(0, 100, name.as_bytes());
+    let mode = if typeflag == b'5' { b"0000755\0" } else { b"0000644\0" };
+    field(100, 8, mode);
+    field(108, 8, b"0000000\0"); // uid
+    field(116, 8, b"0000000\0"); // gid
+    // (a size too big for this field has a pax record instead)
+    field(124, 12, octal(size.min(0o77777777777), 12).as_bytes());
+    field(136, 12, octal(0, 12).as_bytes()); // mtime
+    field(156, 1, &[typeflag]);
+    field(257, 8, b"ustar\x0000");
+    field(345, 155, prefix.as_bytes());
+    // The checksum is figured with its own field full of spaces
+    header[148..156].copy_from_slice(b"        ");
+    let sum: u32 = header.iter().map(|&x| x as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+    out.extend_from_slice(&header);
+}
+
+fn write_padded(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(data);
+    let rem = data.len() % BLOCK;
+    if rem != 0 { out.resize(out.len() + BLOCK - rem, 0) }
+}
+
+#[cfg(all(test, feature = "rom"))]
+mod test {
+    use super::*;
+    use crate::*;
+    use std::io::{ErrorKind, Read};
+    /// Reads a tar file back, returning the name and data of every entry.
+    /// Only understands what `write_tar_entry` writes.
+    fn untar(tar: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let field = |block: &[u8], start: usize, len: usize| {
+            let field = &block[start .. start + len];
+            let end = field.iter().position(|&x| x == 0).unwrap_or(len);
+            String::from_utf8(field[..end].to_vec()).unwrap()
+        };
+        let mut ret = vec![];
+        let mut pos = 0;
+        let mut pax_path = None;
+        loop {
+            let block = &tar[pos .. pos + BLOCK];
+            pos += BLOCK;
+            if block.iter().all(|&x| x == 0) {
+                assert!(tar[pos .. pos + BLOCK].iter().all(|&x| x == 0));
+                assert_eq!(pos + BLOCK, tar.len());
+                break
+            }
+            let mut sum_block = block.to_vec();
+            sum_block[148..156].copy_from_slice(b"        ");
+            let sum: u32 = sum_block.iter().map(|&x| x as u32).sum();
+            let checksum = field(block, 148, 6);
+            assert_eq!(u32::from_str_radix(&checksum, 8).unwrap(), sum);
+            assert_eq!(&block[257..265], b"ustar\x0000");
+            let size = u64::from_str_radix(&field(block, 124, 11), 8)
+                .unwrap() as usize;
+            let data = tar[pos .. pos + size].to_vec();
+            pos += size.div_ceil(BLOCK) * BLOCK;
+            if block[156] == b'x' {
+                let records = String::from_utf8(data).unwrap();
+                let (len, record) = records.split_once(' ').unwrap();
+                assert_eq!(len.parse::<usize>().unwrap(), records.len());
+                pax_path = Some(record.strip_prefix("path=").unwrap()
+                                .strip_suffix('\n').unwrap().to_string());
+                continue
+            }
+            let prefix = field(block, 345, 155);
+            let name = pax_path.take().unwrap_or_else(|| {
+                if prefix.is_empty() { field(block, 0, 100) }
+                else { format!("{}/{}", prefix, field(block, 0, 100)) }
+            });
+            assert_eq!(block[156] == b'5', name.ends_with('/'));
+            ret.push((name, data));
+        }
+        ret
+    }
+    #[test] fn tar() {
+        let split_file: &str = format!("/game/maps/{}", "x".repeat(99)).leak();
+        let long_dir: &str = format!("/game/{}/", "d".repeat(120)).leak();
+        let long_file: &str = format!("/game/{}/{}", "d".repeat(120),
+                                "f".repeat(120)).leak();
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(crate::rom::Source::new(&[
+            (Path::from_str_preverified("/game/"), b""),
+            (Path::from_str_preverified("/game/maps/"), b""),
+            (Path::from_str_preverified("/game/maps/e1m1.map"),
+             b"first map"),
+            (Path::from_str_preverified(split_file), b"split"),
+            (Path::from_str_preverified("/game/readme"), &[b'!'; 1000]),
+            (Path::from_str_preverified(long_dir), b""),
+            (Path::from_str_preverified(long_file), b"long"),
+            (Path::from_str_preverified("/other"), b"not included"),
+        ]))).unwrap();
+        let mut archive = vfs.open_as_archive(
+            Path::from_str_preverified("/game/"), ArchiveFormat::Tar)
+            .unwrap();
+        let mut tar = vec![];
+        archive.read_to_end(&mut tar).unwrap();
+        assert_eq!(tar.len() % BLOCK, 0);
+        let expected = [
+            (&long_dir["/game/".len()..], &b""[..]),
+            (&long_file["/game/".len()..], b"long"),
+            ("maps/", b""),
+            ("maps/e1m1.map", b"first map"),
+            (&split_file["/game/".len()..], b"split"),
+            ("readme", &[b'!'; 1000]),
+        ];
+        assert_eq!(untar(&tar), expected.iter()
+                   .map(|&(name, data)| (name.to_string(), data.to_vec()))
+                   .collect::<Vec<_>>());
+        assert_eq!(vfs.open_as_archive(Path::from_str_preverified("/game"),
+                                       ArchiveFormat::Tar)
+                   .err().unwrap().kind(), ErrorKind::InvalidInput);
+        assert_eq!(vfs.open_as_archive(Path::from_str_preverified("/nope/"),
+                                       ArchiveFormat::Tar)
+                   .err().unwrap().kind(), ErrorKind::NotFound);
+    }
+}
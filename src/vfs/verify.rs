@@ -0,0 +1,130 @@
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use sha2::{Digest, Sha256};
+
+use super::*;
+
+/// Wraps a `DataFile`, hashing everything read from it, and checking the hash
+/// when the end of the file is reached.
+pub(crate) struct VerifiedFile {
+    inner: Box<dyn DataFile>,
+    hasher: Sha256,
+    expected: [u8; 32],
+    /// Current position in the file.
+    pos: u64,
+    /// Everything before this has gone into `hasher`. Never less than `pos`.
+    hashed: u64,
+    /// `None` until the end of the file has been reached.
+    verified: Option<bool>,
+}
+
+impl VerifiedFile {
+    pub fn new(inner: Box<dyn DataFile>, expected: [u8; 32]) -> VerifiedFile {
+        VerifiedFile { inner, hasher: Sha256::new(), expected, pos: 0,
+                       hashed: 0, verified: None }
+    }
+    /// Called at the end of the file.
+    fn check(&mut self) -> io::Result<()> {
+        let verified = match self.verified {
+            Some(x) => x,
+            None => {
+                let hash = self.hasher.clone().finalize();
+                let verified = hash.as_slice() == self.expected;
+                self.verified = Some(verified);
+                verified
+            },
+        };
+        if verified { Ok(()) }
+        else { Err(io::Error::new(ErrorKind::InvalidData,
+                                  "file does not match expected hash")) }
+    }
+}
+
+impl Read for VerifiedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let start = self.pos;
+        self.pos += n as u64;
+        // (`start` can only be past `hashed` after seeking past the end)
+        if n > 0 && self.pos > self.hashed {
+            let skip = (self.hashed - start) as usize;
+            self.hasher.update(&buf[skip..n]);
+            self.hashed = self.pos;
+        }
+        if n == 0 && !buf.is_empty() { self.check()?; }
+        Ok(n)
+    }
+}
+
+impl Seek for VerifiedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = self.inner.seek(pos)?;
+        if target > self.hashed {
+            // Catch up on everything being skipped over
+            self.inner.seek(SeekFrom::Start(self.hashed))?;
+            let mut buf = [0u8; 4096];
+            while self.hashed < target {
+                let wanted = (target - self.hashed).min(buf.len() as u64);
+                let n = self.inner.read(&mut buf[..wanted as usize])?;
+                if n == 0 { break }
+                self.hasher.update(&buf[..n]);
+                self.hashed += n as u64;
+            }
+            self.inner.seek(SeekFrom::Start(target))?;
+        }
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+impl DataFile for VerifiedFile {
+    fn stream_len(&mut self) -> io::Result<u64> {
+        DataFile::stream_len(self.inner.as_mut())
+    }
+}
+
+#[cfg(all(test, feature = "rom"))]
+mod test {
+    use super::*;
+    const TEXT: &[u8] = b"The quick brown fox jumps over the lazy dog";
+    fn vfs_with(text: &'static [u8]) -> VFS {
+        let vfs = VFS::new();
+        vfs.mount(PathBuf::from_str("/"), Box::new(crate::rom::Source::new(&[
+            (Path::from_str_preverified("/fox"), text),
+        ]))).unwrap();
+        vfs
+    }
+    #[test] fn verified() {
+        let expected: [u8; 32] = Sha256::digest(TEXT).into();
+        let vfs = vfs_with(TEXT);
+        let fox = Path::from_str_preverified("/fox");
+        let mut buf = vec![];
+        vfs.open_verified(fox, &expected).unwrap()
+            .read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, TEXT);
+        // skip around, re-read some of it
+        let mut file = vfs.open_verified(fox, &expected).unwrap();
+        file.seek(SeekFrom::Start(10)).unwrap();
+        let mut buf = [0u8; 5];
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"brown");
+        file.seek(SeekFrom::Start(4)).unwrap();
+        let mut buf = vec![];
+        file.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, &TEXT[4..]);
+        // reading at the end again is fine
+        assert_eq!(file.read(&mut [0u8; 1]).unwrap(), 0);
+    }
+    #[test] fn tampered() {
+        let expected: [u8; 32] = Sha256::digest(TEXT).into();
+        let vfs = vfs_with(b"The quick brown fox jumps over the lazy cat");
+        let mut file = vfs.open_verified(Path::from_str_preverified("/fox"),
+                                         &expected).unwrap();
+        let mut buf = [0u8; 40];
+        file.read_exact(&mut buf).unwrap();
+        let mut buf = vec![];
+        assert_eq!(file.read_to_end(&mut buf).unwrap_err().kind(),
+                   ErrorKind::InvalidData);
+        assert_eq!(file.read(&mut [0u8; 1]).unwrap_err().kind(),
+                   ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,170 @@
+//! Expands `p!` many times in one crate, as a large program might, and checks
+//! that every expansion agrees with `Path::from_str`. Keeps an eye on how
+//! long the macro takes, too: this file should build about as quickly as any
+//! other test.
+
+use psilo_vfs::{p, Path};
+
+macro_rules! check {
+    ($($path:literal),* $(,)?) => {
+        $(assert_eq!(p!($path), &*Path::from_str($path), "{:?}", $path);)*
+    }
+}
+
+#[test] fn many_paths() {
+    check![
+        "/maps/e1m0.map", "/maps/door1.wav", "/maps/résumé2", "/maps/3/",
+        "/maps/data4.bin", "/sounds/e1m5.map", "/sounds/door6.wav",
+        "/sounds/résumé7", "/sounds/8/", "/sounds/data9.bin",
+        "/café/e1m10.map", "/café/door11.wav", "/café/résumé12", "/café/13/",
+        "/café/data14.bin", "/x/../e1m15.map", "/x/../door16.wav",
+        "/x/../résumé17", "/x/../18/", "/x/../data19.bin", "/naïve/e1m20.map",
+        "/naïve/door21.wav", "/naïve/résumé22", "/naïve/23/",
+        "/naïve/data24.bin", "/e1m25.map", "/door26.wav", "/résumé27", "/28/",
+        "/data29.bin", "maps/e1m30.map", "maps/door31.wav", "maps/résumé32",
+        "maps/33/", "maps/data34.bin", "sounds/e1m35.map", "sounds/door36.wav",
+        "sounds/résumé37", "sounds/38/", "sounds/data39.bin", "café/e1m40.map",
+        "café/door41.wav", "café/résumé42", "café/43/", "café/data44.bin",
+        "x/../e1m45.map", "x/../door46.wav", "x/../résumé47", "x/../48/",
+        "x/../data49.bin", "naïve/e1m50.map", "naïve/door51.wav",
+        "naïve/résumé52", "naïve/53/", "naïve/data54.bin", "e1m55.map",
+        "door56.wav", "résumé57", "58/", "data59.bin", "../maps/e1m60.map",
+        "../maps/door61.wav", "../maps/résumé62", "../maps/63/",
+        "../maps/data64.bin", "../sounds/e1m65.map", "../sounds/door66.wav",
+        "../sounds/résumé67", "../sounds/68/", "../sounds/data69.bin",
+        "../café/e1m70.map", "../café/door71.wav", "../café/résumé72",
+        "../café/73/", "../café/data74.bin", "../x/../e1m75.map",
+        "../x/../door76.wav", "../x/../résumé77", "../x/../78/",
+        "../x/../data79.bin", "../naïve/e1m80.map", "../naïve/door81.wav",
+        "../naïve/résumé82", "../naïve/83/", "../naïve/data84.bin",
+        "../e1m85.map", "../door86.wav", "../résumé87", "../88/",
+        "../data89.bin", "/./maps/e1m90.map", "/./maps/door91.wav",
+        "/./maps/résumé92", "/./maps/93/", "/./maps/data94.bin",
+        "/./sounds/e1m95.map", "/./sounds/door96.wav", "/./sounds/résumé97",
+        "/./sounds/98/", "/./sounds/data99.bin", "/./café/e1m100.map",
+        "/./café/door101.wav", "/./café/résumé102", "/./café/103/",
+        "/./café/data104.bin", "/./x/../e1m105.map", "/./x/../door106.wav",
+        "/./x/../résumé107", "/./x/../108/", "/./x/../data109.bin",
+        "/./naïve/e1m110.map", "/./naïve/door111.wav", "/./naïve/résumé112",
+        "/./naïve/113/", "/./naïve/data114.bin", "/./e1m115.map",
+        "/./door116.wav", "/./résumé117", "/./118/", "/./data119.bin",
+        "./maps/e1m120.map", "./maps/door121.wav", "./maps/résumé122",
+        "./maps/123/", "./maps/data124.bin", "./sounds/e1m125.map",
+        "./sounds/door126.wav", "./sounds/résumé127", "./sounds/128/",
+        "./sounds/data129.bin", "./café/e1m130.map", "./café/door131.wav",
+        "./café/résumé132", "./café/133/", "./café/data134.bin",
+        "./x/../e1m135.map", "./x/../door136.wav", "./x/../résumé137",
+        "./x/../138/", "./x/../data139.bin", "./naïve/e1m140.map",
+        "./naïve/door141.wav", "./naïve/résumé142", "./naïve/143/",
+        "./naïve/data144.bin", "./e1m145.map", "./door146.wav", "./résumé147",
+        "./148/", "./data149.bin", "/maps/e1m150.map", "/maps/door151.wav",
+        "/maps/résumé152", "/maps/153/", "/maps/data154.bin",
+        "/sounds/e1m155.map", "/sounds/door156.wav", "/sounds/résumé157",
+        "/sounds/158/", "/sounds/data159.bin", "/café/e1m160.map",
+        "/café/door161.wav", "/café/résumé162", "/café/163/",
+        "/café/data164.bin", "/x/../e1m165.map", "/x/../door166.wav",
+        "/x/../résumé167", "/x/../168/", "/x/../data169.bin",
+        "/naïve/e1m170.map", "/naïve/door171.wav", "/naïve/résumé172",
+        "/naïve/173/", "/naïve/data174.bin", "/e1m175.map", "/door176.wav",
+        "/résumé177", "/178/", "/data179.bin", "maps/e1m180.map",
+        "maps/door181.wav", "maps/résumé182", "maps/183/", "maps/data184.bin",
+        "sounds/e1m185.map", "sounds/door186.wav", "sounds/résumé187",
+        "sounds/188/", "sounds/data189.bin", "café/e1m190.map",
+        "café/door191.wav", "café/résumé192", "café/193/", "café/data194.bin",
+        "x/../e1m195.map", "x/../door196.wav", "x/../résumé197", "x/../198/",
+        "x/../data199.bin", "naïve/e1m200.map", "naïve/door201.wav",
+        "naïve/résumé202", "naïve/203/", "naïve/data204.bin", "e1m205.map",
+        "door206.wav", "résumé207", "208/", "data209.bin",
+        "../maps/e1m210.map", "../maps/door211.wav", "../maps/résumé212",
+        "../maps/213/", "../maps/data214.bin", "../sounds/e1m215.map",
+        "../sounds/door216.wav", "../sounds/résumé217", "../sounds/218/",
+        "../sounds/data219.bin", "../café/e1m220.map", "../café/door221.wav",
+        "../café/résumé222", "../café/223/", "../café/data224.bin",
+        "../x/../e1m225.map", "../x/../door226.wav", "../x/../résumé227",
+        "../x/../228/", "../x/../data229.bin", "../naïve/e1m230.map",
+        "../naïve/door231.wav", "../naïve/résumé232", "../naïve/233/",
+        "../naïve/data234.bin", "../e1m235.map", "../door236.wav",
+        "../résumé237", "../238/", "../data239.bin", "/./maps/e1m240.map",
+        "/./maps/door241.wav", "/./maps/résumé242", "/./maps/243/",
+        "/./maps/data244.bin", "/./sounds/e1m245.map", "/./sounds/door246.wav",
+        "/./sounds/résumé247", "/./sounds/248/", "/./sounds/data249.bin",
+        "/./café/e1m250.map", "/./café/door251.wav", "/./café/résumé252",
+        "/./café/253/", "/./café/data254.bin", "/./x/../e1m255.map",
+        "/./x/../door256.wav", "/./x/../résumé257", "/./x/../258/",
+        "/./x/../data259.bin", "/./naïve/e1m260.map", "/./naïve/door261.wav",
+        "/./naïve/résumé262", "/./naïve/263/", "/./naïve/data264.bin",
+        "/./e1m265.map", "/./door266.wav", "/./résumé267", "/./268/",
+        "/./data269.bin", "./maps/e1m270.map", "./maps/door271.wav",
+        "./maps/résumé272", "./maps/273/", "./maps/data274.bin",
+        "./sounds/e1m275.map", "./sounds/door276.wav", "./sounds/résumé277",
+        "./sounds/278/", "./sounds/data279.bin", "./café/e1m280.map",
+        "./café/door281.wav", "./café/résumé282", "./café/283/",
+        "./café/data284.bin", "./x/../e1m285.map", "./x/../door286.wav",
+        "./x/../résumé287", "./x/../288/", "./x/../data289.bin",
+        "./naïve/e1m290.map", "./naïve/door291.wav", "./naïve/résumé292",
+        "./naïve/293/", "./naïve/data294.bin", "./e1m295.map", "./door296.wav",
+        "./résumé297", "./298/", "./data299.bin", "/maps/e1m300.map",
+        "/maps/door301.wav", "/maps/résumé302", "/maps/303/",
+        "/maps/data304.bin", "/sounds/e1m305.map", "/sounds/door306.wav",
+        "/sounds/résumé307", "/sounds/308/", "/sounds/data309.bin",
+        "/café/e1m310.map", "/café/door311.wav", "/café/résumé312",
+        "/café/313/", "/café/data314.bin", "/x/../e1m315.map",
+        "/x/../door316.wav", "/x/../résumé317", "/x/../318/",
+        "/x/../data319.bin", "/naïve/e1m320.map", "/naïve/door321.wav",
+        "/naïve/résumé322", "/naïve/323/", "/naïve/data324.bin", "/e1m325.map",
+        "/door326.wav", "/résumé327", "/328/", "/data329.bin",
+        "maps/e1m330.map", "maps/door331.wav", "maps/résumé332", "maps/333/",
+        "maps/data334.bin", "sounds/e1m335.map", "sounds/door336.wav",
+        "sounds/résumé337", "sounds/338/", "sounds/data339.bin",
+        "café/e1m340.map", "café/door341.wav", "café/résumé342", "café/343/",
+        "café/data344.bin", "x/../e1m345.map", "x/../door346.wav",
+        "x/../résumé347", "x/../348/", "x/../data349.bin", "naïve/e1m350.map",
+        "naïve/door351.wav", "naïve/résumé352", "naïve/353/",
+        "naïve/data354.bin", "e1m355.map", "door356.wav", "résumé357", "358/",
+        "data359.bin", "../maps/e1m360.map", "../maps/door361.wav",
+        "../maps/résumé362", "../maps/363/", "../maps/data364.bin",
+        "../sounds/e1m365.map", "../sounds/door366.wav", "../sounds/résumé367",
+        "../sounds/368/", "../sounds/data369.bin", "../café/e1m370.map",
+        "../café/door371.wav", "../café/résumé372", "../café/373/",
+        "../café/data374.bin", "../x/../e1m375.map", "../x/../door376.wav",
+        "../x/../résumé377", "../x/../378/", "../x/../data379.bin",
+        "../naïve/e1m380.map", "../naïve/door381.wav", "../naïve/résumé382",
+        "../naïve/383/", "../naïve/data384.bin", "../e1m385.map",
+        "../door386.wav", "../résumé387", "../388/", "../data389.bin",
+        "/./maps/e1m390.map", "/./maps/door391.wav", "/./maps/résumé392",
+        "/./maps/393/", "/./maps/data394.bin", "/./sounds/e1m395.map",
+        "/./sounds/door396.wav", "/./sounds/résumé397", "/./sounds/398/",
+        "/./sounds/data399.bin", "/./café/e1m400.map", "/./café/door401.wav",
+        "/./café/résumé402", "/./café/403/", "/./café/data404.bin",
+        "/./x/../e1m405.map", "/./x/../door406.wav", "/./x/../résumé407",
+        "/./x/../408/", "/./x/../data409.bin", "/./naïve/e1m410.map",
+        "/./naïve/door411.wav", "/./naïve/résumé412", "/./naïve/413/",
+        "/./naïve/data414.bin", "/./e1m415.map", "/./door416.wav",
+        "/./résumé417", "/./418/", "/./data419.bin", "./maps/e1m420.map",
+        "./maps/door421.wav", "./maps/résumé422", "./maps/423/",
+        "./maps/data424.bin", "./sounds/e1m425.map", "./sounds/door426.wav",
+        "./sounds/résumé427", "./sounds/428/", "./sounds/data429.bin",
+        "./café/e1m430.map", "./café/door431.wav", "./café/résumé432",
+        "./café/433/", "./café/data434.bin", "./x/../e1m435.map",
+        "./x/../door436.wav", "./x/../résumé437", "./x/../438/",
+        "./x/../data439.bin", "./naïve/e1m440.map", "./naïve/door441.wav",
+        "./naïve/résumé442", "./naïve/443/", "./naïve/data444.bin",
+        "./e1m445.map", "./door446.wav", "./résumé447", "./448/",
+        "./data449.bin", "/maps/e1m450.map", "/maps/door451.wav",
+        "/maps/résumé452", "/maps/453/", "/maps/data454.bin",
+        "/sounds/e1m455.map", "/sounds/door456.wav", "/sounds/résumé457",
+        "/sounds/458/", "/sounds/data459.bin", "/café/e1m460.map",
+        "/café/door461.wav", "/café/résumé462", "/café/463/",
+        "/café/data464.bin", "/x/../e1m465.map", "/x/../door466.wav",
+        "/x/../résumé467", "/x/../468/", "/x/../data469.bin",
+        "/naïve/e1m470.map", "/naïve/door471.wav", "/naïve/résumé472",
+        "/naïve/473/", "/naïve/data474.bin", "/e1m475.map", "/door476.wav",
+        "/résumé477", "/478/", "/data479.bin", "maps/e1m480.map",
+        "maps/door481.wav", "maps/résumé482", "maps/483/", "maps/data484.bin",
+        "sounds/e1m485.map", "sounds/door486.wav", "sounds/résumé487",
+        "sounds/488/", "sounds/data489.bin", "café/e1m490.map",
+        "café/door491.wav", "café/résumé492", "café/493/", "café/data494.bin",
+        "x/../e1m495.map", "x/../door496.wav", "x/../résumé497", "x/../498/",
+        "x/../data499.bin",
+    ];
+}